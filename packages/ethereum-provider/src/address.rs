@@ -0,0 +1,95 @@
+//! EIP-55 helpers for the addresses returned by wallets, which might be lowercase or checksummed
+
+use crate::ProviderError;
+use tiny_keccak::{Hasher, Keccak};
+
+fn hex_digits(address: &str) -> Option<&str> {
+    let hex = address.strip_prefix("0x").unwrap_or(address);
+    (hex.len() == 40 && hex.chars().all(|c| c.is_ascii_hexdigit())).then_some(hex)
+}
+
+/// Formats `address` (with or without `0x`, in any case) with its EIP-55 checksum
+pub fn to_checksum(address: &str) -> Result<String, ProviderError> {
+    let hex = hex_digits(address)
+        .ok_or_else(|| ProviderError::Unsupported(format!("invalid address: {}", address)))?
+        .to_ascii_lowercase();
+    let mut hash = [0u8; 32];
+    let mut hasher = Keccak::v256();
+    hasher.update(hex.as_bytes());
+    hasher.finalize(&mut hash);
+    let checksummed = hex
+        .chars()
+        .enumerate()
+        .map(|(i, c)| {
+            let nibble = (hash[i / 2] >> if i % 2 == 0 { 4 } else { 0 }) & 0xf;
+            if nibble >= 8 {
+                c.to_ascii_uppercase()
+            } else {
+                c
+            }
+        })
+        .collect::<String>();
+    Ok(format!("0x{}", checksummed))
+}
+
+/// Whether `address` is a valid address, mixed-case addresses must match their EIP-55 checksum
+/// (lowercase or uppercase ones carry no checksum)
+pub fn is_valid_address(address: &str) -> bool {
+    let hex = match hex_digits(address) {
+        Some(hex) => hex,
+        None => return false,
+    };
+    if hex.chars().all(|c| !c.is_ascii_lowercase()) || hex.chars().all(|c| !c.is_ascii_uppercase())
+    {
+        return true
+    }
+    to_checksum(hex).map_or(false, |checksummed| checksummed[2..] == *hex)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // from EIP-55
+    const VECTORS: [&str; 8] = [
+        "0x52908400098527886E0F7030069857D2E4169EE7",
+        "0x8617E340B3D01FA5F11F306F4090FD50E238070D",
+        "0xde709f2102306220921060314715629080e2fb77",
+        "0x27b1fdb04752bbc536007a920d24acb045561c26",
+        "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed",
+        "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359",
+        "0xdbF03B407c01E7cD3CBea99509d93f8DDDC8C6FB",
+        "0xD1220A0cf47c7B9Be7A2E6BA89F429762e7b9aDb",
+    ];
+
+    #[test]
+    fn it_checksums_addresses() {
+        for address in VECTORS {
+            let checksummed = to_checksum(&address.to_lowercase()).unwrap();
+            // all-caps and all-lowercase vectors are their own checksum
+            assert_eq!(checksummed.to_lowercase(), address.to_lowercase());
+            if address[2..].chars().any(|c| c.is_ascii_lowercase()) &&
+                address[2..].chars().any(|c| c.is_ascii_uppercase())
+            {
+                assert_eq!(checksummed, address);
+            }
+        }
+        assert_eq!(
+            to_checksum("5AAEB6053F3E94C9B9A09F33669435E7EF1BEAED").unwrap(),
+            "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed"
+        );
+        assert!(to_checksum("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeA").is_err());
+        assert!(to_checksum("0xzzAeb6053F3E94C9b9A09f33669435E7Ef1BeAed").is_err());
+    }
+
+    #[test]
+    fn it_validates_addresses() {
+        for address in VECTORS {
+            assert!(is_valid_address(address));
+        }
+        assert!(is_valid_address("0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed"));
+        assert!(!is_valid_address("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAeD"));
+        assert!(!is_valid_address("0x5aaeb6053f3e94c9b9a09f33669435e7ef1bea"));
+        assert!(!is_valid_address("hello"));
+    }
+}
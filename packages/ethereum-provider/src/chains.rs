@@ -0,0 +1,38 @@
+//! A classification of well-known chains, e.g. to flag testnets in the UI
+
+/// Mainnets of popular networks
+const MAINNETS: [u64; 14] =
+    [1, 10, 56, 100, 137, 250, 324, 1101, 8453, 42161, 42170, 43114, 59144, 534352];
+
+/// Testnets of popular networks, including deprecated ones still used by older tools
+const TESTNETS: [u64; 20] = [
+    5, 97, 300, 420, 1442, 2442, 4002, 17000, 43113, 59141, 80001, 80002, 84531, 84532, 421613,
+    421614, 534351, 11155111, 11155420, 10200,
+];
+
+/// Whether `chain_id` is a testnet, `None` if the chain is unknown
+pub fn is_testnet(chain_id: u64) -> Option<bool> {
+    if TESTNETS.contains(&chain_id) {
+        Some(true)
+    } else if MAINNETS.contains(&chain_id) {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_classifies_known_chains() {
+        assert_eq!(is_testnet(1), Some(false));
+        assert_eq!(is_testnet(8453), Some(false));
+        assert_eq!(is_testnet(5), Some(true));
+        assert_eq!(is_testnet(11155111), Some(true));
+        assert_eq!(is_testnet(84532), Some(true));
+        assert_eq!(is_testnet(7777), None);
+        assert!(TESTNETS.iter().all(|id| !MAINNETS.contains(id)));
+    }
+}
@@ -1,6 +1,8 @@
 #![doc = include_str!("../README.md")]
 #![cfg_attr(docsrs, feature(doc_cfg))]
 
+pub mod address;
+pub mod chains;
 pub mod provider;
 pub use provider::{Provider, ProviderError};
 
@@ -1,7 +1,15 @@
+use futures_channel::mpsc;
+use futures_core::{Future, Stream};
 use js_sys::{Function, Object};
+pub use primitive_types::U256;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::{fmt, vec::Vec};
+use std::{
+    fmt,
+    pin::Pin,
+    task::{Context, Poll},
+    vec::Vec,
+};
 use wasm_bindgen::{closure::Closure, prelude::*, JsValue};
 use web_sys::Window;
 
@@ -15,6 +23,9 @@ pub enum ProviderError {
     Unsupported(String),
     #[error("unknown chain: {0}")]
     UnknownChain(RPCError),
+    /// The wallet is still on another chain after being asked to switch
+    #[error("wallet is on chain {actual} instead of {expected}")]
+    ChainMismatch { expected: u64, actual: u64 },
 }
 
 impl From<JsValue> for ProviderError {
@@ -29,6 +40,28 @@ impl From<serde_wasm_bindgen::Error> for ProviderError {
     }
 }
 
+/// Nonstandard providers might throw synchronously or return a plain value instead of a Promise
+fn to_promise(
+    method: &str,
+    result: Result<JsValue, JsValue>,
+) -> Result<js_sys::Promise, ProviderError> {
+    let value = result.map_err(|e| {
+        ProviderError::Unsupported(format!("`{}` request threw synchronously: {:?}", method, e))
+    })?;
+    if let Some(promise) = value.dyn_ref::<js_sys::Promise>() {
+        return Ok(promise.clone())
+    }
+    let then = js_sys::Reflect::get(&value, &JsValue::from("then")).unwrap_or(JsValue::UNDEFINED);
+    if then.is_function() {
+        // a thenable, let the JS runtime adopt it
+        return Ok(js_sys::Promise::resolve(&value))
+    }
+    Err(ProviderError::Unsupported(format!(
+        "`{}` request returned a non-Promise value: {:?}",
+        method, value
+    )))
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct Provider {
     this: JsValue,
@@ -80,6 +113,47 @@ impl Provider {
             _is_meta_mask: is_meta_mask.and_then(|v| v.as_bool()),
         })
     }
+
+    /// The wallet behind this provider according to its (non-standard) flags
+    pub fn wallet_name(&self) -> Option<&'static str> {
+        if self._is_coinbase_wallet.unwrap_or(false) {
+            Some("Coinbase Wallet")
+        } else if self._is_meta_mask.unwrap_or(false) {
+            Some("MetaMask")
+        } else {
+            None
+        }
+    }
+
+    /// The active account as exposed by the (non-standard) `selectedAddress` property, read
+    /// without any RPC call, `None` when the wallet doesn't expose it or is disconnected
+    pub fn selected_address(&self) -> Option<String> {
+        js_sys::Reflect::get(&self.this, &JsValue::from("selectedAddress")).ok()?.as_string()
+    }
+
+    /// Detects several wallet extensions injecting `window.ethereum`, in which case the last one
+    /// to inject wins and requests might not reach the wallet the user expects
+    pub fn multiple_providers(&self) -> Option<MultipleProviders> {
+        if let Some(providers) = self._providers.as_ref().filter(|p| p.len() > 1) {
+            return Some(MultipleProviders {
+                wallets: providers.iter().map(|p| p.wallet_name().map(str::to_owned)).collect(),
+            })
+        }
+        // a single provider claiming to be several wallets is likely one overriding the other
+        if self._is_coinbase_wallet.unwrap_or(false) && self._is_meta_mask.unwrap_or(false) {
+            return Some(MultipleProviders {
+                wallets: vec![Some("Coinbase Wallet".to_owned()), Some("MetaMask".to_owned())],
+            })
+        }
+        None
+    }
+}
+
+/// Returned by `Provider::multiple_providers` when wallets conflict over `window.ethereum`
+#[derive(Debug, PartialEq, Clone)]
+pub struct MultipleProviders {
+    /// The name of each wallet found, `None` when it couldn't be identified
+    pub wallets: Vec<Option<String>>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -190,6 +264,236 @@ impl Provider {
 
 pub type Callback = Closure<dyn Fn(JsValue)>;
 
+/// A stream of `accountsChanged` events, the listener is removed when it is dropped
+pub struct AccountsStream {
+    provider: Provider,
+    callback: Callback,
+    receiver: mpsc::UnboundedReceiver<Vec<String>>,
+}
+
+impl Stream for AccountsStream {
+    type Item = Vec<String>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.get_mut().receiver).poll_next(cx)
+    }
+}
+
+impl Drop for AccountsStream {
+    fn drop(&mut self) {
+        let _ = self.provider.remove_accounts_changed_listener(&self.callback);
+    }
+}
+
+impl Provider {
+    /// Returns a stream yielding the new accounts every time they change (events which fail to
+    /// parse are skipped)
+    pub fn accounts_stream(&self) -> Result<AccountsStream, ProviderError> {
+        let (sender, receiver) = mpsc::unbounded();
+        let callback = self.on_accounts_changed(Box::new(move |accounts| {
+            if let Ok(accounts) = accounts {
+                let _ = sender.unbounded_send(accounts);
+            }
+        }))?;
+        Ok(AccountsStream { provider: self.clone(), callback, receiver })
+    }
+}
+
+/// The `type` of the `message` events carrying subscription results
+static SUBSCRIPTION_MESSAGE: &str = "eth_subscription";
+
+/// The subscription id and result carried by `message`, if it is a subscription result
+fn subscription_result(message: Message) -> Option<(String, Value)> {
+    let data = match message {
+        Message::Subscription(subscription) => subscription.data,
+        // `Generic` matches first as its data is any value
+        Message::Generic(message) if message.typ == SUBSCRIPTION_MESSAGE => {
+            serde_json::from_value::<SubscriptionData>(message.data).ok()?
+        }
+        Message::Generic(_) => return None,
+    };
+    Some((data.subscription, data.result))
+}
+
+/// A stream of the results of an `eth_subscribe` subscription, see `Provider::subscribe`. The
+/// listener is removed when it is dropped but the wallet keeps the subscription until
+/// `unsubscribe` is called
+pub struct SubscriptionStream {
+    provider: Provider,
+    id: String,
+    callback: Callback,
+    /// Every subscription result, as they can arrive before the id is known
+    receiver: mpsc::UnboundedReceiver<(String, Value)>,
+}
+
+impl SubscriptionStream {
+    /// The id returned by `eth_subscribe`
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Cancels the subscription, returns whether the wallet knew it
+    pub async fn unsubscribe(self) -> Result<bool, ProviderError> {
+        self.provider.request_unsubscribe(self.id.clone()).await
+    }
+}
+
+impl Stream for SubscriptionStream {
+    type Item = Value;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            match Pin::new(&mut this.receiver).poll_next(cx) {
+                Poll::Ready(Some((id, result))) if id == this.id => {
+                    return Poll::Ready(Some(result))
+                }
+                Poll::Ready(Some(_)) => {}
+                other => return other.map(|_| None),
+            }
+        }
+    }
+}
+
+impl Drop for SubscriptionStream {
+    fn drop(&mut self) {
+        let _ = self.provider.remove_message_listener(&self.callback);
+    }
+}
+
+impl Provider {
+    /// Starts an `eth_subscribe` subscription of `kind` (e.g. `newHeads` or `logs`, with the
+    /// filter as `params`) and returns a stream of its results. Not every wallet supports it
+    pub async fn subscribe(
+        &self,
+        kind: &str,
+        params: Option<Value>,
+    ) -> Result<SubscriptionStream, ProviderError> {
+        let (sender, receiver) = mpsc::unbounded();
+        // listening first so no result is missed
+        let callback = self.on_message(Box::new(move |message| {
+            if let Some(result) = message.ok().and_then(subscription_result) {
+                let _ = sender.unbounded_send(result);
+            }
+        }))?;
+        let mut stream =
+            SubscriptionStream { provider: self.clone(), id: String::new(), callback, receiver };
+        let mut args = vec![Value::String(kind.to_owned())];
+        args.extend(params);
+        let data = self
+            .request(REQUEST_SUBSCRIBE.to_owned(), Some(RequestMethodParams::Vec(args)))
+            .await?;
+        stream.id = parse_js(data)?;
+        Ok(stream)
+    }
+
+    /// Cancels the subscription `id`, returns whether the wallet knew it
+    pub async fn request_unsubscribe(&self, id: String) -> Result<bool, ProviderError> {
+        let data = self
+            .request(REQUEST_UNSUBSCRIBE.to_owned(), Some(RequestMethodParams::Vec(vec![id])))
+            .await?;
+        parse_js(data)
+    }
+}
+
+/// The kinds of EIP-1193 events, see `Provider::next_event`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    Message,
+    Connect,
+    Disconnect,
+    ChainChanged,
+    AccountsChanged,
+}
+
+impl EventKind {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Message => MESSAGE,
+            Self::Connect => CONNECT,
+            Self::Disconnect => DISCONNECT,
+            Self::ChainChanged => CHAIN_CHANGED,
+            Self::AccountsChanged => ACCOUNTS_CHANGED,
+        }
+    }
+}
+
+fn forward_event<T: 'static>(
+    sender: &mpsc::UnboundedSender<Event>,
+    wrap: fn(T) -> Event,
+) -> Box<dyn Fn(Result<T, ProviderError>)> {
+    let sender = sender.clone();
+    Box::new(move |data| {
+        if let Ok(data) = data {
+            let _ = sender.unbounded_send(wrap(data));
+        }
+    })
+}
+
+/// Resolves to the next event matching one of the requested kinds, the listeners are removed
+/// once it resolves or when it is dropped
+pub struct NextEvent {
+    provider: Provider,
+    listeners: Vec<(EventKind, Callback)>,
+    receiver: mpsc::UnboundedReceiver<Event>,
+}
+
+impl NextEvent {
+    fn remove_listeners(&mut self) {
+        for (kind, callback) in self.listeners.drain(..) {
+            let _ = self.provider.remove_listener(kind.name().to_owned(), &callback);
+        }
+    }
+}
+
+impl Future for NextEvent {
+    type Output = Event;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        match Pin::new(&mut this.receiver).poll_next(cx) {
+            Poll::Ready(Some(event)) => {
+                this.remove_listeners();
+                Poll::Ready(event)
+            }
+            // the senders live in our own listeners so the channel cannot be closed
+            _ => Poll::Pending,
+        }
+    }
+}
+
+impl Drop for NextEvent {
+    fn drop(&mut self) {
+        self.remove_listeners();
+    }
+}
+
+impl Provider {
+    /// Waits for the next event of one of the given kinds, e.g. until the user switches chain
+    /// (events which fail to parse are skipped)
+    pub fn next_event(&self, kinds: &[EventKind]) -> Result<NextEvent, ProviderError> {
+        let (sender, receiver) = mpsc::unbounded();
+        let mut next = NextEvent { provider: self.clone(), listeners: vec![], receiver };
+        for kind in kinds {
+            let callback = match kind {
+                EventKind::Message => self.on_message(forward_event(&sender, Event::Message))?,
+                EventKind::Connect => self.on_connect(forward_event(&sender, Event::Connect))?,
+                EventKind::Disconnect => {
+                    self.on_disconnect(forward_event(&sender, Event::Disconnect))?
+                }
+                EventKind::ChainChanged => {
+                    self.on_chain_changed(forward_event(&sender, Event::ChainChanged))?
+                }
+                EventKind::AccountsChanged => {
+                    self.on_accounts_changed(forward_event(&sender, Event::AccountsChanged))?
+                }
+            };
+            next.listeners.push((*kind, callback));
+        }
+        Ok(next)
+    }
+}
+
 static MESSAGE: &str = "message";
 static CONNECT: &str = "connect";
 static DISCONNECT: &str = "disconnect";
@@ -237,26 +541,66 @@ pub enum RequestMethodParams<T: Serialize> {
     Object(T),
 }
 
+fn raw_params(params: Value) -> Option<RequestMethodParams<Value>> {
+    match params {
+        Value::Null => None,
+        Value::Array(params) => Some(RequestMethodParams::Vec(params)),
+        params => Some(RequestMethodParams::Object(params)),
+    }
+}
+
 #[derive(Serialize)]
 pub struct RequestMethod<T: Serialize> {
     pub method: String,
     pub params: Option<RequestMethodParams<T>>,
 }
 
-#[derive(Deserialize, Debug, Clone, PartialEq)]
-// TODO: this is not working
-#[serde(untagged)]
-#[repr(i64)]
+/// EIP-1193 error codes, (de)serialized as the plain number wallets use
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(from = "i64", into = "i64")]
 pub enum ErrorCodes {
-    UserRejectedRequest = 4001,
-    Unauthorized = 4100,
-    UnsupportedMethod = 4200,
-    Disconnected = 4900,
-    ChainDisconnected = 4901,
+    UserRejectedRequest,
+    Unauthorized,
+    UnsupportedMethod,
+    Disconnected,
+    ChainDisconnected,
     Other(i64),
 }
 
-#[derive(Deserialize, Debug, Clone, PartialEq)]
+impl ErrorCodes {
+    /// The numeric EIP-1193 (or JSON-RPC) error code
+    pub fn code(&self) -> i64 {
+        match self {
+            Self::UserRejectedRequest => 4001,
+            Self::Unauthorized => 4100,
+            Self::UnsupportedMethod => 4200,
+            Self::Disconnected => 4900,
+            Self::ChainDisconnected => 4901,
+            Self::Other(code) => *code,
+        }
+    }
+}
+
+impl From<i64> for ErrorCodes {
+    fn from(code: i64) -> Self {
+        match code {
+            4001 => Self::UserRejectedRequest,
+            4100 => Self::Unauthorized,
+            4200 => Self::UnsupportedMethod,
+            4900 => Self::Disconnected,
+            4901 => Self::ChainDisconnected,
+            code => Self::Other(code),
+        }
+    }
+}
+
+impl From<ErrorCodes> for i64 {
+    fn from(code: ErrorCodes) -> Self {
+        code.code()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct RPCError {
     pub code: ErrorCodes,
     pub message: String,
@@ -269,12 +613,50 @@ impl fmt::Display for RPCError {
     }
 }
 
+/// A permission granted to the page (EIP-2255), e.g. `eth_accounts`
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Permission {
+    #[serde(rename = "parentCapability")]
+    pub parent_capability: String,
+    /// The origin the permission was granted to
+    pub invoker: Option<String>,
+    /// Restrictions of the permission (e.g. the accounts exposed), their format depends on it
+    #[serde(default)]
+    pub caveats: Vec<Value>,
+}
+
 #[derive(Serialize)]
 struct SwitchEthereumChainParams {
     #[serde(rename = "chainId")]
     chain_id: String,
 }
 
+/// The EIP-747 `wallet_watchAsset` parameters, only ERC-20 tokens are widely supported
+#[derive(Debug, Clone, Serialize)]
+pub struct WatchAssetParams {
+    #[serde(rename = "type")]
+    pub asset_type: String,
+    pub options: WatchAssetOptions,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WatchAssetOptions {
+    pub address: String,
+    pub symbol: String,
+    pub decimals: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+}
+
+impl WatchAssetParams {
+    pub fn erc20(address: String, symbol: String, decimals: u8, image: Option<String>) -> Self {
+        Self {
+            asset_type: "ERC20".to_owned(),
+            options: WatchAssetOptions { address, symbol, decimals, image },
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct NativeCurrency {
     pub name: String,
@@ -299,14 +681,62 @@ pub struct ChainData {
 }
 
 #[derive(Serialize)]
+#[serde(untagged)]
 enum TypedData<T: Serialize> {
     Address(String),
     Data(T),
+    Json(String),
 }
 
-#[derive(Serialize, Debug)]
+/// The version of `eth_signTypedData` to use, which also decides how the data is passed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TypedDataVersion {
+    /// `eth_signTypedData`, the data is an array of `{ type, name, value }` objects
+    V1,
+    /// `eth_signTypedData_v3`, the data is EIP-712 typed data passed as a JSON string
+    V3,
+    /// `eth_signTypedData_v4`, the data is EIP-712 typed data passed as a JSON string
+    #[default]
+    V4,
+}
+
+impl TypedDataVersion {
+    fn method(&self) -> &'static str {
+        match self {
+            Self::V1 => REQUEST_SIGN_TYPED_DATA,
+            Self::V3 => REQUEST_SIGN_TYPED_DATA_V3,
+            Self::V4 => REQUEST_SIGN_TYPED_DATA_V4,
+        }
+    }
+
+    /// Whether the wallet expects the data as a JSON string rather than an object
+    pub fn expects_json_string(&self) -> bool {
+        !matches!(self, Self::V1)
+    }
+}
+
+fn sign_typed_data_params<T: Serialize>(
+    address: String,
+    data: T,
+    version: TypedDataVersion,
+) -> Result<RequestMethodParams<TypedData<T>>, ProviderError> {
+    Ok(RequestMethodParams::Vec(match version {
+        TypedDataVersion::V1 => vec![TypedData::Data(data), TypedData::Address(address)],
+        TypedDataVersion::V3 | TypedDataVersion::V4 => vec![
+            TypedData::Address(address),
+            TypedData::Json(
+                serde_json::to_string(&data)
+                    .map_err(|e| ProviderError::Deserialize(e.to_string()))?,
+            ),
+        ],
+    }))
+}
+
+#[derive(Serialize, Debug, Clone)]
 pub struct Transaction {
-    pub from: String,
+    /// Required to sign or send, usually omitted for `eth_call`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
     pub to: String,
     pub gas: Option<u64>,
     #[serde(rename = "gasPrice")]
@@ -316,13 +746,93 @@ pub struct Transaction {
     pub nonce: Option<u64>,
 }
 
+/// The `eth_newFilter` parameters, unset fields match everything. Blocks are hex numbers or tags
+/// (e.g. `latest`)
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct FilterParams {
+    #[serde(rename = "fromBlock", skip_serializing_if = "Option::is_none")]
+    pub from_block: Option<String>,
+    #[serde(rename = "toBlock", skip_serializing_if = "Option::is_none")]
+    pub to_block: Option<String>,
+    /// The contracts emitting the logs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<Vec<String>>,
+    /// The topics by position, each matching any of its values (`None` matching anything)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub topics: Vec<Option<Vec<String>>>,
+}
+
 static REQUEST_SWITCH_CHAIN_ID: &str = "wallet_switchEthereumChain";
 static REQUEST_ADD_CHAIN: &str = "wallet_addEthereumChain";
 static REQUEST_ACCOUNTS: &str = "eth_requestAccounts";
+static REQUEST_CONNECTED_ACCOUNTS: &str = "eth_accounts";
+static REQUEST_PERMISSIONS: &str = "wallet_requestPermissions";
+static REQUEST_GET_PERMISSIONS: &str = "wallet_getPermissions";
+static REQUEST_REVOKE_PERMISSIONS: &str = "wallet_revokePermissions";
 static REQUEST_PERSONAL_SIGN: &str = "personal_sign";
 static REQUEST_SIGN: &str = "eth_sign";
 static REQUEST_SIGN_TYPED_DATA: &str = "eth_signTypedData";
+static REQUEST_SIGN_TYPED_DATA_V3: &str = "eth_signTypedData_v3";
+static REQUEST_SIGN_TYPED_DATA_V4: &str = "eth_signTypedData_v4";
 static REQUEST_SIGN_TRANSACTION: &str = "eth_signTransaction";
+static REQUEST_SEND_TRANSACTION: &str = "eth_sendTransaction";
+static REQUEST_SEND_RAW_TRANSACTION: &str = "eth_sendRawTransaction";
+static REQUEST_ESTIMATE_GAS: &str = "eth_estimateGas";
+static REQUEST_GAS_PRICE: &str = "eth_gasPrice";
+static REQUEST_TRANSACTION_BY_HASH: &str = "eth_getTransactionByHash";
+static REQUEST_TRANSACTION_RECEIPT: &str = "eth_getTransactionReceipt";
+static REQUEST_WATCH_ASSET: &str = "wallet_watchAsset";
+static REQUEST_CHAIN_ID: &str = "eth_chainId";
+static REQUEST_BALANCE: &str = "eth_getBalance";
+static REQUEST_CALL: &str = "eth_call";
+static REQUEST_SUBSCRIBE: &str = "eth_subscribe";
+static REQUEST_UNSUBSCRIBE: &str = "eth_unsubscribe";
+static REQUEST_NEW_FILTER: &str = "eth_newFilter";
+static REQUEST_NEW_BLOCK_FILTER: &str = "eth_newBlockFilter";
+static REQUEST_NEW_PENDING_TRANSACTION_FILTER: &str = "eth_newPendingTransactionFilter";
+static REQUEST_FILTER_CHANGES: &str = "eth_getFilterChanges";
+static REQUEST_FILTER_LOGS: &str = "eth_getFilterLogs";
+static REQUEST_UNINSTALL_FILTER: &str = "eth_uninstallFilter";
+static REQUEST_NET_VERSION: &str = "net_version";
+
+/// A parameter of `eth_call`
+#[derive(Serialize)]
+#[serde(untagged)]
+enum CallParam {
+    Transaction(Transaction),
+    Block(String),
+}
+
+fn call_params(transaction: Transaction, block: Option<String>) -> RequestMethodParams<CallParam> {
+    RequestMethodParams::Vec(vec![
+        CallParam::Transaction(transaction),
+        CallParam::Block(block.unwrap_or_else(|| "latest".to_owned())),
+    ])
+}
+
+/// Describes the disagreement between `eth_chainId` (hex) and `net_version` (decimal), `None` if
+/// they agree or can't be parsed
+fn network_mismatch(chain_id: &str, net_version: &str) -> Option<String> {
+    let chain_id = u64::from_str_radix(chain_id.trim_start_matches("0x"), 16).ok()?;
+    let net_version = match net_version.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok()?,
+        None => net_version.parse::<u64>().ok()?,
+    };
+    if chain_id == net_version {
+        return None
+    }
+    Some(format!(
+        "the wallet reports chain {} (eth_chainId) but network {} (net_version), it is likely \
+         misconfigured and signing might fail",
+        chain_id, net_version
+    ))
+}
+
+/// Parses a hex quantity returned by the node (e.g. `0x1bc16d674ec80000`)
+fn parse_quantity(quantity: &str) -> Result<U256, ProviderError> {
+    U256::from_str_radix(quantity.strip_prefix("0x").unwrap_or(quantity), 16)
+        .map_err(|e| ProviderError::Deserialize(format!("invalid quantity `{}`: {}", quantity, e)))
+}
 
 impl Provider {
     pub async fn request<T: Serialize>(
@@ -330,10 +840,9 @@ impl Provider {
         method: String,
         params: Option<RequestMethodParams<T>>,
     ) -> Result<JsValue, ProviderError> {
-        let promise = self
-            .request
-            .call1(&self.this, &serde_wasm_bindgen::to_value(&RequestMethod { method, params })?)?;
-        wasm_bindgen_futures::JsFuture::from(js_sys::Promise::from(promise)).await.map_err(|e| {
+        let args = serde_wasm_bindgen::to_value(&RequestMethod { method: method.clone(), params })?;
+        let promise = to_promise(&method, self.request.call1(&self.this, &args))?;
+        wasm_bindgen_futures::JsFuture::from(promise).await.map_err(|e| {
             match serde_wasm_bindgen::from_value(e) {
                 Ok(err) => ProviderError::RPC(err),
                 Err(err) => ProviderError::Deserialize(err.to_string()),
@@ -343,6 +852,8 @@ impl Provider {
 
     // TODO: missing functions
 
+    /// Per EIP-3326, a successful switch resolves to `null` (or `undefined` for some wallets),
+    /// so the result is never deserialized
     pub async fn request_switch_chain(&self, chain_id: String) -> Result<(), ProviderError> {
         match self
             .request(
@@ -365,11 +876,139 @@ impl Provider {
         Ok(())
     }
 
+    /// Returns the balance of `address` in wei at `block` (a block number, hash or tag), defaults
+    /// to `latest`
+    pub async fn request_balance(
+        &self,
+        address: String,
+        block: Option<String>,
+    ) -> Result<U256, ProviderError> {
+        let block = block.unwrap_or_else(|| "latest".to_owned());
+        let data = self
+            .request(
+                REQUEST_BALANCE.to_owned(),
+                Some(RequestMethodParams::Vec(vec![address, block])),
+            )
+            .await?;
+        parse_quantity(&parse_js::<String>(data)?)
+    }
+
+    /// Executes a read-only call (e.g. an ERC-20 `balanceOf`) at `block` (a block number, hash or
+    /// tag), defaults to `latest`. Returns the hex encoded return data
+    pub async fn request_call(
+        &self,
+        transaction: Transaction,
+        block: Option<String>,
+    ) -> Result<String, ProviderError> {
+        let data =
+            self.request(REQUEST_CALL.to_owned(), Some(call_params(transaction, block))).await?;
+        parse_js(data)
+    }
+
+    pub async fn request_chain_id(&self) -> Result<String, ProviderError> {
+        let data = self.request::<()>(REQUEST_CHAIN_ID.to_owned(), None).await?;
+        parse_js(data)
+    }
+
+    pub async fn request_net_version(&self) -> Result<String, ProviderError> {
+        let data = self.request::<()>(REQUEST_NET_VERSION.to_owned(), None).await?;
+        parse_js(data)
+    }
+
+    /// Returns a warning if `eth_chainId` and `net_version` disagree (a known MetaMask
+    /// inconsistency), never an error: wallets not supporting one of them are given the benefit of
+    /// the doubt
+    pub async fn request_network_mismatch(&self) -> Option<String> {
+        let chain_id = self.request_chain_id().await.ok()?;
+        let net_version = self.request_net_version().await.ok()?;
+        network_mismatch(&chain_id, &net_version)
+    }
+
+    /// Asks the user to track a token in their wallet, returns whether they accepted
+    pub async fn request_watch_asset(
+        &self,
+        params: WatchAssetParams,
+    ) -> Result<bool, ProviderError> {
+        let data = self
+            .request(REQUEST_WATCH_ASSET.to_owned(), Some(RequestMethodParams::Object(params)))
+            .await?;
+        parse_js(data)
+    }
+
     pub async fn request_accounts(&self) -> Result<Vec<String>, ProviderError> {
         let data = self.request::<()>(REQUEST_ACCOUNTS.to_owned(), None).await?;
         parse_js(data)
     }
 
+    /// Same as `selected_address`, falling back to the first account of `eth_accounts` (which
+    /// never prompts the user) for wallets without `selectedAddress`
+    pub async fn request_selected_address(&self) -> Result<Option<String>, ProviderError> {
+        if let Some(address) = self.selected_address() {
+            return Ok(Some(address))
+        }
+        let data = self.request::<()>(REQUEST_CONNECTED_ACCOUNTS.to_owned(), None).await?;
+        Ok(parse_js::<Vec<String>>(data)?.into_iter().next())
+    }
+
+    /// Asks the user to grant `permissions` (e.g. `{ "eth_accounts": {} }`), returns the granted
+    /// ones
+    pub async fn request_permissions(
+        &self,
+        permissions: Value,
+    ) -> Result<Vec<Permission>, ProviderError> {
+        let data = self
+            .request(
+                REQUEST_PERMISSIONS.to_owned(),
+                Some(RequestMethodParams::Vec(vec![permissions])),
+            )
+            .await?;
+        parse_js(data)
+    }
+
+    /// Sends any method, for the ones without a dedicated `request_*` method. `params` is sent as
+    /// is, unless it's `null` in which case it's omitted
+    pub async fn request_raw(&self, method: String, params: Value) -> Result<Value, ProviderError> {
+        let data = self.request(method, raw_params(params)).await?;
+        parse_js(data)
+    }
+
+    /// Returns the permissions currently granted to the page, never prompts the user
+    pub async fn request_get_permissions(&self) -> Result<Vec<Permission>, ProviderError> {
+        let data = self.request::<()>(REQUEST_GET_PERMISSIONS.to_owned(), None).await?;
+        parse_js(data)
+    }
+
+    /// Revokes `permissions` (e.g. `{ "eth_accounts": {} }`), only supported by some wallets
+    /// (e.g. MetaMask)
+    pub async fn request_revoke_permissions(
+        &self,
+        permissions: Value,
+    ) -> Result<(), ProviderError> {
+        self.request(
+            REQUEST_REVOKE_PERMISSIONS.to_owned(),
+            Some(RequestMethodParams::Vec(vec![permissions])),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Re-prompts the wallet's account selector (through EIP-2255 `wallet_requestPermissions`)
+    /// and returns the newly selected accounts
+    pub async fn prompt_account_selection(&self) -> Result<Vec<String>, ProviderError> {
+        match self.request_permissions(serde_json::json!({ "eth_accounts": {} })).await {
+            Ok(_) => self.request_accounts().await,
+            Err(ProviderError::RPC(e))
+                if matches!(e.code, ErrorCodes::UnsupportedMethod | ErrorCodes::Other(-32601)) =>
+            {
+                Err(ProviderError::Unsupported(format!(
+                    "wallet does not support switching accounts: {}",
+                    e.message
+                )))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
     pub async fn request_sign_text(
         &self,
         address: String,
@@ -398,6 +1037,9 @@ impl Provider {
         parse_js(data)
     }
 
+    /// Signs with the bare `eth_signTypedData`, kept for compatibility: its format depends on the
+    /// wallet, prefer `request_sign_typed_data_v4` which modern wallets (e.g. MetaMask) require
+    /// for nested types and arrays
     pub async fn request_sign_typed_data<T: Serialize>(
         &self,
         address: String,
@@ -415,6 +1057,83 @@ impl Provider {
         parse_js(data)
     }
 
+    /// Signs with `eth_signTypedData_v4`, the data being sent as a JSON string after the address
+    pub async fn request_sign_typed_data_v4<T: Serialize>(
+        &self,
+        address: String,
+        data: T,
+    ) -> Result<String, ProviderError> {
+        self.request_sign_typed_data_with_version(address, data, TypedDataVersion::V4).await
+    }
+
+    /// Sign typed data with a specific version of `eth_signTypedData`, the data is stringified
+    /// or not depending on what the version expects
+    pub async fn request_sign_typed_data_with_version<T: Serialize>(
+        &self,
+        address: String,
+        data: T,
+        version: TypedDataVersion,
+    ) -> Result<String, ProviderError> {
+        let data = self
+            .request(
+                version.method().to_owned(),
+                Some(sign_typed_data_params(address, data, version)?),
+            )
+            .await?;
+        parse_js(data)
+    }
+
+    /// Returns the gas limit estimated by the wallet's node, fails with the node's RPC error when
+    /// the transaction would revert
+    pub async fn request_estimate_gas(
+        &self,
+        transaction: Transaction,
+    ) -> Result<U256, ProviderError> {
+        let data = self
+            .request(
+                REQUEST_ESTIMATE_GAS.to_owned(),
+                Some(RequestMethodParams::Vec(vec![transaction])),
+            )
+            .await?;
+        parse_quantity(&parse_js::<String>(data)?)
+    }
+
+    /// Returns the current gas price of the wallet's node, as a hex quantity
+    pub async fn request_gas_price(&self) -> Result<String, ProviderError> {
+        let data = self.request::<()>(REQUEST_GAS_PRICE.to_owned(), None).await?;
+        parse_js(data)
+    }
+
+    /// Returns the transaction object as given by the wallet's node, `None` if the hash is unknown
+    // TODO: parse the common fields into a typed struct
+    pub async fn request_transaction_by_hash(
+        &self,
+        hash: String,
+    ) -> Result<Option<Value>, ProviderError> {
+        let data = self
+            .request(
+                REQUEST_TRANSACTION_BY_HASH.to_owned(),
+                Some(RequestMethodParams::Vec(vec![hash])),
+            )
+            .await?;
+        parse_js(data)
+    }
+
+    /// Returns the receipt as given by the wallet's node, `None` until the transaction is mined
+    /// (e.g. to poll for its confirmation after `request_send_transaction`)
+    pub async fn request_transaction_receipt(
+        &self,
+        hash: String,
+    ) -> Result<Option<Value>, ProviderError> {
+        let data = self
+            .request(
+                REQUEST_TRANSACTION_RECEIPT.to_owned(),
+                Some(RequestMethodParams::Vec(vec![hash])),
+            )
+            .await?;
+        parse_js(data)
+    }
+
     pub async fn request_sign_transaction(
         &self,
         transaction: Transaction,
@@ -427,4 +1146,503 @@ impl Provider {
             .await?;
         parse_js(data)
     }
+
+    /// Signs and broadcasts the transaction through the wallet, returning its hash. Some wallets
+    /// (e.g. MetaMask) only support this and refuse `eth_signTransaction`
+    pub async fn request_send_transaction(
+        &self,
+        transaction: Transaction,
+    ) -> Result<String, ProviderError> {
+        let data = self
+            .request(
+                REQUEST_SEND_TRANSACTION.to_owned(),
+                Some(RequestMethodParams::Vec(vec![transaction])),
+            )
+            .await?;
+        parse_js(data)
+    }
+
+    /// Broadcasts an already signed transaction (RLP, hex encoded) through the wallet's node,
+    /// returning its hash
+    pub async fn request_send_raw_transaction(
+        &self,
+        signed_rlp: String,
+    ) -> Result<String, ProviderError> {
+        let data = self
+            .request(
+                REQUEST_SEND_RAW_TRANSACTION.to_owned(),
+                Some(RequestMethodParams::Vec(vec![signed_rlp])),
+            )
+            .await?;
+        parse_js(data)
+    }
+
+    /// Creates a filter of the logs matching `params`, returns its id. Filters are the polling
+    /// alternative to `subscribe`, for wallets which don't support subscriptions, nodes drop the
+    /// ones which aren't polled for a while (usually 5 minutes)
+    pub async fn request_new_filter(&self, params: FilterParams) -> Result<String, ProviderError> {
+        let data = self
+            .request(REQUEST_NEW_FILTER.to_owned(), Some(RequestMethodParams::Vec(vec![params])))
+            .await?;
+        parse_js(data)
+    }
+
+    /// Creates a filter of the new blocks, whose changes are block hashes
+    pub async fn request_new_block_filter(&self) -> Result<String, ProviderError> {
+        let data = self.request::<()>(REQUEST_NEW_BLOCK_FILTER.to_owned(), None).await?;
+        parse_js(data)
+    }
+
+    /// Creates a filter of the new pending transactions, whose changes are transaction hashes
+    pub async fn request_new_pending_transaction_filter(&self) -> Result<String, ProviderError> {
+        let data =
+            self.request::<()>(REQUEST_NEW_PENDING_TRANSACTION_FILTER.to_owned(), None).await?;
+        parse_js(data)
+    }
+
+    /// Returns what changed since the filter `id` was last polled
+    pub async fn request_filter_changes(&self, id: String) -> Result<Vec<Value>, ProviderError> {
+        let data = self
+            .request(REQUEST_FILTER_CHANGES.to_owned(), Some(RequestMethodParams::Vec(vec![id])))
+            .await?;
+        parse_js(data)
+    }
+
+    /// Returns all the logs matching the filter `id`, only for filters from `request_new_filter`
+    pub async fn request_filter_logs(&self, id: String) -> Result<Vec<Value>, ProviderError> {
+        let data = self
+            .request(REQUEST_FILTER_LOGS.to_owned(), Some(RequestMethodParams::Vec(vec![id])))
+            .await?;
+        parse_js(data)
+    }
+
+    /// Removes the filter `id`, returns whether the node knew it
+    pub async fn request_uninstall_filter(&self, id: String) -> Result<bool, ProviderError> {
+        let data = self
+            .request(REQUEST_UNINSTALL_FILTER.to_owned(), Some(RequestMethodParams::Vec(vec![id])))
+            .await?;
+        parse_js(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn it_passes_typed_data_in_the_format_expected_by_each_version() {
+        let address = "0xcd2a3d9f938e13cd947ec05abc7fe734df8dd826".to_owned();
+        let data = json!({ "primaryType": "Mail" });
+
+        let params = |version| {
+            serde_json::to_value(
+                sign_typed_data_params(address.clone(), data.clone(), version).unwrap(),
+            )
+            .unwrap()
+        };
+        assert_eq!(params(TypedDataVersion::V1), json!([data, address]));
+        assert_eq!(params(TypedDataVersion::V3), json!([address, data.to_string()]));
+        assert_eq!(params(TypedDataVersion::V4), json!([address, data.to_string()]));
+        assert!(params(TypedDataVersion::V4)[1].is_string());
+    }
+
+    #[test]
+    fn it_serializes_watch_asset_params() {
+        let params = WatchAssetParams::erc20(
+            "0xb60e8dd61c5d32be8058bb8eb970870f07233155".to_owned(),
+            "FOO".to_owned(),
+            18,
+            None,
+        );
+        assert_eq!(
+            serde_json::to_value(RequestMethodParams::Object(params)).unwrap(),
+            json!({
+                "type": "ERC20",
+                "options": {
+                    "address": "0xb60e8dd61c5d32be8058bb8eb970870f07233155",
+                    "symbol": "FOO",
+                    "decimals": 18,
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn it_serializes_call_params() {
+        let transaction = Transaction {
+            from: None,
+            to: "0x6b175474e89094c44da98b954eedeac495271d0f".to_owned(),
+            gas: None,
+            gas_price: None,
+            value: None,
+            data: "0x70a08231".to_owned(),
+            nonce: None,
+        };
+        let params = serde_json::to_value(call_params(transaction, None)).unwrap();
+        assert_eq!(params[0].get("from"), None);
+        assert_eq!(params[0]["to"], "0x6b175474e89094c44da98b954eedeac495271d0f");
+        assert_eq!(params[1], "latest");
+    }
+
+    #[test]
+    fn it_serializes_filter_params() {
+        assert_eq!(serde_json::to_value(FilterParams::default()).unwrap(), json!({}));
+        let params = FilterParams {
+            from_block: Some("0x1".to_owned()),
+            to_block: Some("latest".to_owned()),
+            address: Some(vec!["0x6b175474e89094c44da98b954eedeac495271d0f".to_owned()]),
+            topics: vec![
+                Some(vec![
+                    "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef".to_owned()
+                ]),
+                None,
+            ],
+        };
+        assert_eq!(
+            serde_json::to_value(params).unwrap(),
+            json!({
+                "fromBlock": "0x1",
+                "toBlock": "latest",
+                "address": ["0x6b175474e89094c44da98b954eedeac495271d0f"],
+                "topics": [
+                    ["0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef"],
+                    null,
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn it_passes_raw_params_as_is() {
+        let params = |params| serde_json::to_value(raw_params(params)).unwrap();
+        assert_eq!(params(json!(["0x1234"])), json!(["0x1234"]));
+        assert_eq!(params(json!({ "type": "ERC20" })), json!({ "type": "ERC20" }));
+        assert_eq!(params(json!(null)), json!(null));
+    }
+
+    #[test]
+    fn it_extracts_subscription_results() {
+        let message = |typ: &str| -> Message {
+            serde_json::from_value(json!({
+                "type": typ,
+                "data": { "subscription": "0x1", "result": { "number": "0x5" } },
+            }))
+            .unwrap()
+        };
+        assert_eq!(
+            subscription_result(message("eth_subscription")),
+            Some(("0x1".to_owned(), json!({ "number": "0x5" })))
+        );
+        assert_eq!(subscription_result(message("other")), None);
+    }
+
+    #[test]
+    fn it_parses_quantities() {
+        assert_eq!(parse_quantity("0x0"), Ok(U256::zero()));
+        assert_eq!(
+            parse_quantity("0x1bc16d674ec80000"),
+            Ok(U256::from(2_000_000_000_000_000_000u64))
+        );
+        assert!(matches!(parse_quantity("0xzz"), Err(ProviderError::Deserialize(_))));
+    }
+
+    #[test]
+    fn it_detects_network_mismatches() {
+        assert_eq!(network_mismatch("0x1", "1"), None);
+        assert_eq!(network_mismatch("0x89", "137"), None);
+        assert_eq!(network_mismatch("0x89", "0x89"), None);
+        assert_eq!(network_mismatch("0x1", "unknown"), None);
+        assert!(network_mismatch("0x1", "5").unwrap().contains("chain 1 (eth_chainId)"));
+    }
+
+    #[test]
+    fn it_deserializes_error_codes() {
+        let err: RPCError =
+            serde_json::from_value(json!({ "code": 4001, "message": "rejected" })).unwrap();
+        assert_eq!(err.code, ErrorCodes::UserRejectedRequest);
+        let err: RPCError = serde_json::from_value(
+            json!({ "code": -32000, "message": "nonce too low", "data": { "a": 1 } }),
+        )
+        .unwrap();
+        assert_eq!(err.code, ErrorCodes::Other(-32000));
+        assert_eq!(err.code.code(), -32000);
+        assert_eq!(err.data, Some(json!({ "a": 1 })));
+        for code in [4001, 4100, 4200, 4900, 4901, -32603] {
+            assert_eq!(serde_json::to_value(ErrorCodes::from(code)).unwrap(), json!(code));
+        }
+    }
+}
+
+#[cfg(all(test, target_arch = "wasm32"))]
+mod wasm_tests {
+    use super::*;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    fn stub_provider(request_body: &str) -> Provider {
+        let provider = Object::new();
+        let noop = Function::new_no_args("");
+        let request = Function::new_with_args("args", request_body);
+        js_sys::Reflect::set(&provider, &JsValue::from("request"), &request).unwrap();
+        js_sys::Reflect::set(&provider, &JsValue::from("on"), &noop).unwrap();
+        js_sys::Reflect::set(&provider, &JsValue::from("removeListener"), &noop).unwrap();
+        Provider::from_object(provider, false).unwrap()
+    }
+
+    fn set(object: &Object, key: &str, value: &JsValue) {
+        js_sys::Reflect::set(object, &JsValue::from(key), value).unwrap();
+    }
+
+    #[wasm_bindgen_test]
+    fn it_detects_multiple_providers() {
+        let flagged = |flag: &str| {
+            let object = Object::new();
+            set(&object, flag, &JsValue::TRUE);
+            object
+        };
+
+        let provider = Provider::from_object(flagged("isMetaMask"), true).unwrap();
+        assert_eq!(provider.multiple_providers(), None);
+
+        let object = Object::new();
+        let providers = js_sys::Array::of3(
+            &flagged("isMetaMask"),
+            &flagged("isCoinbaseWallet"),
+            &Object::new(),
+        );
+        set(&object, "providers", &providers);
+        let provider = Provider::from_object(object, true).unwrap();
+        assert_eq!(
+            provider.multiple_providers(),
+            Some(MultipleProviders {
+                wallets: vec![
+                    Some("MetaMask".to_owned()),
+                    Some("Coinbase Wallet".to_owned()),
+                    None
+                ],
+            })
+        );
+
+        let object = flagged("isMetaMask");
+        set(&object, "isCoinbaseWallet", &JsValue::TRUE);
+        let provider = Provider::from_object(object, true).unwrap();
+        assert!(provider.multiple_providers().is_some());
+    }
+
+    #[wasm_bindgen_test]
+    async fn it_reads_the_selected_address() {
+        let provider = stub_provider("return Promise.resolve(['0xabc', '0xdef'])");
+        assert_eq!(provider.selected_address(), None);
+        assert_eq!(provider.request_selected_address().await.unwrap(), Some("0xabc".to_owned()));
+
+        set(&provider.this.clone().into(), "selectedAddress", &JsValue::NULL);
+        assert_eq!(provider.selected_address(), None);
+
+        set(&provider.this.clone().into(), "selectedAddress", &JsValue::from("0x123"));
+        assert_eq!(provider.selected_address(), Some("0x123".to_owned()));
+        assert_eq!(provider.request_selected_address().await.unwrap(), Some("0x123".to_owned()));
+    }
+
+    #[wasm_bindgen_test]
+    async fn it_resolves_the_next_matching_event() {
+        let object = Object::new();
+        set(&object, "listeners", &js_sys::Array::new());
+        set(&object, "request", &Function::new_no_args(""));
+        set(&object, "on", &Function::new_with_args("e, cb", "this.listeners.push([e, cb])"));
+        set(
+            &object,
+            "removeListener",
+            &Function::new_with_args(
+                "e, cb",
+                "this.listeners = this.listeners.filter(([e2, cb2]) => e2 !== e || cb2 !== cb)",
+            ),
+        );
+        let emit = Function::new_with_args(
+            "e, data",
+            "this.listeners.filter(([e2]) => e2 === e).forEach(([, cb]) => cb(data))",
+        );
+        let listeners = || {
+            js_sys::Array::from(&js_sys::Reflect::get(&object, &"listeners".into()).unwrap())
+                .length()
+        };
+        let provider = Provider::from_object(object.clone(), false).unwrap();
+
+        let next = provider.next_event(&[EventKind::ChainChanged, EventKind::Connect]).unwrap();
+        assert_eq!(listeners(), 2);
+        emit.call2(&object, &"accountsChanged".into(), &js_sys::Array::new()).unwrap();
+        emit.call2(&object, &"chainChanged".into(), &"0x5".into()).unwrap();
+        assert!(matches!(next.await, Event::ChainChanged(id) if id == "0x5"));
+        assert_eq!(listeners(), 0);
+
+        let next = provider.next_event(&[EventKind::AccountsChanged]).unwrap();
+        assert_eq!(listeners(), 1);
+        drop(next);
+        assert_eq!(listeners(), 0);
+    }
+
+    #[wasm_bindgen_test]
+    async fn it_streams_subscription_results() {
+        let object = Object::new();
+        set(&object, "listeners", &js_sys::Array::new());
+        set(&object, "request", &Function::new_with_args("args", "return Promise.resolve('0x2')"));
+        set(&object, "on", &Function::new_with_args("e, cb", "this.listeners.push(cb)"));
+        set(&object, "removeListener", &Function::new_with_args("e, cb", "this.listeners = []"));
+        let provider = Provider::from_object(object.clone(), false).unwrap();
+
+        let mut stream = provider.subscribe("newHeads", None).await.unwrap();
+        assert_eq!(stream.id(), "0x2");
+        let emit = Function::new_with_args(
+            "id, n",
+            "this.listeners.forEach((cb) => cb({ type: 'eth_subscription', data: { \
+             subscription: id, result: n } }))",
+        );
+        emit.call2(&object, &"0x1".into(), &1.into()).unwrap();
+        emit.call2(&object, &"0x2".into(), &2.into()).unwrap();
+        let next = std::future::poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await;
+        assert_eq!(next, Some(json!(2)));
+    }
+
+    #[wasm_bindgen_test]
+    async fn it_treats_a_null_switch_chain_result_as_success() {
+        let provider = stub_provider("return Promise.resolve(null)");
+        assert_eq!(provider.request_switch_chain("0x1".to_owned()).await, Ok(()));
+        let provider = stub_provider("return Promise.resolve(undefined)");
+        assert_eq!(provider.request_switch_chain("0x1".to_owned()).await, Ok(()));
+    }
+
+    #[wasm_bindgen_test]
+    async fn it_looks_up_transactions_by_hash() {
+        let hash = "0x88df016429689c079f3b2f6ad39fa052532c56795b733da78a91ebe6a713944b";
+        let provider = stub_provider("return Promise.resolve(null)");
+        assert_eq!(provider.request_transaction_by_hash(hash.to_owned()).await, Ok(None));
+
+        let provider = stub_provider(
+            "return Promise.resolve({ hash: args.params[0], blockNumber: '0x5daf3b', nonce: '0x15' })",
+        );
+        let transaction = provider.request_transaction_by_hash(hash.to_owned()).await.unwrap();
+        assert_eq!(transaction.unwrap()["hash"], hash);
+    }
+
+    #[wasm_bindgen_test]
+    async fn it_polls_filters() {
+        let provider = stub_provider(
+            "return Promise.resolve(args.method === 'eth_newBlockFilter' ? '0x1' : ['0xabc'])",
+        );
+        let id = provider.request_new_block_filter().await.unwrap();
+        assert_eq!(id, "0x1");
+        let changes = provider.request_filter_changes(id).await.unwrap();
+        assert_eq!(changes, vec![Value::String("0xabc".to_owned())]);
+    }
+
+    #[wasm_bindgen_test]
+    async fn it_creates_and_removes_log_filters() {
+        // the filter id is the parameters as received by the wallet
+        let provider = stub_provider(
+            "return Promise.resolve(args.method === 'eth_newFilter' ? JSON.stringify(args.params) \
+             : args.method === 'eth_uninstallFilter' && args.params[0] === '0x1')",
+        );
+        let params = FilterParams {
+            from_block: Some("latest".to_owned()),
+            address: Some(vec!["0xabc".to_owned()]),
+            topics: vec![None, Some(vec!["0xdef".to_owned()])],
+            ..Default::default()
+        };
+        assert_eq!(
+            provider.request_new_filter(params).await,
+            Ok(r#"[{"fromBlock":"latest","address":["0xabc"],"topics":[null,["0xdef"]]}]"#
+                .to_owned())
+        );
+        assert_eq!(provider.request_uninstall_filter("0x1".to_owned()).await, Ok(true));
+        assert_eq!(provider.request_uninstall_filter("0x2".to_owned()).await, Ok(false));
+    }
+
+    #[wasm_bindgen_test]
+    async fn it_polls_transaction_receipts() {
+        let hash = "0x88df016429689c079f3b2f6ad39fa052532c56795b733da78a91ebe6a713944b";
+        let provider = stub_provider("return Promise.resolve(null)");
+        assert_eq!(provider.request_transaction_receipt(hash.to_owned()).await, Ok(None));
+
+        let provider = stub_provider(
+            "return Promise.resolve(args.method === 'eth_getTransactionReceipt' ? { \
+             transactionHash: args.params[0], status: '0x1' } : null)",
+        );
+        let receipt = provider.request_transaction_receipt(hash.to_owned()).await.unwrap();
+        assert_eq!(receipt.unwrap()["status"], "0x1");
+    }
+
+    #[wasm_bindgen_test]
+    async fn it_reads_the_chain_id() {
+        let provider = stub_provider(
+            "return args.method === 'eth_chainId' ? Promise.resolve('0x5') : Promise.reject({ \
+             code: -32601, message: 'method not found', data: null })",
+        );
+        assert_eq!(provider.request_chain_id().await, Ok("0x5".to_owned()));
+    }
+
+    #[wasm_bindgen_test]
+    async fn it_ignores_unsupported_network_checks() {
+        let provider = stub_provider(
+            "return args.method === 'eth_chainId' ? Promise.resolve('0x1') : Promise.reject({ \
+             code: -32601, message: 'method not found', data: null })",
+        );
+        assert_eq!(provider.request_network_mismatch().await, None);
+        let provider =
+            stub_provider("return Promise.resolve(args.method === 'eth_chainId' ? '0x1' : '5')");
+        assert!(provider.request_network_mismatch().await.is_some());
+    }
+
+    #[wasm_bindgen_test]
+    async fn it_broadcasts_raw_transactions() {
+        let hash = "0x88df016429689c079f3b2f6ad39fa052532c56795b733da78a91ebe6a713944b";
+        let provider = stub_provider(&format!("return Promise.resolve('{}')", hash));
+        assert_eq!(
+            provider.request_send_raw_transaction("0xf86c".to_owned()).await,
+            Ok(hash.to_owned())
+        );
+
+        let provider = stub_provider(
+            "return Promise.reject({ code: -32000, message: 'nonce too low', data: null })",
+        );
+        assert!(matches!(
+            provider.request_send_raw_transaction("0xf86c".to_owned()).await,
+            Err(ProviderError::RPC(e)) if e.code == ErrorCodes::Other(-32000)
+        ));
+    }
+
+    #[wasm_bindgen_test]
+    async fn it_reads_permissions() {
+        let provider = stub_provider(
+            "return Promise.resolve(args.method === 'wallet_getPermissions' ? [{ \
+             parentCapability: 'eth_accounts', invoker: 'http://localhost', caveats: [] }] : [])",
+        );
+        let permissions = provider.request_get_permissions().await.unwrap();
+        assert_eq!(permissions.len(), 1);
+        assert_eq!(permissions[0].parent_capability, "eth_accounts");
+        assert_eq!(permissions[0].invoker.as_deref(), Some("http://localhost"));
+    }
+
+    #[wasm_bindgen_test]
+    async fn it_maps_unsupported_account_selection() {
+        let provider = stub_provider(
+            "return Promise.reject({ code: -32601, message: 'method not found', data: null })",
+        );
+        assert!(matches!(
+            provider.prompt_account_selection().await,
+            Err(ProviderError::Unsupported(e)) if e.contains("switching accounts")
+        ));
+    }
+
+    #[wasm_bindgen_test]
+    async fn it_rejects_providers_not_returning_a_promise() {
+        let provider = stub_provider("return 42");
+        assert!(matches!(
+            provider.request_accounts().await,
+            Err(ProviderError::Unsupported(e)) if e.contains("non-Promise")
+        ));
+        let provider = stub_provider("throw new Error('boom')");
+        assert!(matches!(
+            provider.request_accounts().await,
+            Err(ProviderError::Unsupported(e)) if e.contains("threw synchronously")
+        ));
+    }
 }
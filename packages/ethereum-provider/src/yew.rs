@@ -1,6 +1,11 @@
 pub use crate::provider::NativeCurrency;
-use crate::provider::{ChainData, Provider, ProviderError};
-use std::{collections::HashMap, rc::Rc};
+use crate::{
+    chains,
+    provider::{
+        ChainData, ConnectInfo, ErrorCodes, Provider, ProviderError, RPCError, WatchAssetParams,
+    },
+};
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
 use tokio::sync::mpsc;
 use wasm_bindgen_futures::spawn_local;
 use web_sys::{window, Window};
@@ -17,32 +22,69 @@ fn listen_to_provider(
     provider: Provider,
     error: UseStateHandle<Option<ProviderError>>,
     chain_id: UseStateHandle<Option<String>>,
+    chain_changed: Rc<RefCell<bool>>,
     accounts: UseStateHandle<Option<Vec<String>>>,
+    connected: UseStateHandle<Option<bool>>,
 ) -> Result<Box<dyn Fn()>, ProviderError> {
     let chain_changed_cb = {
         let error = error.clone();
         Box::new(move |new_chain_id: Result<String, ProviderError>| match new_chain_id {
-            Ok(new_chain_id) => chain_id.set(Some(new_chain_id)),
+            Ok(new_chain_id) => {
+                *chain_changed.borrow_mut() = true;
+                chain_id.set(Some(new_chain_id))
+            }
             Err(err) => error.set(Some(err)),
         })
     };
 
-    let accounts_changed_cb =
+    let accounts_changed_cb = {
+        let accounts = accounts.clone();
+        let error = error.clone();
         Box::new(move |new_accounts: Result<Vec<String>, ProviderError>| match new_accounts {
             Ok(new_accounts) => accounts.set(Some(new_accounts)),
             Err(err) => error.set(Some(err)),
-        });
+        })
+    };
+
+    let connect_cb = {
+        let connected = connected.clone();
+        Box::new(move |info: Result<ConnectInfo, ProviderError>| match info {
+            Ok(_) => connected.set(Some(true)),
+            Err(err) => error.set(Some(err)),
+        })
+    };
+
+    // the payload is the reason, the wallet is disconnected even if it doesn't parse
+    let disconnect_cb = Box::new(move |_: Result<RPCError, ProviderError>| {
+        connected.set(Some(false));
+        accounts.set(None);
+    });
 
     let chain_changed_closure = provider.on_chain_changed(chain_changed_cb)?;
     let accounts_changed_closure = provider.on_accounts_changed(accounts_changed_cb)?;
+    let connect_closure = provider.on_connect(connect_cb)?;
+    let disconnect_closure = provider.on_disconnect(disconnect_cb)?;
 
     Ok(Box::new(move || {
         // FIXME: no error checking because it's too hard (and it's just for logging anyway)
         let _ = provider.remove_chain_changed_listener(&chain_changed_closure);
         let _ = provider.remove_accounts_changed_listener(&accounts_changed_closure);
+        let _ = provider.remove_connect_listener(&connect_closure);
+        let _ = provider.remove_disconnect_listener(&disconnect_closure);
     }))
 }
 
+/// Waits for `provide_chain_info` (which sends `true`) or `cancel_chain_info` (`false`)
+async fn wait_for_chain_info(
+    rx: &mut mpsc::Receiver<bool>,
+    err: RPCError,
+) -> Result<(), ProviderError> {
+    match rx.recv().await {
+        Some(true) => Ok(()),
+        _ => Err(ProviderError::UnknownChain(err)),
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ChainInfo {
     pub chain_name: Option<String>,
@@ -50,8 +92,22 @@ pub struct ChainInfo {
     pub icon_urls: Option<Vec<String>>,
     pub native_currency: Option<NativeCurrency>,
     pub block_explorer_urls: Option<Vec<String>>,
+    /// Overrides the classification of `chains::is_testnet`
+    pub testnet: Option<bool>,
 }
 
+impl ChainInfo {
+    /// Whether there is enough to add the chain to the wallet without asking the user (EIP-3085
+    /// requires at least one RPC URL, and wallets show the name)
+    fn is_complete(&self) -> bool {
+        self.chain_name.is_some() && self.rpc_urls.as_ref().map_or(false, |urls| !urls.is_empty())
+    }
+}
+
+/// A chain waiting for its info, with what is already known about it and where to send whether
+/// it was provided
+type ChainInfoRequest = (u64, Option<ChainInfo>, mpsc::Sender<bool>);
+
 #[derive(Debug, Clone)]
 pub struct ProviderStatus {
     /// The current provider
@@ -60,8 +116,18 @@ pub struct ProviderStatus {
     pub chain_id: Option<String>,
     /// The accounts available on this provider with the current `chain_id`
     pub accounts: Option<Vec<String>>,
+    /// Set when the wallet looks misconfigured, see `Provider::request_network_mismatch`
+    pub network_warning: Option<String>,
+    /// Whether the wallet can reach its node, from the EIP-1193 `connect` and `disconnect`
+    /// events, `None` until one of them fires
+    pub connected: Option<bool>,
 
-    requires_chain_info: UseStateHandle<Option<(u64, mpsc::Sender<()>)>>,
+    requires_chain_info: UseStateHandle<Option<ChainInfoRequest>>,
+    /// Set by `disconnect` (or until `connect` without `UseProviderOptions::eager_connect`),
+    /// cleared by `connect` and `reconnect`
+    disconnected: UseStateHandle<bool>,
+    chain_id_setter: UseStateHandle<Option<String>>,
+    accounts_setter: UseStateHandle<Option<Vec<String>>>,
 }
 
 impl PartialEq for ProviderStatus {
@@ -69,12 +135,15 @@ impl PartialEq for ProviderStatus {
         self.provider == other.provider &&
             self.chain_id == other.chain_id &&
             self.accounts == other.accounts &&
+            self.network_warning == other.network_warning &&
+            self.connected == other.connected &&
+            *self.disconnected == *other.disconnected &&
             match (
                 Option::clone(&self.requires_chain_info),
                 Option::clone(&other.requires_chain_info),
             ) {
                 (None, None) => true,
-                (Some((a, _)), Some((b, _))) => a == b,
+                (Some((a, _, _)), Some((b, _, _))) => a == b,
                 _ => false,
             }
     }
@@ -82,7 +151,9 @@ impl PartialEq for ProviderStatus {
 
 impl ProviderStatus {
     /// Change the current `chain_id` with smart handling for missing chains, see
-    /// `requires_chain_info`
+    /// `requires_chain_info`. Chains missing from the wallet are added right away if `chains`
+    /// has their name and RPC URLs, otherwise their info is required (prefilled with what
+    /// `chains` has about them, see `suggested_chain_info`)
     pub async fn change_chain(
         &self,
         chain_id: u64,
@@ -91,39 +162,108 @@ impl ProviderStatus {
         let chain_id_str = format!("{:x}", chain_id);
         match self.provider.request_switch_chain(chain_id_str.clone()).await {
             Err(ProviderError::UnknownChain(e)) => {
-                if let Some(chains) = chains {
-                    if let Some(info) = chains.get(&chain_id) {
-                        self.provider
-                            .request_add_chain(ChainData {
-                                chain_id: chain_id_str,
-                                chain_name: info.chain_name.clone(),
-                                rpc_urls: info.rpc_urls.clone(),
-                                icon_urls: info.icon_urls.clone(),
-                                native_currency: info.native_currency.clone(),
-                                block_explorer_urls: info.block_explorer_urls.clone(),
-                            })
-                            .await?;
-                        return Ok(())
-                    }
+                let known = chains.and_then(|mut chains| chains.remove(&chain_id));
+                if let Some(info) = known.as_ref().filter(|info| info.is_complete()) {
+                    self.provider
+                        .request_add_chain(ChainData {
+                            chain_id: chain_id_str,
+                            chain_name: info.chain_name.clone(),
+                            rpc_urls: info.rpc_urls.clone(),
+                            icon_urls: info.icon_urls.clone(),
+                            native_currency: info.native_currency.clone(),
+                            block_explorer_urls: info.block_explorer_urls.clone(),
+                        })
+                        .await?;
+                    return Ok(())
                 }
                 let (tx, mut rx) = mpsc::channel(1);
-                self.requires_chain_info.set(Some((chain_id, tx)));
-                rx.recv().await.ok_or(ProviderError::UnknownChain(e))
+                self.requires_chain_info.set(Some((chain_id, known, tx)));
+                wait_for_chain_info(&mut rx, e).await
             }
             a => a,
         }
     }
 
-    /// If `Some()` is returned it means you should call `provide_chain_info` to unblock the
-    /// `change_chain` call
+    /// Whether the current chain is a known testnet, `None` if the chain is unknown
+    pub fn is_testnet(&self) -> Option<bool> {
+        self.is_testnet_with(None)
+    }
+
+    /// Same as `is_testnet`, the `testnet` of the current chain's info taking precedence
+    pub fn is_testnet_with(&self, chains: Option<&HashMap<u64, ChainInfo>>) -> Option<bool> {
+        let chain_id = self.chain_id.as_deref()?;
+        let chain_id =
+            u64::from_str_radix(chain_id.strip_prefix("0x").unwrap_or(chain_id), 16).ok()?;
+        chains
+            .and_then(|chains| chains.get(&chain_id))
+            .and_then(|info| info.testnet)
+            .or_else(|| chains::is_testnet(chain_id))
+    }
+
+    /// Asks the wallet for its accounts, which prompts the user unless the page is already
+    /// connected. Needed without `UseProviderOptions::eager_connect` or after `disconnect`
+    pub async fn connect(&self) -> Result<Vec<String>, ProviderError> {
+        let accounts = self.provider.request_accounts().await?;
+        self.disconnected.set(false);
+        Ok(accounts)
+    }
+
+    /// Shows the wallet's account selection again (e.g. after the user switched accounts in the
+    /// wallet) instead of relying on the accounts it already exposes, returns the selected ones
+    pub async fn reconnect(&self) -> Result<Vec<String>, ProviderError> {
+        let accounts = self.provider.prompt_account_selection().await?;
+        // listening again and fetching the chain and accounts
+        self.disconnected.set(false);
+        Ok(accounts)
+    }
+
+    /// Stops listening to the wallet and forgets its chain and accounts until `connect`, also
+    /// revoking the `eth_accounts` permission for wallets supporting it (e.g. on a shared machine)
+    pub async fn disconnect(&self) -> Result<(), ProviderError> {
+        self.disconnected.set(true);
+        self.chain_id_setter.set(None);
+        self.accounts_setter.set(None);
+        match self
+            .provider
+            .request_revoke_permissions(serde_json::json!({ "eth_accounts": {} }))
+            .await
+        {
+            Err(ProviderError::RPC(e))
+                if matches!(e.code, ErrorCodes::UnsupportedMethod | ErrorCodes::Other(-32601)) =>
+            {
+                Ok(())
+            }
+            res => res,
+        }
+    }
+
+    /// Whether the accounts are unknown until `connect` (or `reconnect`) is called
+    pub fn is_disconnected(&self) -> bool {
+        *self.disconnected
+    }
+
+    /// Asks the user to track a token (e.g. to offer an "add token" button), returns whether they
+    /// accepted
+    pub async fn watch_asset(&self, params: WatchAssetParams) -> Result<bool, ProviderError> {
+        self.provider.request_watch_asset(params).await
+    }
+
+    /// If `Some()` is returned it means you should call `provide_chain_info` (or
+    /// `cancel_chain_info`) to unblock the `change_chain` call
     pub fn requires_chain_info(&self) -> Option<u64> {
-        self.requires_chain_info.as_ref().map(|(chain_id, _)| *chain_id)
+        self.requires_chain_info.as_ref().map(|(chain_id, _, _)| *chain_id)
+    }
+
+    /// What is already known about the chain returned by `requires_chain_info`, to prefill
+    /// the info the user is asked for
+    pub fn suggested_chain_info(&self) -> Option<ChainInfo> {
+        self.requires_chain_info.as_ref().and_then(|(_, info, _)| info.clone())
     }
 
     pub async fn provide_chain_info(&self, info: ChainInfo) -> Result<(), ProviderError> {
         match Option::clone(&self.requires_chain_info) {
             None => Err(ProviderError::Unsupported("no chain info required".to_string())),
-            Some((chain_id, sender)) => {
+            Some((chain_id, _, sender)) => {
                 let chain_id = format!("{:x}", chain_id);
                 self.provider
                     .request_add_chain(ChainData {
@@ -136,7 +276,7 @@ impl ProviderStatus {
                     })
                     .await?;
                 sender
-                    .send(())
+                    .send(true)
                     .await
                     .map_err(|_| ProviderError::Unsupported("send error".to_string()))?;
                 self.requires_chain_info.set(None);
@@ -144,15 +284,54 @@ impl ProviderStatus {
             }
         }
     }
+
+    /// Gives up on adding the chain, the waiting `change_chain` call returns `UnknownChain`
+    pub fn cancel_chain_info(&self) -> Result<(), ProviderError> {
+        match Option::clone(&self.requires_chain_info) {
+            None => Err(ProviderError::Unsupported("no chain info required".to_string())),
+            Some((_, _, sender)) => {
+                // the receiver might already be gone, which is fine as nobody is waiting anymore
+                let _ = sender.try_send(false);
+                self.requires_chain_info.set(None);
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UseProviderOptions {
+    /// Request the accounts as soon as the wallet is found, which shows the wallet's connection
+    /// prompt on page load. Otherwise they are only requested by `ProviderStatus::connect` (e.g.
+    /// from a "Connect" button). Defaults to true
+    pub eager_connect: bool,
+}
+
+impl Default for UseProviderOptions {
+    fn default() -> Self {
+        Self { eager_connect: true }
+    }
 }
 
 #[hook]
 pub fn use_provider() -> Option<Result<ProviderStatus, ProviderError>> {
+    use_provider_with_options(UseProviderOptions::default())
+}
+
+#[hook]
+pub fn use_provider_with_options(
+    options: UseProviderOptions,
+) -> Option<Result<ProviderStatus, ProviderError>> {
     let provider = use_state(|| None);
     let error = use_state(|| None);
     let chain_id = use_state(|| None);
     let requires_chain_info = use_state(|| None);
     let accounts = use_state(|| None);
+    let network_warning = use_state(|| None);
+    let disconnected = use_state(|| !options.eager_connect);
+    let connected = use_state(|| None);
+    // set once `chainChanged` fired, its value is then more recent than the one fetched eagerly
+    let chain_changed = use_mut_ref(|| false);
 
     {
         let provider = provider.clone();
@@ -174,14 +353,42 @@ pub fn use_provider() -> Option<Result<ProviderStatus, ProviderError>> {
         );
     }
 
+    {
+        let chain_id = chain_id.clone();
+        let chain_changed = chain_changed.clone();
+        use_effect_with_deps(
+            move |(provider, disconnected)| {
+                *chain_changed.borrow_mut() = false;
+                if *disconnected {
+                    return
+                }
+                if let Some(provider) = provider.as_deref() {
+                    let provider = provider.clone();
+                    // `chainChanged` only fires on changes, so the initial chain must be asked for
+                    spawn_local(async move {
+                        if let Ok(current) = provider.request_chain_id().await {
+                            if !*chain_changed.borrow() {
+                                chain_id.set(Some(current));
+                            }
+                        }
+                    });
+                }
+            },
+            (provider.clone(), *disconnected),
+        );
+    }
+
     {
         let provider = provider.clone();
         let error = error.clone();
         let chain_id = chain_id.clone();
+        let chain_changed = chain_changed.clone();
         let accounts = accounts.clone();
+        let connected = connected.clone();
         use_effect_with_deps(
-            move |provider| -> Box<dyn Fn()> {
-                match provider.as_deref() {
+            // the listeners are removed by the cleanup when disconnecting
+            move |(provider, disconnected)| -> Box<dyn Fn()> {
+                match provider.as_deref().filter(|_| !*disconnected) {
                     None => {
                         error.set(None);
                         Box::new(|| {})
@@ -191,7 +398,9 @@ pub fn use_provider() -> Option<Result<ProviderStatus, ProviderError>> {
                             provider.clone(),
                             error.clone(),
                             chain_id,
+                            chain_changed,
                             accounts,
+                            connected,
                         ) {
                             Ok(cleanup) => {
                                 error.set(None);
@@ -205,7 +414,7 @@ pub fn use_provider() -> Option<Result<ProviderStatus, ProviderError>> {
                     }
                 }
             },
-            provider,
+            (provider, *disconnected),
         );
     }
 
@@ -214,18 +423,25 @@ pub fn use_provider() -> Option<Result<ProviderStatus, ProviderError>> {
         let error = error.clone();
         let chain_id = chain_id.clone();
         let accounts_setter = accounts.clone();
+        let network_warning = network_warning.clone();
 
         #[derive(PartialEq)]
         struct Deps {
             provider: Option<Rc<Provider>>,
             chain_id: Option<String>,
+            disconnected: bool,
         }
-        let deps = Deps { provider: Option::clone(&provider), chain_id: Option::clone(&chain_id) };
+        let deps = Deps {
+            provider: Option::clone(&provider),
+            chain_id: Option::clone(&chain_id),
+            disconnected: *disconnected,
+        };
 
         use_effect_with_deps(
             move |deps| {
-                let Deps { provider, chain_id: _ } = deps;
+                let Deps { provider, chain_id: _, disconnected } = deps;
                 match provider {
+                    Some(_) if *disconnected => {}
                     None => {}
                     Some(provider) => {
                         let provider = provider.clone();
@@ -239,6 +455,7 @@ pub fn use_provider() -> Option<Result<ProviderStatus, ProviderError>> {
                                     accounts_setter.set(None);
                                 }
                             };
+                            network_warning.set(provider.request_network_mismatch().await);
                         });
                     }
                 }
@@ -256,7 +473,37 @@ pub fn use_provider() -> Option<Result<ProviderStatus, ProviderError>> {
             provider: provider.clone(),
             chain_id: Option::clone(&chain_id),
             accounts: Option::clone(&accounts),
+            network_warning: Option::clone(&network_warning),
+            connected: *connected,
             requires_chain_info,
+            disconnected,
+            chain_id_setter: chain_id,
+            accounts_setter: accounts,
         })
     })
 }
+
+#[cfg(all(test, target_arch = "wasm32"))]
+mod wasm_tests {
+    use super::*;
+    use crate::provider::ErrorCodes;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    fn unknown_chain() -> RPCError {
+        RPCError { code: ErrorCodes::Other(4902), message: "unknown chain".to_owned(), data: None }
+    }
+
+    #[wasm_bindgen_test]
+    async fn it_unblocks_change_chain_when_cancelled() {
+        let (tx, mut rx) = mpsc::channel(1);
+        tx.try_send(false).unwrap();
+        assert_eq!(
+            wait_for_chain_info(&mut rx, unknown_chain()).await,
+            Err(ProviderError::UnknownChain(unknown_chain()))
+        );
+
+        let (tx, mut rx) = mpsc::channel(1);
+        tx.try_send(true).unwrap();
+        assert_eq!(wait_for_chain_info(&mut rx, unknown_chain()).await, Ok(()));
+    }
+}
@@ -1,4 +1,7 @@
-use crate::components::{label::Label, text_input::TextInput};
+use crate::{
+    components::{label::Label, text_input::TextInput, text_list_input::TextListInput},
+    helpers::utils::{non_empty_entries, ChainInfoErrors},
+};
 use ethereum_provider::yew::{ChainInfo, NativeCurrency as RNativeCurrency, ProviderStatus};
 use yew::prelude::*;
 
@@ -12,46 +15,50 @@ pub(crate) struct AddChainModalProps {
 pub(crate) fn add_chain_modal(props: &AddChainModalProps) -> Html {
     let loading = use_state(|| false);
     let error = use_state(|| None);
-    let chain_name = use_state(|| None);
-    let rpc_url = use_state(|| None);
-    let icon_url = use_state(|| None);
-    let nc_name = use_state(|| None);
-    let nc_symbol = use_state(|| None);
-    let nc_decimals = use_state(|| None);
-    let block_explorer_url = use_state(|| None);
+    // prefilled with what the signer knows about the chain, the user only has to confirm
+    let suggested = props.status.suggested_chain_info();
+    let chain_name = use_state(|| suggested.as_ref().and_then(|i| i.chain_name.clone()));
+    let rpc_urls = use_state(|| entries(suggested.as_ref().and_then(|i| i.rpc_urls.clone())));
+    let icon_urls = use_state(|| entries(suggested.as_ref().and_then(|i| i.icon_urls.clone())));
+    let native_currency = suggested.as_ref().and_then(|i| i.native_currency.clone());
+    let nc_name = use_state(|| native_currency.as_ref().map(|nc| nc.name.clone()));
+    let nc_symbol = use_state(|| native_currency.as_ref().map(|nc| nc.symbol.clone()));
+    let nc_decimals = use_state(|| native_currency.as_ref().map(|nc| nc.decimals.to_string()));
+    let block_explorer_urls =
+        use_state(|| entries(suggested.as_ref().and_then(|i| i.block_explorer_urls.clone())));
 
     let submit = {
         let loading = loading.clone();
         let error = error.clone();
         let chain_name = chain_name.clone();
-        let rpc_url = rpc_url.clone();
-        let icon_url = icon_url.clone();
+        let rpc_urls = rpc_urls.clone();
+        let icon_urls = icon_urls.clone();
         let nc_name = nc_name.clone();
         let nc_symbol = nc_symbol.clone();
         let nc_decimals = nc_decimals.clone();
-        let block_explorer_url = block_explorer_url.clone();
+        let block_explorer_urls = block_explorer_urls.clone();
 
         #[derive(PartialEq, Clone)]
         struct Deps {
             status: ProviderStatus,
             chain_name: UseStateHandle<Option<String>>,
-            rpc_url: UseStateHandle<Option<String>>,
-            icon_url: UseStateHandle<Option<String>>,
+            rpc_urls: UseStateHandle<Vec<String>>,
+            icon_urls: UseStateHandle<Vec<String>>,
             nc_name: UseStateHandle<Option<String>>,
             nc_symbol: UseStateHandle<Option<String>>,
             nc_decimals: UseStateHandle<Option<String>>,
-            block_explorer_url: UseStateHandle<Option<String>>,
+            block_explorer_urls: UseStateHandle<Vec<String>>,
         }
 
         let deps = Deps {
             status: props.status.clone(),
             chain_name: chain_name.clone(),
-            rpc_url: rpc_url.clone(),
-            icon_url: icon_url.clone(),
+            rpc_urls: rpc_urls.clone(),
+            icon_urls: icon_urls.clone(),
             nc_name: nc_name.clone(),
             nc_symbol: nc_symbol.clone(),
             nc_decimals: nc_decimals.clone(),
-            block_explorer_url: block_explorer_url.clone(),
+            block_explorer_urls: block_explorer_urls.clone(),
         };
 
         use_callback(
@@ -64,36 +71,48 @@ pub(crate) fn add_chain_modal(props: &AddChainModalProps) -> Html {
                     let Deps {
                         status,
                         chain_name,
-                        rpc_url,
-                        icon_url,
+                        rpc_urls,
+                        icon_urls,
                         nc_name,
                         nc_symbol,
                         nc_decimals,
-                        block_explorer_url,
+                        block_explorer_urls,
                     } = deps;
+                    // the submit button is disabled, but the form can still be submitted with Enter
+                    if !validate(
+                        &chain_name,
+                        &rpc_urls,
+                        &icon_urls,
+                        &nc_decimals,
+                        &block_explorer_urls,
+                    )
+                    .is_valid()
+                    {
+                        return
+                    }
                     loading.set(true);
 
                     let mut native_currency = None;
-                    if let (Some(nc_name), Some(nc_symbol), Some(nc_decimals)) = (
+                    if let (Some(nc_name), Some(nc_symbol), Some(Ok(nc_decimals))) = (
                         Option::clone(&nc_name),
                         Option::clone(&nc_symbol),
-                        Option::clone(&nc_decimals),
+                        nc_decimals.as_ref().map(|d| d.trim().parse()),
                     ) {
                         native_currency = Some(RNativeCurrency {
                             name: nc_name,
                             symbol: nc_symbol,
-                            decimals: nc_decimals.parse().unwrap_or(0),
+                            decimals: nc_decimals,
                         });
                     }
 
                     match status
                         .provide_chain_info(ChainInfo {
                             chain_name: Option::clone(&chain_name),
-                            rpc_urls: Option::clone(&rpc_url).map(|v| vec![v]),
-                            icon_urls: Option::clone(&icon_url).map(|v| vec![v]),
+                            rpc_urls: non_empty_entries(&rpc_urls),
+                            icon_urls: non_empty_entries(&icon_urls),
                             native_currency,
-                            block_explorer_urls: Option::clone(&block_explorer_url)
-                                .map(|v| vec![v]),
+                            block_explorer_urls: non_empty_entries(&block_explorer_urls),
+                            testnet: None,
                         })
                         .await
                     {
@@ -112,7 +131,19 @@ pub(crate) fn add_chain_modal(props: &AddChainModalProps) -> Html {
         )
     };
 
-    // FIXME: no validation before submitting
+    let cancel = {
+        let error = error.clone();
+        use_callback(
+            move |_: MouseEvent, status: &ProviderStatus| {
+                if let Err(e) = status.cancel_chain_info() {
+                    error.set(Some(e));
+                }
+            },
+            props.status.clone(),
+        )
+    };
+
+    let errors = validate(&chain_name, &rpc_urls, &icon_urls, &nc_decimals, &block_explorer_urls);
 
     html! {
       <>
@@ -129,19 +160,44 @@ pub(crate) fn add_chain_modal(props: &AddChainModalProps) -> Html {
           </div>
           <form onsubmit={submit} style="display: flex; flex-direction: column; flex-grow: 1;">
             <div style="flex-grow: 1; margin: 20px 0;">
-              <TextInput id="chain_name" label="Chain name" placeholder="CoolChain" state={chain_name.clone()} />
-              <TextInput id="rpc_url" label="RPC URL" placeholder="https://website/api/rpc" state={rpc_url.clone()} />
-              <TextInput id="icon_url" label="Icon URL" placeholder="https://website/icon.png" state={icon_url.clone()} />
+              <TextInput id="chain_name" label="Chain name" placeholder="CoolChain" state={chain_name.clone()} error={errors.chain_name.clone()} />
+              <TextListInput id="rpc_url" label="RPC URL" placeholder="https://website/api/rpc" state={rpc_urls.clone()} error={errors.rpc_urls.clone()} />
+              <TextListInput id="icon_url" label="Icon URL" placeholder="https://website/icon.png" state={icon_urls.clone()} error={errors.icon_urls.clone()} />
               <TextInput id="nc_name" label="Native currency name" placeholder="CoolCoin" state={nc_name.clone()} />
               <TextInput id="nc_symbol" label="Native currency symbol" placeholder="CC" state={nc_symbol.clone()} />
-              <TextInput id="nc_decimals" label="Native currency decimals" placeholder="18" state={nc_decimals.clone()} />
-              <TextInput id="block_explorer_url" label="Block explorer URL" placeholder="https://website/block/{block}" state={block_explorer_url.clone()} />
+              <TextInput id="nc_decimals" label="Native currency decimals" placeholder="18" state={nc_decimals.clone()} error={errors.nc_decimals.clone()} />
+              <TextListInput id="block_explorer_url" label="Block explorer URL" placeholder="https://website/block/{block}" state={block_explorer_urls.clone()} error={errors.block_explorer_urls.clone()} />
             </div>
             <div>
-              <button type="submit" disabled={*loading}><code>{if *loading { "Loading" } else { "Add"}}</code></button>
+              <button type="submit" disabled={*loading || !errors.is_valid()}><code>{if *loading { "Loading" } else { "Add"}}</code></button>
+              <button type="button" onclick={cancel} disabled={*loading}><code>{"Cancel"}</code></button>
             </div>
           </form>
         </dialog>
       </>
     }
 }
+
+fn validate(
+    chain_name: &UseStateHandle<Option<String>>,
+    rpc_urls: &UseStateHandle<Vec<String>>,
+    icon_urls: &UseStateHandle<Vec<String>>,
+    nc_decimals: &UseStateHandle<Option<String>>,
+    block_explorer_urls: &UseStateHandle<Vec<String>>,
+) -> ChainInfoErrors {
+    ChainInfoErrors::new(
+        chain_name.as_deref(),
+        rpc_urls,
+        icon_urls,
+        nc_decimals.as_deref(),
+        block_explorer_urls,
+    )
+}
+
+/// The initial entries of a `TextListInput`, which always has at least one
+fn entries(values: Option<Vec<String>>) -> Vec<String> {
+    match values {
+        Some(values) if !values.is_empty() => values,
+        _ => vec!["".to_string()],
+    }
+}
@@ -1,4 +1,7 @@
 pub(crate) mod add_chain_modal;
 pub(crate) mod label;
+pub(crate) mod request_review;
 pub(crate) mod text_input;
+pub(crate) mod text_list_input;
 pub(crate) mod wallet_status;
+pub(crate) mod warning;
@@ -0,0 +1,39 @@
+use crate::components::{label::Label, warning::Warning};
+use yew::prelude::*;
+
+#[derive(Properties, PartialEq)]
+pub(crate) struct RequestReviewProps {
+    pub title: String,
+    pub fields: Vec<(String, String)>,
+    /// The gas estimate of a transaction or the reason it failed, `None` while estimating or
+    /// when there is nothing to estimate
+    pub gas_estimate: Option<Result<String, String>>,
+    pub on_approve: Callback<()>,
+    pub on_reject: Callback<()>,
+}
+
+/// A signing request waiting for the user, it only reaches the wallet once approved
+#[function_component(RequestReview)]
+pub(crate) fn request_review(props: &RequestReviewProps) -> Html {
+    let on_approve = props.on_approve.reform(|_: MouseEvent| ());
+    let on_reject = props.on_reject.reform(|_: MouseEvent| ());
+
+    html! {
+      <div style="border: 2px solid steelblue; padding: 10px; margin-bottom: 10px;">
+        <h2 style="margin-top: 0;">{ props.title.clone() }</h2>
+        { for props.fields.iter().map(|(name, value)| html! {
+          <Label name={name.clone()} value={value.clone()} />
+        }) }
+        { match &props.gas_estimate {
+          Some(Ok(gas)) => html! { <Label name="Estimated gas" value={gas.clone()} /> },
+          Some(Err(e)) => html! {
+            <Warning message={format!("gas estimation failed, the transaction will likely fail: {}", e)} />
+          },
+          None => html! {},
+        } }
+        <button onclick={on_approve}>{ "Approve" }</button>
+        { " " }
+        <button onclick={on_reject}>{ "Reject" }</button>
+      </div>
+    }
+}
@@ -8,6 +8,9 @@ pub(crate) struct TextInputProps {
     pub label: String,
     pub placeholder: String,
     pub state: UseStateHandle<Option<String>>,
+    /// Shown under the input when its value is invalid
+    #[prop_or_default]
+    pub error: Option<String>,
 }
 
 #[function_component(TextInput)]
@@ -29,6 +32,7 @@ pub(crate) fn text_input(props: &TextInputProps) -> Html {
     };
 
     html! {
+      <>
       <div style="margin: 7px 0; display: flex;">
         <label for={props.id.clone()}>
           <strong><code>{props.label.clone()}{": "}</code></strong>
@@ -41,5 +45,9 @@ pub(crate) fn text_input(props: &TextInputProps) -> Html {
           value={Option::clone(&props.state).unwrap_or("".to_string())}
           onchange={callback} />
       </div>
+      if let Some(error) = props.error.clone() {
+        <div style="margin: -5px 0 7px 0; color: darkred;"><code>{error}</code></div>
+      }
+      </>
     }
 }
@@ -0,0 +1,78 @@
+use wasm_bindgen::JsCast;
+use web_sys::HtmlInputElement;
+use yew::prelude::*;
+
+#[derive(Properties, PartialEq)]
+pub(crate) struct TextListInputProps {
+    pub id: String,
+    pub label: String,
+    pub placeholder: String,
+    /// One entry per input, empty ones included, there is always at least one
+    pub state: UseStateHandle<Vec<String>>,
+    /// Shown under the inputs when one of the values is invalid
+    #[prop_or_default]
+    pub error: Option<String>,
+}
+
+/// Like `TextInput`, with buttons to add and remove entries
+#[function_component(TextListInput)]
+pub(crate) fn text_list_input(props: &TextListInputProps) -> Html {
+    // recreated when the entries change, otherwise it would append to stale ones
+    let add = use_callback(
+        move |_: MouseEvent, state: &UseStateHandle<Vec<String>>| {
+            let mut entries = Vec::clone(state);
+            entries.push("".to_string());
+            state.set(entries);
+        },
+        props.state.clone(),
+    );
+
+    let entries = props.state.iter().enumerate().map(|(index, value)| {
+        let onchange = {
+            let state = props.state.clone();
+            Callback::from(move |e: Event| {
+                let input = e.target().and_then(|t| t.dyn_into::<HtmlInputElement>().ok());
+                if let Some(input) = input {
+                    let mut entries = Vec::clone(&state);
+                    entries[index] = input.value();
+                    state.set(entries);
+                }
+            })
+        };
+        let remove = {
+            let state = props.state.clone();
+            Callback::from(move |_: MouseEvent| {
+                let mut entries = Vec::clone(&state);
+                entries.remove(index);
+                state.set(entries);
+            })
+        };
+        let id = format!("{}_{}", props.id, index);
+
+        html! {
+          <div style="margin: 7px 0; display: flex;">
+            <label for={id.clone()}>
+              <strong><code>{props.label.clone()}{": "}</code></strong>
+            </label>
+            <input
+              style="font-family: monospace; margin-left: 7px; flex-grow: 1;"
+              id={id}
+              type="text"
+              placeholder={props.placeholder.clone()}
+              value={value.clone()}
+              {onchange} />
+            <button type="button" onclick={remove} disabled={props.state.len() <= 1}><code>{"-"}</code></button>
+          </div>
+        }
+    });
+
+    html! {
+      <>
+        { for entries }
+        if let Some(error) = props.error.clone() {
+          <div style="margin: -5px 0 7px 0; color: darkred;"><code>{error}</code></div>
+        }
+        <button type="button" onclick={add}><code>{format!("+ {}", props.label)}</code></button>
+      </>
+    }
+}
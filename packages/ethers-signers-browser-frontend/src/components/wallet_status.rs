@@ -1,14 +1,149 @@
-use crate::components::{add_chain_modal::AddChainModal, label::Label};
+use crate::{
+    components::{add_chain_modal::AddChainModal, label::Label, warning::Warning},
+    helpers::ethers::format_balance,
+};
 use ethereum_provider::{yew::ProviderStatus, ProviderError};
 use yew::prelude::*;
 
 fn get_wallet_name(status: &ProviderStatus) -> String {
-    if status.provider._is_coinbase_wallet.unwrap_or(false) {
-        "CoinBase Wallet".to_owned()
-    } else if status.provider._is_meta_mask.unwrap_or(false) {
-        "MetaMask".to_owned()
-    } else {
-        "Unknown".to_owned()
+    status.provider.wallet_name().unwrap_or("Unknown").to_owned()
+}
+
+fn multiple_providers_warning(status: &ProviderStatus) -> Option<String> {
+    status.provider.multiple_providers().map(|multiple| {
+        let wallets = multiple
+            .wallets
+            .into_iter()
+            .map(|name| name.unwrap_or("Unknown".to_owned()))
+            .collect::<Vec<_>>();
+        format!(
+            "Several wallets are installed ({}), requests might not reach the one you expect. \
+             Disable the other extensions if the wrong wallet answers.",
+            wallets.join(", ")
+        )
+    })
+}
+
+#[derive(Properties, PartialEq)]
+struct SwitchAccountProps {
+    status: ProviderStatus,
+}
+
+#[function_component(SwitchAccount)]
+fn switch_account(props: &SwitchAccountProps) -> Html {
+    let loading = use_state(|| false);
+    let error = use_state(|| None);
+
+    let onclick = {
+        let loading = loading.clone();
+        let error = error.clone();
+        use_callback(
+            move |_: MouseEvent, status: &ProviderStatus| {
+                let loading = loading.clone();
+                let error = error.clone();
+                let status = status.clone();
+                wasm_bindgen_futures::spawn_local(async move {
+                    loading.set(true);
+                    // the new accounts are picked up through the `accountsChanged` event
+                    let res = if status.is_disconnected() {
+                        status.connect().await
+                    } else {
+                        status.reconnect().await
+                    };
+                    match res {
+                        Ok(_) => error.set(None),
+                        Err(e) => error.set(Some(format!("{}", e))),
+                    }
+                    loading.set(false);
+                });
+            },
+            props.status.clone(),
+        )
+    };
+
+    html! {
+      <>
+        <button {onclick} disabled={*loading}>
+          { if props.status.is_disconnected() { "Connect" } else { "Switch account" } }
+        </button>
+        if let Some(error) = Option::clone(&error) {
+          <Label name="Error" value={error} />
+        }
+      </>
+    }
+}
+
+#[derive(Properties, PartialEq)]
+struct DisconnectProps {
+    status: ProviderStatus,
+}
+
+/// Forgets the wallet's accounts (and revokes the page's access when the wallet supports it),
+/// e.g. before leaving a shared machine
+#[function_component(Disconnect)]
+fn disconnect(props: &DisconnectProps) -> Html {
+    let error = use_state(|| None);
+
+    let onclick = {
+        let error = error.clone();
+        use_callback(
+            move |_: MouseEvent, status: &ProviderStatus| {
+                let error = error.clone();
+                let status = status.clone();
+                wasm_bindgen_futures::spawn_local(async move {
+                    match status.disconnect().await {
+                        Ok(_) => error.set(None),
+                        Err(e) => error.set(Some(format!("{}", e))),
+                    }
+                });
+            },
+            props.status.clone(),
+        )
+    };
+
+    html! {
+      <>
+        <button {onclick}>{ "Disconnect" }</button>
+        if let Some(error) = Option::clone(&error) {
+          <Label name="Error" value={error} />
+        }
+      </>
+    }
+}
+
+#[derive(Properties, PartialEq)]
+struct BalanceProps {
+    status: ProviderStatus,
+    address: String,
+}
+
+/// The balance of `address`, fetched again whenever the wallet status changes (e.g. its chain)
+#[function_component(Balance)]
+fn balance(props: &BalanceProps) -> Html {
+    let balance = use_state(|| None);
+
+    {
+        let balance = balance.clone();
+        use_effect_with_deps(
+            move |(status, address)| {
+                let status = status.clone();
+                let address = address.clone();
+                wasm_bindgen_futures::spawn_local(async move {
+                    let res = match status.provider.request_balance(address, None).await {
+                        Ok(wei) => format_balance(&wei),
+                        Err(e) => Err(format!("{}", e)),
+                    };
+                    balance.set(Some(res));
+                });
+            },
+            (props.status.clone(), props.address.clone()),
+        );
+    }
+
+    match Option::clone(&balance) {
+        Some(Ok(balance)) => html! { <Label name="Balance" value={balance} /> },
+        Some(Err(e)) => html! { <Label name="Balance" value={format!("unknown ({})", e)} /> },
+        None => html! {},
     }
 }
 
@@ -23,9 +158,25 @@ pub(crate) fn wallet_status(props: &WalletStatusProps) -> Html {
         Some(status) => match status {
             Ok(status) => html! {
               <>
+                if let Some(message) = multiple_providers_warning(&status) {
+                  <Warning message={message} />
+                }
+                if let Some(message) = status.network_warning.clone() {
+                  <Warning message={message} />
+                }
+                if status.connected == Some(false) {
+                  <Warning message={"The wallet lost the connection to its node".to_string()} />
+                }
                 <Label name="Wallet" value={ get_wallet_name(&status) } />
                 <Label name="Chain ID" value={status.clone().chain_id.unwrap_or("unknown".to_string())} />
                 <Label name="Accounts" value={status.clone().accounts.map_or("unknown".to_string(), |a| a.join(", "))} />
+                if let Some(address) = status.accounts.as_ref().and_then(|a| a.first().cloned()) {
+                  <Balance status={status.clone()} address={address} />
+                }
+                <SwitchAccount status={status.clone()} />
+                if !status.is_disconnected() {
+                  <Disconnect status={status.clone()} />
+                }
                 if let Some(chain_id) = status.clone().requires_chain_info() {
                   <AddChainModal chain_id={chain_id} status={status} />
                 }
@@ -0,0 +1,17 @@
+use yew::prelude::*;
+
+#[derive(Properties, PartialEq)]
+pub(crate) struct WarningProps {
+    pub message: String,
+}
+
+#[function_component(Warning)]
+pub(crate) fn warning(props: &WarningProps) -> Html {
+    html! {
+        <pre style="border: 2px solid darkred; background: mistyrose; padding: 10px;">
+          <strong style="color: darkred;">{"Warning"}</strong>
+          {": "}
+          <span style="text-wrap: wrap;">{props.message.clone()}</span>
+        </pre>
+    }
+}
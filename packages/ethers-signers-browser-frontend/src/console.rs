@@ -4,6 +4,8 @@ use wasm_bindgen::prelude::wasm_bindgen;
 extern "C" {
     #[wasm_bindgen(js_namespace = console)]
     pub(crate) fn error(s: &str);
+    #[wasm_bindgen(js_namespace = console)]
+    pub(crate) fn log(s: &str);
 }
 
 #[allow(unused_macros)]
@@ -12,3 +14,10 @@ macro_rules! console_error {
 }
 #[allow(unused_imports)]
 pub(crate) use console_error;
+
+#[allow(unused_macros)]
+macro_rules! console_log {
+    ($($t:tt)*) => (crate::console::log(&format_args!($($t)*).to_string()))
+}
+#[allow(unused_imports)]
+pub(crate) use console_log;
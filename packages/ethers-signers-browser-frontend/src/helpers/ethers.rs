@@ -1,20 +1,102 @@
-use ethereum_provider::provider::Transaction;
+use crate::ws::messages::{RequestContent, TypedDataVersion};
+use ethereum_provider::{address::is_valid_address, provider::Transaction};
 use ethers::{
     abi::Address,
-    types::{transaction::eip2718::TypedTransaction, TransactionRequest},
+    types::{
+        transaction::{eip2718::TypedTransaction, eip712::TypedData},
+        TransactionRequest, U256,
+    },
+    utils::{format_ether, to_checksum},
 };
+use serde_json::{json, Value};
+use std::str::FromStr;
+
+// keccak256("approve(address,uint256)")[..4]
+const APPROVE_SELECTOR: [u8; 4] = [0x09, 0x5e, 0xa7, 0xb3];
+const DEFAULT_APPROVAL_WARNING_THRESHOLD: U256 =
+    U256([u64::MAX, u64::MAX, u64::MAX, u64::MAX >> 1]);
+/// The methods a `RawRequest` can use without being reviewed (along with every `eth_get*`), any
+/// other one might change something (e.g. `wallet_addEthereumChain`, `eth_sendTransaction`)
+const READ_ONLY_METHODS: [&str; 9] = [
+    "eth_accounts",
+    "eth_blockNumber",
+    "eth_call",
+    "eth_chainId",
+    "eth_estimateGas",
+    "eth_feeHistory",
+    "eth_gasPrice",
+    "eth_maxPriorityFeePerGas",
+    "net_version",
+];
 
 pub(crate) fn address_to_string(address: Address) -> String {
     format!("{:#x}", address)
 }
 
+/// Converts EIP-712 typed data to the `{ type, name, value }` array of `eth_signTypedData` (v1),
+/// which only supports the fields of the primary type with atomic types
+pub(crate) fn to_legacy_typed_data(data: &TypedData) -> Result<Vec<Value>, String> {
+    let fields = data
+        .types
+        .get(&data.primary_type)
+        .ok_or_else(|| format!("primary type `{}` is not defined", data.primary_type))?;
+    fields
+        .iter()
+        .map(|field| {
+            if data.types.contains_key(field.r#type.trim_end_matches("[]")) {
+                return Err(format!(
+                    "field `{}` is a struct (`{}`), which v1 doesn't support",
+                    field.name, field.r#type
+                ))
+            }
+            let value = data
+                .message
+                .get(&field.name)
+                .ok_or_else(|| format!("missing field `{}`", field.name))?;
+            Ok(json!({ "type": field.r#type, "name": field.name, "value": value }))
+        })
+        .collect()
+}
+
+/// Parses a chain ID as returned by the wallet (e.g. `0x5`)
+pub(crate) fn parse_chain_id(chain_id: &str) -> Option<u64> {
+    u64::from_str_radix(chain_id.strip_prefix("0x").unwrap_or(chain_id), 16).ok()
+}
+
+/// Parses an estimate returned by the wallet's node as a hex quantity (e.g. `0x5208`)
+pub(crate) fn parse_estimate(estimate: &str) -> Result<u64, String> {
+    u64::from_str_radix(estimate.strip_prefix("0x").unwrap_or(estimate), 16)
+        .map_err(|e| format!("invalid estimate `{}`: {}", estimate, e))
+}
+
+/// Adds `percent` to an estimate of the wallet's node
+pub(crate) fn bump_estimate(estimate: u64, percent: u64) -> u64 {
+    estimate.saturating_add(estimate.saturating_mul(percent) / 100)
+}
+
+/// Formats a balance in wei as ether (e.g. `1.500000000000000000`)
+pub(crate) fn format_balance(wei: &impl ToString) -> Result<String, String> {
+    let wei = wei.to_string();
+    let wei = U256::from_dec_str(&wei).map_err(|e| format!("invalid balance `{}`: {}", wei, e))?;
+    Ok(format_ether(wei))
+}
+
+/// Parses an address returned by the wallet, the `bool` is false when the address is mixed-case
+/// but doesn't match its EIP-55 checksum (lowercase or uppercase addresses carry no checksum)
+pub(crate) fn parse_wallet_address(address: &str) -> Result<(Address, bool), String> {
+    let parsed = Address::from_str(address).map_err(|e| format!("{}", e))?;
+    Ok((parsed, is_valid_address(address)))
+}
+
 fn transform_legacy_transaction(
     transaction: TransactionRequest,
 ) -> Result<(Option<u64>, Transaction), String> {
     Ok((
         transaction.chain_id.map(|chain_id| chain_id.as_u64()),
         Transaction {
-            from: transaction.from.map(address_to_string).ok_or_else(|| "missing from address")?,
+            from: Some(
+                transaction.from.map(address_to_string).ok_or_else(|| "missing from address")?,
+            ),
             to: transaction
                 .to
                 .map(|v| match v {
@@ -39,10 +121,12 @@ pub(crate) fn transform_transaction(
         TypedTransaction::Eip1559(transaction) => (
             transaction.chain_id.map(|chain_id| chain_id.as_u64()),
             Transaction {
-                from: transaction
-                    .from
-                    .map(address_to_string)
-                    .ok_or_else(|| "missing from address")?,
+                from: Some(
+                    transaction
+                        .from
+                        .map(address_to_string)
+                        .ok_or_else(|| "missing from address")?,
+                ),
                 to: transaction
                     .to
                     .map(|v| match v {
@@ -65,3 +149,237 @@ pub(crate) fn transform_transaction(
         TypedTransaction::Eip2930(transaction) => transform_legacy_transaction(transaction.tx)?,
     })
 }
+
+fn decode_approval(transaction: &TypedTransaction) -> Option<(Address, U256)> {
+    let data = transaction.data()?;
+    if data.len() != 4 + 32 * 2 || data[..4] != APPROVE_SELECTOR {
+        return None
+    }
+    Some((Address::from_slice(&data[16..36]), U256::from_big_endian(&data[36..68])))
+}
+
+/// Returns a warning if the transaction is an ERC-20 `approve` with an allowance at or above the
+/// threshold
+pub(crate) fn large_approval_warning(
+    transaction: &TypedTransaction,
+    threshold: Option<U256>,
+) -> Option<String> {
+    let (spender, amount) = decode_approval(transaction)?;
+    if amount < threshold.unwrap_or(DEFAULT_APPROVAL_WARNING_THRESHOLD) {
+        return None
+    }
+    let token = transaction.to().map_or("unknown token".to_string(), |to| match to {
+        ethers::types::NameOrAddress::Address(address) => address_to_string(*address),
+        ethers::types::NameOrAddress::Name(name) => name.clone(),
+    });
+    let amount =
+        if amount == U256::MAX { "an unlimited".to_string() } else { format!("a {}", amount) };
+    Some(format!(
+        "this transaction grants {} allowance on {} to {}, only proceed if you trust this spender",
+        amount,
+        token,
+        address_to_string(spender),
+    ))
+}
+
+fn describe_transaction(transaction: &TypedTransaction) -> Vec<(String, String)> {
+    let mut fields = vec![];
+    if let Some(from) = transaction.from() {
+        fields.push(("From".to_owned(), to_checksum(from, None)));
+    }
+    let to = transaction.to().map_or("none (contract creation)".to_owned(), |to| match to {
+        ethers::types::NameOrAddress::Address(address) => to_checksum(address, None),
+        ethers::types::NameOrAddress::Name(name) => name.clone(),
+    });
+    fields.push(("To".to_owned(), to));
+    let value = transaction.value().copied().unwrap_or_default();
+    fields.push(("Value".to_owned(), format!("{} ETH", format_ether(value))));
+    if let Some(data) = transaction.data() {
+        fields.push(("Data".to_owned(), data.to_string()));
+    }
+    if let Some(chain_id) = transaction.chain_id() {
+        fields.push(("Chain".to_owned(), chain_id.to_string()));
+    }
+    fields
+}
+
+fn is_read_only(method: &str) -> bool {
+    method.starts_with("eth_get") || READ_ONLY_METHODS.contains(&method)
+}
+
+/// A title and the fields shown to the user before a signing request reaches the wallet, `None`
+/// for requests which don't need their approval
+pub(crate) fn describe_request(
+    content: &RequestContent,
+) -> Option<(String, Vec<(String, String)>)> {
+    let account = |address: &Address| ("Account".to_owned(), to_checksum(address, None));
+    Some(match content {
+        RequestContent::SignTextMessage { address, message } => (
+            "Sign a message".to_owned(),
+            vec![account(address), ("Message".to_owned(), message.clone())],
+        ),
+        RequestContent::SignBinaryMessage { address, message, original } => {
+            let mut fields = vec![account(address), ("Hash".to_owned(), format!("{:?}", message))];
+            if let Some(original) = original {
+                fields.push(("Hashed message".to_owned(), original.to_string()));
+            }
+            ("Sign a hash".to_owned(), fields)
+        }
+        RequestContent::SignTransaction { transaction } => {
+            ("Sign a transaction".to_owned(), describe_transaction(transaction))
+        }
+        RequestContent::SendTransaction { transaction } => {
+            ("Send a transaction".to_owned(), describe_transaction(transaction))
+        }
+        RequestContent::SignTypedData { address, typed_data, version } => {
+            let version = match version {
+                TypedDataVersion::V1 => "v1",
+                TypedDataVersion::V3 => "v3",
+                TypedDataVersion::V4 => "v4",
+            };
+            let mut fields = vec![account(address)];
+            if let Some(name) = &typed_data.domain.name {
+                fields.push(("Domain".to_owned(), name.clone()));
+            }
+            fields.push(("Type".to_owned(), typed_data.primary_type.clone()));
+            fields.push((
+                "Data".to_owned(),
+                serde_json::to_string_pretty(&typed_data.message).unwrap_or_default(),
+            ));
+            (format!("Sign typed data ({})", version), fields)
+        }
+        RequestContent::RawRequest { method, params } if !is_read_only(method) => (
+            "Send a request".to_owned(),
+            vec![
+                ("Method".to_owned(), method.clone()),
+                ("Params".to_owned(), serde_json::to_string_pretty(params).unwrap_or_default()),
+            ],
+        ),
+        RequestContent::Init { .. } |
+        RequestContent::Accounts {} |
+        RequestContent::Call { .. } |
+        RequestContent::RawRequest { .. } |
+        RequestContent::Pong {} |
+        RequestContent::Cancel {} => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_describes_signing_requests() {
+        let address = Address::from_str("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed").unwrap();
+        assert!(describe_request(&RequestContent::Accounts {}).is_none());
+
+        let (title, fields) = describe_request(&RequestContent::SignTextMessage {
+            address,
+            message: "hello".to_owned(),
+        })
+        .unwrap();
+        assert_eq!(title, "Sign a message");
+        assert_eq!(fields[1], ("Message".to_owned(), "hello".to_owned()));
+
+        let transaction = TypedTransaction::Legacy(
+            TransactionRequest::new().to(address).value(U256::exp10(18)).chain_id(5),
+        );
+        let (title, fields) =
+            describe_request(&RequestContent::SendTransaction { transaction }).unwrap();
+        assert_eq!(title, "Send a transaction");
+        assert_eq!(
+            fields,
+            vec![
+                ("To".to_owned(), "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed".to_owned()),
+                ("Value".to_owned(), "1.000000000000000000 ETH".to_owned()),
+                ("Chain".to_owned(), "5".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_only_lets_read_only_raw_requests_through() {
+        let raw =
+            |method: &str, params| RequestContent::RawRequest { method: method.to_owned(), params };
+        for method in ["eth_getBalance", "eth_call", "eth_chainId", "net_version"] {
+            assert!(describe_request(&raw(method, json!([]))).is_none(), "{}", method);
+        }
+
+        let params = json!([{ "chainId": "0x2a" }]);
+        let (title, fields) = describe_request(&raw("wallet_addEthereumChain", params)).unwrap();
+        assert_eq!(title, "Send a request");
+        assert_eq!(
+            fields,
+            vec![
+                ("Method".to_owned(), "wallet_addEthereumChain".to_owned()),
+                ("Params".to_owned(), "[\n  {\n    \"chainId\": \"0x2a\"\n  }\n]".to_owned()),
+            ]
+        );
+        assert!(describe_request(&raw("eth_sendTransaction", json!([]))).is_some());
+    }
+
+    #[test]
+    fn it_formats_balances() {
+        assert_eq!(format_balance(&"1500000000000000000"), Ok("1.500000000000000000".to_owned()));
+        assert_eq!(format_balance(&0), Ok("0.000000000000000000".to_owned()));
+        assert!(format_balance(&"-1").is_err());
+    }
+
+    #[test]
+    fn it_bumps_estimates() {
+        assert_eq!(parse_estimate("0x5208"), Ok(21_000));
+        assert!(parse_estimate("gas").is_err());
+        assert_eq!(bump_estimate(21_000, 20), 25_200);
+        assert_eq!(bump_estimate(21_000, 0), 21_000);
+        assert_eq!(bump_estimate(u64::MAX, 10), u64::MAX);
+    }
+
+    #[test]
+    fn it_converts_typed_data_to_the_legacy_format() {
+        let data = |message_type: &str| -> TypedData {
+            serde_json::from_value(json!({
+                "types": {
+                    "EIP712Domain": [],
+                    "Person": [{ "name": "name", "type": "string" }],
+                    "Mail": [
+                        { "name": "contents", "type": "string" },
+                        { "name": "from", "type": message_type },
+                    ],
+                },
+                "primaryType": "Mail",
+                "domain": {},
+                "message": { "contents": "hello", "from": "0x1234" },
+            }))
+            .unwrap()
+        };
+
+        assert_eq!(
+            to_legacy_typed_data(&data("bytes")),
+            Ok(vec![
+                json!({ "type": "string", "name": "contents", "value": "hello" }),
+                json!({ "type": "bytes", "name": "from", "value": "0x1234" }),
+            ])
+        );
+        assert!(to_legacy_typed_data(&data("Person")).unwrap_err().contains("struct"));
+    }
+
+    #[test]
+    fn it_parses_chain_ids() {
+        assert_eq!(parse_chain_id("0x5"), Some(5));
+        assert_eq!(parse_chain_id("0xaa36a7"), Some(11155111));
+        assert_eq!(parse_chain_id("nope"), None);
+    }
+
+    #[test]
+    fn it_verifies_address_checksums() {
+        let checksummed = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+        let address = Address::from_str(checksummed).unwrap();
+        assert_eq!(parse_wallet_address(checksummed), Ok((address, true)));
+        assert_eq!(parse_wallet_address(&checksummed.to_lowercase()), Ok((address, true)));
+        assert_eq!(
+            parse_wallet_address("0x5AAeb6053F3E94C9b9A09f33669435E7Ef1BeAed"),
+            Ok((address, false))
+        );
+        assert!(parse_wallet_address("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeA").is_err());
+    }
+}
@@ -1,20 +1,370 @@
-use crate::{hooks::use_ws::WSState, ws::WebsocketStatus};
-
-pub(crate) fn get_ws_status(ws: WSState) -> String {
-    match ws.status {
-        None => "connecting...".to_owned(),
-        Some(status) => {
-            match status {
-                Ok(status) => match status {
-                    WebsocketStatus::Connected => "connected".to_owned(),
-                    WebsocketStatus::Pending => "connecting...".to_owned(),
-                    WebsocketStatus::Disconnected(_e) => {
-                        format!("disconnected (check that the command is still running), reconnecting...")
-                    }
-                    WebsocketStatus::Error(e) => format!("error ({})", e),
-                },
-                Err(e) => format!("error ({})", e),
+use crate::{
+    hooks::use_ws::{ServerClose, WSState},
+    ws::{messages, CloseEvent, WebsocketStatus},
+};
+use ethereum_provider::{yew::ProviderStatus, ProviderError};
+use std::fmt;
+
+/// The websocket and wallet statuses combined, requests can only be signed when `Ready`
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum ConnectionState {
+    /// The connection was lost without the server shutting down, the `attempt`-th reconnection
+    /// is scheduled (0 until the first one is)
+    Disconnected {
+        attempt: u32,
+    },
+    Connecting,
+    /// Connected to the server, but the wallet is missing or didn't share any account yet
+    WaitingForWallet,
+    Ready,
+    /// The server shut down cleanly, the command is done
+    Finished,
+    /// The server only talks to one tab at a time and another one has the connection, retrying
+    ConnectedElsewhere,
+    /// Another tab took the connection over
+    Replaced,
+    Error(String),
+}
+
+impl ConnectionState {
+    pub(crate) fn new(
+        ws: &WSState,
+        wallet: &Option<Result<ProviderStatus, ProviderError>>,
+    ) -> Self {
+        let wallet = wallet.as_ref().map(|wallet| match wallet {
+            Ok(status) => Ok(status.accounts.as_ref().map_or(false, |a| !a.is_empty())),
+            Err(e) => Err(e.to_string()),
+        });
+        Self::from_parts(ws.status.as_ref(), ws.closed, ws.reconnect_attempts, wallet)
+    }
+
+    /// `wallet` is `Some(Ok(true))` when the wallet shares at least one account, `closed` hides
+    /// the failing reconnections which follow a deliberate close
+    fn from_parts(
+        ws: Option<&Result<WebsocketStatus, String>>,
+        closed: Option<ServerClose>,
+        reconnect_attempts: u32,
+        wallet: Option<Result<bool, String>>,
+    ) -> Self {
+        match ws {
+            Some(Err(e)) => Self::Error(e.clone()),
+            Some(Ok(WebsocketStatus::Connected)) => match wallet {
+                Some(Ok(true)) => Self::Ready,
+                Some(Ok(false)) | None => Self::WaitingForWallet,
+                Some(Err(e)) => Self::Error(e),
+            },
+            _ if closed == Some(ServerClose::Finished) => Self::Finished,
+            _ if closed == Some(ServerClose::ConnectedElsewhere) => Self::ConnectedElsewhere,
+            _ if closed == Some(ServerClose::Replaced) => Self::Replaced,
+            Some(Ok(WebsocketStatus::Disconnected(event))) if is_clean_shutdown(event) => {
+                Self::Finished
+            }
+            None | Some(Ok(WebsocketStatus::Pending)) => Self::Connecting,
+            Some(Ok(WebsocketStatus::Disconnected(_))) => {
+                Self::Disconnected { attempt: reconnect_attempts }
             }
+            Some(Ok(WebsocketStatus::Error(e))) => Self::Error(e.clone()),
+        }
+    }
+}
+
+impl fmt::Display for ConnectionState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Disconnected { attempt: 0 } => write!(f, "connection lost, reconnecting..."),
+            Self::Disconnected { attempt } => write!(
+                f,
+                "connection lost, retrying in {}s (attempt {})...",
+                reconnect_delay(*attempt) / 1000,
+                attempt
+            ),
+            Self::Connecting => write!(f, "connecting..."),
+            Self::WaitingForWallet => write!(f, "waiting for the wallet to share an account"),
+            Self::Ready => write!(f, "ready"),
+            Self::Finished => write!(f, "the command finished, you can close this page"),
+            Self::ConnectedElsewhere => {
+                write!(
+                    f,
+                    "another tab is already connected, close it to use this one (retrying...)"
+                )
+            }
+            Self::Replaced => {
+                write!(f, "another tab took over, reload this page to use this one instead")
+            }
+            Self::Error(e) => write!(f, "error ({})", e),
+        }
+    }
+}
+
+/// Whether the server closed the connection because it is shutting down (i.e. the command
+/// finished) rather than it being lost (e.g. network blip, killed process, missed keepalive)
+pub(crate) fn is_clean_shutdown(event: &CloseEvent) -> bool {
+    const NORMAL_CLOSURE: u16 = 1000;
+    const GOING_AWAY: u16 = 1001;
+    event.was_clean && matches!(event.code, NORMAL_CLOSURE | GOING_AWAY)
+}
+
+/// How long to wait before the `attempt`-th reconnection (starting at 1) in milliseconds: 1s,
+/// 2s, 4s... up to 30s
+pub(crate) fn reconnect_delay(attempt: u32) -> u32 {
+    const FIRST_DELAY: u32 = 1_000;
+    const MAX_DELAY: u32 = 30_000;
+    (FIRST_DELAY << attempt.saturating_sub(1).min(5)).min(MAX_DELAY)
+}
+
+/// The directory of the page at `pathname` (e.g. `/wallet/` for `/wallet/index.html`), where the
+/// server's other routes are when no page config was injected
+pub(crate) fn base_path(pathname: &str) -> String {
+    match pathname.rfind('/') {
+        Some(end) => pathname[..=end].to_owned(),
+        None => "/".to_owned(),
+    }
+}
+
+/// The query of the websocket URL: the page's own `search` (which carries the nonce) plus the
+/// session to resume, if any
+pub(crate) fn ws_query(search: &str, session: Option<&str>) -> String {
+    match session {
+        None => search.to_owned(),
+        Some(session) if search.len() <= 1 => format!("?session={}", session),
+        Some(session) => format!("{}&session={}", search, session),
+    }
+}
+
+/// The field-level errors of the `AddChainModal` form, which can only be submitted once there are
+/// none, so the wallet doesn't reject the chain (or worse, accept a broken one)
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct ChainInfoErrors {
+    pub(crate) chain_name: Option<String>,
+    pub(crate) rpc_urls: Option<String>,
+    pub(crate) icon_urls: Option<String>,
+    pub(crate) nc_decimals: Option<String>,
+    pub(crate) block_explorer_urls: Option<String>,
+}
+
+impl ChainInfoErrors {
+    /// Checks the raw inputs, empty ones being `None` (or ignored in lists)
+    pub(crate) fn new(
+        chain_name: Option<&str>,
+        rpc_urls: &[String],
+        icon_urls: &[String],
+        nc_decimals: Option<&str>,
+        block_explorer_urls: &[String],
+    ) -> Self {
+        Self {
+            chain_name: match chain_name.map(str::trim) {
+                Some(name) if !name.is_empty() => None,
+                _ => Some("required".to_owned()),
+            },
+            rpc_urls: match non_empty_entries(rpc_urls) {
+                Some(urls) => check_urls(&urls),
+                None => Some("required".to_owned()),
+            },
+            icon_urls: non_empty_entries(icon_urls).and_then(|urls| check_urls(&urls)),
+            nc_decimals: nc_decimals.and_then(|decimals| match decimals.trim().parse::<u64>() {
+                Ok(_) => None,
+                Err(_) => Some("must be a whole number".to_owned()),
+            }),
+            block_explorer_urls: non_empty_entries(block_explorer_urls)
+                .and_then(|urls| check_urls(&urls)),
         }
     }
+
+    pub(crate) fn is_valid(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// The trimmed entries of a `TextListInput` which aren't empty, `None` if there are none
+pub(crate) fn non_empty_entries(entries: &[String]) -> Option<Vec<String>> {
+    let entries: Vec<_> = entries
+        .iter()
+        .map(|entry| entry.trim())
+        .filter(|entry| !entry.is_empty())
+        .map(str::to_owned)
+        .collect();
+    match entries.is_empty() {
+        true => None,
+        false => Some(entries),
+    }
+}
+
+/// The error of the first URL which isn't `http://` or `https://`
+fn check_urls(urls: &[String]) -> Option<String> {
+    urls.iter().find_map(|url| {
+        let lower = url.to_ascii_lowercase();
+        let valid = ["http://", "https://"]
+            .iter()
+            .any(|scheme| lower.len() > scheme.len() && lower.starts_with(scheme));
+        match valid {
+            true => None,
+            false => Some(format!("{} must be an http:// or https:// URL", url)),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_combines_websocket_and_wallet_statuses() {
+        let closed = WebsocketStatus::Disconnected(CloseEvent {
+            code: 1006,
+            reason: "".to_owned(),
+            was_clean: false,
+        });
+        let cases = [
+            (None, Some(Ok(true)), ConnectionState::Connecting),
+            (Some(Ok(WebsocketStatus::Pending)), Some(Ok(true)), ConnectionState::Connecting),
+            (Some(Ok(closed)), Some(Ok(true)), ConnectionState::Disconnected { attempt: 2 }),
+            (
+                Some(Ok(WebsocketStatus::Error("refused".to_owned()))),
+                Some(Ok(true)),
+                ConnectionState::Error("refused".to_owned()),
+            ),
+            (
+                Some(Err("no window".to_owned())),
+                None,
+                ConnectionState::Error("no window".to_owned()),
+            ),
+            (Some(Ok(WebsocketStatus::Connected)), None, ConnectionState::WaitingForWallet),
+            (
+                Some(Ok(WebsocketStatus::Connected)),
+                Some(Ok(false)),
+                ConnectionState::WaitingForWallet,
+            ),
+            (
+                Some(Ok(WebsocketStatus::Connected)),
+                Some(Err("rejected".to_owned())),
+                ConnectionState::Error("rejected".to_owned()),
+            ),
+            (Some(Ok(WebsocketStatus::Connected)), Some(Ok(true)), ConnectionState::Ready),
+        ];
+        for (ws, wallet, expected) in cases {
+            assert_eq!(ConnectionState::from_parts(ws.as_ref(), None, 2, wallet), expected);
+        }
+
+        let cases = [
+            (Some(Ok(WebsocketStatus::Pending)), ConnectionState::Finished),
+            (Some(Ok(WebsocketStatus::Error("refused".to_owned()))), ConnectionState::Finished),
+            (Some(Ok(WebsocketStatus::Connected)), ConnectionState::Ready),
+            (Some(Err("restarted".to_owned())), ConnectionState::Error("restarted".to_owned())),
+        ];
+        for (ws, expected) in cases {
+            let closed = Some(ServerClose::Finished);
+            assert_eq!(
+                ConnectionState::from_parts(ws.as_ref(), closed, 0, Some(Ok(true))),
+                expected
+            );
+        }
+
+        let closed = Some(ServerClose::ConnectedElsewhere);
+        assert_eq!(
+            ConnectionState::from_parts(Some(&Ok(WebsocketStatus::Pending)), closed, 0, None),
+            ConnectionState::ConnectedElsewhere
+        );
+        let closed = Some(ServerClose::Replaced);
+        assert_eq!(
+            ConnectionState::from_parts(Some(&Ok(WebsocketStatus::Pending)), closed, 0, None),
+            ConnectionState::Replaced
+        );
+        // retrying doesn't hide that another tab has the connection
+        let closed = Some(ServerClose::ConnectedElsewhere);
+        let ws = Ok(WebsocketStatus::Disconnected(CloseEvent {
+            code: 1000,
+            reason: messages::ALREADY_CONNECTED_REASON.to_owned(),
+            was_clean: true,
+        }));
+        assert_eq!(
+            ConnectionState::from_parts(Some(&ws), closed, 1, None),
+            ConnectionState::ConnectedElsewhere
+        );
+    }
+
+    #[test]
+    fn it_tells_shutdowns_from_lost_connections() {
+        let close = |code, was_clean| CloseEvent { code, reason: "".to_owned(), was_clean };
+        let cases = [
+            (close(1000, true), ConnectionState::Finished),
+            (close(1001, true), ConnectionState::Finished),
+            (close(1001, false), ConnectionState::Disconnected { attempt: 1 }),
+            (close(1006, false), ConnectionState::Disconnected { attempt: 1 }),
+            (close(1011, true), ConnectionState::Disconnected { attempt: 1 }),
+            (close(4000, false), ConnectionState::Disconnected { attempt: 1 }),
+        ];
+        for (event, expected) in cases {
+            let ws = Ok(WebsocketStatus::Disconnected(event));
+            assert_eq!(ConnectionState::from_parts(Some(&ws), None, 1, None), expected);
+        }
+    }
+
+    #[test]
+    fn it_backs_off_reconnections() {
+        let delays: Vec<_> = (0..=8).map(reconnect_delay).collect();
+        assert_eq!(delays, [1_000, 1_000, 2_000, 4_000, 8_000, 16_000, 30_000, 30_000, 30_000]);
+        assert_eq!(reconnect_delay(u32::MAX), 30_000);
+        assert!(ConnectionState::Disconnected { attempt: 3 }
+            .to_string()
+            .ends_with("retrying in 4s (attempt 3)..."));
+    }
+
+    #[test]
+    fn it_derives_the_base_path_from_the_location() {
+        assert_eq!(base_path(""), "/");
+        assert_eq!(base_path("/"), "/");
+        assert_eq!(base_path("/wallet/"), "/wallet/");
+        assert_eq!(base_path("/wallet/index.html"), "/wallet/");
+        assert_eq!(base_path("/wallet"), "/");
+    }
+
+    #[test]
+    fn it_passes_the_nonce_to_the_websocket() {
+        assert_eq!(ws_query("?nonce=abc", None), "?nonce=abc");
+        assert_eq!(ws_query("?nonce=abc", Some("xyz")), "?nonce=abc&session=xyz");
+        assert_eq!(ws_query("", Some("xyz")), "?session=xyz");
+        assert_eq!(ws_query("?", Some("xyz")), "?session=xyz");
+    }
+
+    #[test]
+    fn it_validates_chain_info() {
+        let urls = |urls: &[&str]| urls.iter().map(|url| url.to_string()).collect::<Vec<_>>();
+        let errors = ChainInfoErrors::new(
+            Some("CoolChain"),
+            &urls(&["https://website/api/rpc", " ", "http://backup/rpc"]),
+            &urls(&[""]),
+            Some("18"),
+            &urls(&["http://website/block/{block}"]),
+        );
+        assert!(errors.is_valid());
+
+        let errors = ChainInfoErrors::new(
+            Some(" "),
+            &urls(&["", " "]),
+            &urls(&["https://website/icon.png", "website/icon.png"]),
+            Some("-1"),
+            &urls(&["https://"]),
+        );
+        assert_eq!(
+            errors,
+            ChainInfoErrors {
+                chain_name: Some("required".to_owned()),
+                rpc_urls: Some("required".to_owned()),
+                icon_urls: Some("website/icon.png must be an http:// or https:// URL".to_owned()),
+                nc_decimals: Some("must be a whole number".to_owned()),
+                block_explorer_urls: Some("https:// must be an http:// or https:// URL".to_owned()),
+            }
+        );
+        assert!(!errors.is_valid());
+    }
+
+    #[test]
+    fn it_collects_non_empty_entries() {
+        assert_eq!(non_empty_entries(&[]), None);
+        assert_eq!(non_empty_entries(&["".to_owned(), " ".to_owned()]), None);
+        assert_eq!(
+            non_empty_entries(&[" a ".to_owned(), "".to_owned(), "b".to_owned()]),
+            Some(vec!["a".to_owned(), "b".to_owned()])
+        );
+    }
 }
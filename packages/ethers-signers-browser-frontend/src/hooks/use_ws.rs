@@ -1,25 +1,67 @@
 use crate::{
     console::console_error,
+    helpers::utils::{base_path, is_clean_shutdown, reconnect_delay, ws_query},
     ws::{messages, WebsocketEvent, WebsocketService, WebsocketStatus},
 };
 use std::sync::{Arc, Mutex};
 use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::spawn_local;
 use web_sys::window;
 use yew::prelude::*;
 
-fn create_ws() -> Result<WebsocketService, String> {
+/// The config injected by the server, missing when the page is served by something else (e.g.
+/// `trunk serve` during development)
+fn page_config() -> Option<messages::PageConfig> {
+    let element = window()?.document()?.get_element_by_id(messages::PAGE_CONFIG_ELEMENT_ID)?;
+    match serde_json::from_str(&element.text_content()?) {
+        Ok(config) => Some(config),
+        Err(e) => {
+            console_error!("invalid page config: {}", e);
+            None
+        }
+    }
+}
+
+/// The base path of the current page, see `base_path`
+fn location_base_path() -> String {
+    let pathname = window().and_then(|window| window.location().pathname().ok());
+    base_path(pathname.as_deref().unwrap_or("/"))
+}
+
+fn create_ws(session: Option<String>) -> Result<WebsocketService, String> {
     let window = window().ok_or("no window")?;
     let host = window.location().host().map_err(|e| format!("{:?}", e))?;
-    let secure = match window.location().protocol() {
+    let search = window.location().search().map_err(|e| format!("{:?}", e))?;
+    let page_secure = match window.location().protocol() {
         Ok(protocol) => protocol == "https:",
         Err(_) => false,
     };
-    match WebsocketService::new(format!("{}/ws/", host), secure) {
+    let (path, secure) = match page_config() {
+        Some(config) => (config.ws_path, config.secure.unwrap_or(page_secure)),
+        None => (format!("{}ws/", location_base_path()), page_secure),
+    };
+    // the server checks the nonce, resuming a session lets it skip the init (e.g. chain switch)
+    // after a blip
+    let query = ws_query(&search, session.as_deref());
+    match WebsocketService::new(format!("{}{}{}", host, path, query), secure) {
         Ok(ws) => Ok(ws),
         Err(e) => Err(format!("{}", e)),
     }
 }
 
+/// Checks that the server still accepts our nonce, which is not the case if the command was
+/// restarted without a stable nonce
+async fn is_nonce_valid() -> Result<bool, String> {
+    let window = window().ok_or("no window")?;
+    let search = window.location().search().map_err(|e| format!("{:?}", e))?;
+    let base_path = page_config().map_or_else(location_base_path, |config| config.base_path);
+    let res = reqwasm::http::Request::get(format!("{}nonce/{}", base_path, search).as_str())
+        .send()
+        .await
+        .map_err(|e| format!("{}", e))?;
+    Ok(res.status() != 404)
+}
+
 pub(crate) struct MessageCallbackArgs {
     pub request: messages::Request,
     pub websocket: Arc<Mutex<WebsocketService>>,
@@ -27,8 +69,27 @@ pub(crate) struct MessageCallbackArgs {
 
 pub(crate) type MessageCallback = yew::Callback<MessageCallbackArgs>;
 
+/// Why the server closed the connection on purpose
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ServerClose {
+    /// The server shut down cleanly
+    Finished,
+    /// Another tab is connected, retried as that connection might be stale (e.g. a closed laptop)
+    ConnectedElsewhere,
+    /// Another tab took over, not retried as both tabs would keep taking the connection from
+    /// each other when `ServerOptions::replace_connected_tab` is set
+    Replaced,
+}
+
 pub(crate) struct WSState {
     pub status: Option<Result<WebsocketStatus, String>>,
+    /// The current websocket, replaced on every reconnection
+    pub websocket: Option<Arc<Mutex<WebsocketService>>>,
+    /// Kept until a reconnection succeeds (e.g. the command was restarted with the same nonce)
+    pub closed: Option<ServerClose>,
+    /// How many reconnections were scheduled since the last successful connection, see
+    /// `reconnect_delay`
+    pub reconnect_attempts: u32,
 }
 
 #[hook]
@@ -37,13 +98,17 @@ pub(crate) fn use_ws(on_message: Option<MessageCallback>) -> WSState {
     let websocket = use_state(|| None);
     let status = use_state(|| None);
     let err = use_state(|| None);
+    let closed = use_state(|| None);
+    let reconnect_attempts = use_state(|| 0);
+    let session = use_mut_ref(|| None);
 
     {
         let websocket = websocket.clone();
         let status = status.clone();
         let err = err.clone();
+        let session = session.clone();
         use_effect_with_deps(
-            move |_| match create_ws() {
+            move |_| match create_ws(session.borrow().clone()) {
                 Ok(ws) => {
                     let ws = Arc::new(Mutex::new(ws));
                     websocket.set(Some(ws.clone()));
@@ -64,6 +129,7 @@ pub(crate) fn use_ws(on_message: Option<MessageCallback>) -> WSState {
         let on_message = on_message.clone();
         let websocket = websocket.clone();
         let status = status.clone();
+        let session = session.clone();
 
         #[derive(PartialEq, Clone)]
         struct Deps {
@@ -86,6 +152,17 @@ pub(crate) fn use_ws(on_message: Option<MessageCallback>) -> WSState {
                             Callback::from(move |msg| {
                                 match msg {
                                     WebsocketEvent::Message(msg) => {
+                                        if let messages::RequestContent::Init { config, .. } =
+                                            &msg.content
+                                        {
+                                            *session.borrow_mut() = Some(msg.id.clone());
+                                            let mut websocket =
+                                                websocket.lock().expect("poisoned mutex");
+                                            websocket.set_max_message_size(config.max_message_size);
+                                            websocket
+                                                .set_keepalive_interval(config.keepalive_interval);
+                                            websocket.set_trace_messages(config.trace_messages);
+                                        }
                                         if let Some(on_message) = Option::clone(&on_message) {
                                             on_message.emit(MessageCallbackArgs {
                                                 request: msg,
@@ -119,22 +196,61 @@ pub(crate) fn use_ws(on_message: Option<MessageCallback>) -> WSState {
 
     {
         let recreate = recreate.clone();
+        let err = err.clone();
+        let closed = closed.clone();
+        let reconnect_attempts = reconnect_attempts.clone();
 
         use_effect_with_deps(
-            |status| {
+            move |status| {
                 match status {
                     Some(status) => {
                         match status {
-                            WebsocketStatus::Disconnected(_) => {
+                            WebsocketStatus::Connected => {
+                                closed.set(None);
+                                reconnect_attempts.set(0);
+                            }
+                            WebsocketStatus::Disconnected(event) => {
+                                match event.reason.as_str() {
+                                    messages::ALREADY_CONNECTED_REASON => {
+                                        closed.set(Some(ServerClose::ConnectedElsewhere))
+                                    }
+                                    messages::REPLACED_REASON => {
+                                        closed.set(Some(ServerClose::Replaced));
+                                        return
+                                    }
+                                    messages::SERVER_SHUTDOWN_REASON => {
+                                        closed.set(Some(ServerClose::Finished))
+                                    }
+                                    _ if is_clean_shutdown(event) => {
+                                        closed.set(Some(ServerClose::Finished))
+                                    }
+                                    _ => {}
+                                }
+                                // backing off so a server which is gone for good isn't hammered
+                                let attempt = *reconnect_attempts + 1;
+                                reconnect_attempts.set(attempt);
                                 let callback = Closure::<dyn Fn()>::new(move || {
-                                    recreate.set(*recreate + 1);
+                                    let recreate = recreate.clone();
+                                    let err = err.clone();
+                                    spawn_local(async move {
+                                        match is_nonce_valid().await {
+                                            Ok(false) => err.set(Some(
+                                                "the command was restarted, please open the new \
+                                                 URL it printed"
+                                                    .to_owned(),
+                                            )),
+                                            // if the server is unreachable, the websocket will
+                                            // fail and we will try again
+                                            _ => recreate.set(*recreate + 1),
+                                        }
+                                    });
                                 });
                                 match window() {
                                     Some(window) => {
                                         match window
                                             .set_timeout_with_callback_and_timeout_and_arguments_0(
                                                 callback.as_ref().unchecked_ref(),
-                                                5000,
+                                                reconnect_delay(attempt) as i32,
                                             ) {
                                             Ok(_) => {}
                                             Err(e) => {
@@ -157,6 +273,9 @@ pub(crate) fn use_ws(on_message: Option<MessageCallback>) -> WSState {
     }
 
     WSState {
+        websocket: Option::clone(&websocket),
+        closed: *closed,
+        reconnect_attempts: *reconnect_attempts,
         status: match Option::clone(&err) {
             Some(err) => Some(Err(err.clone())),
             _ => match Option::clone(&status) {
@@ -1,14 +1,30 @@
-use components::{label::Label, wallet_status::WalletStatus};
+use components::{
+    label::Label, request_review::RequestReview, wallet_status::WalletStatus, warning::Warning,
+};
 use console::console_error;
 use ethereum_provider::{
-    provider::ProviderError,
+    provider::{ProviderError, Transaction, TypedDataVersion},
     yew::{use_provider, ChainInfo, NativeCurrency, ProviderStatus},
 };
-use ethers::types::H160;
-use helpers::ethers::{address_to_string, transform_transaction};
+use ethers::types::transaction::eip2718::TypedTransaction;
+use helpers::{
+    ethers::{
+        address_to_string, bump_estimate, describe_request, large_approval_warning, parse_chain_id,
+        parse_estimate, parse_wallet_address, to_legacy_typed_data, transform_transaction,
+    },
+    utils::ConnectionState,
+};
 use hooks::use_ws::use_ws;
-use std::{collections::HashMap, str::FromStr};
-use ws::messages::{RequestContent, Response, ResponseContent};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    rc::Rc,
+    sync::{Arc, Mutex},
+};
+use ws::{
+    messages::{self, FrontendConfig, RequestContent, Response, ResponseContent},
+    WebsocketService,
+};
 use yew::prelude::*;
 
 mod components;
@@ -17,51 +33,136 @@ mod helpers;
 mod hooks;
 mod ws;
 
+type Chains = Option<HashMap<u64, messages::ChainInfo>>;
+
+/// EIP-1193's "user rejected request" error code, sent when a request is rejected in the page
+const USER_REJECTED_CODE: i64 = 4001;
+
+fn to_provider_chains(chains: Chains) -> Option<HashMap<u64, ChainInfo>> {
+    chains.map(|h| {
+        h.iter()
+            .map(|(k, v)| {
+                (
+                    *k,
+                    ChainInfo {
+                        chain_name: v.chain_name.clone(),
+                        rpc_urls: v.rpc_urls.clone(),
+                        icon_urls: v.icon_urls.clone(),
+                        native_currency: v.native_currency.clone().map(|v| NativeCurrency {
+                            name: v.name,
+                            symbol: v.symbol,
+                            decimals: v.decimals,
+                        }),
+                        block_explorer_urls: v.block_explorer_urls.clone(),
+                        testnet: v.testnet,
+                    },
+                )
+            })
+            .collect::<HashMap<_, _>>()
+    })
+}
+
+/// Fills the gas limit and price left unset with the wallet's estimates plus `bump`
+async fn apply_estimate_bump(
+    status: &ProviderStatus,
+    bump: messages::EstimateBump,
+    mut transaction: Transaction,
+) -> Result<Transaction, ProviderError> {
+    if let (None, Some(percent)) = (transaction.gas, bump.gas_limit) {
+        let estimate = status.provider.request_estimate_gas(transaction.clone()).await?;
+        transaction.gas = Some(bump_estimate(u64::try_from(estimate).unwrap_or(u64::MAX), percent));
+    }
+    if let (None, Some(percent)) = (transaction.gas_price, bump.gas_price) {
+        let estimate = status.provider.request_gas_price().await?;
+        let estimate = parse_estimate(&estimate).map_err(ProviderError::Unsupported)?;
+        transaction.gas_price = Some(bump_estimate(estimate, percent));
+    }
+    Ok(transaction)
+}
+
+/// Converts the transaction for the wallet, along with its chain id if it has one
+fn convert_transaction(
+    transaction: TypedTransaction,
+) -> Result<(Option<u64>, Transaction), ProviderError> {
+    transform_transaction(transaction)
+        .map_err(|e| ProviderError::Unsupported(format!("transaction: {}", e)))
+}
+
+/// Fails with `ProviderError::ChainMismatch` unless the wallet is on `chain_id`
+async fn ensure_chain(status: &ProviderStatus, chain_id: u64) -> Result<(), ProviderError> {
+    let actual = status.provider.request_chain_id().await?;
+    match parse_chain_id(&actual) {
+        Some(actual) if actual == chain_id => Ok(()),
+        Some(actual) => Err(ProviderError::ChainMismatch { expected: chain_id, actual }),
+        None => Err(ProviderError::Unsupported(format!("invalid chain id: {}", actual))),
+    }
+}
+
+/// Converts the transaction for the wallet and switches to its chain, failing with
+/// `ProviderError::ChainMismatch` if the wallet ends up on another one
+async fn switch_to_transaction_chain(
+    status: &ProviderStatus,
+    chains: Chains,
+    transaction: TypedTransaction,
+) -> Result<Transaction, ProviderError> {
+    let (chain_id, transaction) = convert_transaction(transaction)?;
+    if let Some(chain_id) = chain_id {
+        status.change_chain(chain_id, to_provider_chains(chains)).await?;
+        // some wallets resolve the switch without switching (or the user switched back since)
+        ensure_chain(status, chain_id).await?;
+    }
+    Ok(transaction)
+}
+
+/// Switches to the transaction's chain and fills in the estimates, see `apply_estimate_bump`
+async fn prepare_transaction(
+    status: &ProviderStatus,
+    config: &FrontendConfig,
+    chains: Chains,
+    transaction: TypedTransaction,
+) -> Result<Transaction, ProviderError> {
+    let transaction = switch_to_transaction_chain(status, chains, transaction).await?;
+    // estimated after switching chains so the right node is asked
+    match config.estimate_bump {
+        Some(bump) => apply_estimate_bump(status, bump, transaction).await,
+        None => Ok(transaction),
+    }
+}
+
+/// `chains` are the ones received during `Init`, switching to one of them adds it to the wallet
+/// automatically if needed (instead of asking the user for its details)
 async fn call_provider(
     status: ProviderStatus,
+    config: FrontendConfig,
+    chains: Chains,
     request: RequestContent,
 ) -> Result<ResponseContent, ProviderError> {
     match request {
-        RequestContent::Init { chain_id, chains } => {
-            let chains = chains.map(|h| {
-                h.iter()
-                    .map(|(k, v)| {
-                        (
-                            *k,
-                            ChainInfo {
-                                chain_name: v.chain_name.clone(),
-                                rpc_urls: v.rpc_urls.clone(),
-                                icon_urls: v.icon_urls.clone(),
-                                native_currency: v.native_currency.clone().map(|v| {
-                                    NativeCurrency {
-                                        name: v.name,
-                                        symbol: v.symbol,
-                                        decimals: v.decimals,
-                                    }
-                                }),
-                                block_explorer_urls: v.block_explorer_urls.clone(),
-                            },
-                        )
-                    })
-                    .collect::<HashMap<_, _>>()
-            });
-            status.change_chain(chain_id, chains).await?;
+        RequestContent::Init { chain_id, chains, config: _ } => {
+            status.change_chain(chain_id, to_provider_chains(chains)).await?;
             Ok(ResponseContent::Init {})
         }
         RequestContent::Accounts {} => {
-            let accounts = status
-                .provider
-                .request_accounts()
-                .await?
-                .into_iter()
-                .filter_map(|v| match H160::from_str(v.as_str()) {
-                    Ok(address) => Some(address),
-                    Err(err) => {
-                        console_error!("error parsing address: {:?}", err);
-                        None
+            let mut accounts = vec![];
+            for v in status.provider.request_accounts().await? {
+                match parse_wallet_address(v.as_str()) {
+                    Ok((address, true)) => accounts.push(address),
+                    Ok((address, false)) => {
+                        if config.strict_address_checksums.unwrap_or(false) {
+                            return Err(ProviderError::Unsupported(format!(
+                                "wallet returned an address with an invalid checksum: {}",
+                                v
+                            )))
+                        }
+                        console_error!(
+                            "wallet returned an address with an invalid checksum: {}",
+                            v
+                        );
+                        accounts.push(address);
                     }
-                })
-                .collect();
+                    Err(err) => console_error!("error parsing address: {:?}", err),
+                }
+            }
             Ok(ResponseContent::Accounts { addresses: accounts })
         }
         RequestContent::SignTextMessage { address, message } => {
@@ -69,7 +170,7 @@ async fn call_provider(
                 status.provider.request_sign_text(address_to_string(address), message).await?;
             Ok(ResponseContent::MessageSignature { signature: sig })
         }
-        RequestContent::SignBinaryMessage { address, message } => {
+        RequestContent::SignBinaryMessage { address, message, .. } => {
             let sig = status
                 .provider
                 .request_sign_hash(address_to_string(address), message.to_string())
@@ -77,66 +178,376 @@ async fn call_provider(
             Ok(ResponseContent::MessageSignature { signature: sig })
         }
         RequestContent::SignTransaction { transaction } => {
-            let (chain_id, transaction) = match transform_transaction(transaction) {
-                Ok(transaction) => transaction,
-                Err(e) => return Err(ProviderError::Unsupported(format!("transaction: {}", e))),
-            };
-            if let Some(chain_id) = chain_id {
-                status.change_chain(chain_id, None).await?;
-            }
+            let transaction = prepare_transaction(&status, &config, chains, transaction).await?;
             let sig = status.provider.request_sign_transaction(transaction).await?;
             Ok(ResponseContent::TransactionSignature { signature: sig })
         }
-        RequestContent::SignTypedData { address, typed_data } => {
-            let sig = status
-                .provider
-                .request_sign_typed_data(address_to_string(address), typed_data)
-                .await?;
+        RequestContent::SendTransaction { transaction } => {
+            let transaction = prepare_transaction(&status, &config, chains, transaction).await?;
+            let hash = status.provider.request_send_transaction(transaction).await?;
+            Ok(ResponseContent::TransactionHash { hash })
+        }
+        RequestContent::SignTypedData { address, typed_data, version } => {
+            let address = address_to_string(address);
+            let sig = match version {
+                messages::TypedDataVersion::V1 => {
+                    let data = to_legacy_typed_data(&typed_data)
+                        .map_err(|e| ProviderError::Unsupported(format!("typed data v1: {}", e)))?;
+                    status
+                        .provider
+                        .request_sign_typed_data_with_version(address, data, TypedDataVersion::V1)
+                        .await?
+                }
+                messages::TypedDataVersion::V3 => {
+                    status
+                        .provider
+                        .request_sign_typed_data_with_version(
+                            address,
+                            typed_data,
+                            TypedDataVersion::V3,
+                        )
+                        .await?
+                }
+                messages::TypedDataVersion::V4 => {
+                    status.provider.request_sign_typed_data_v4(address, typed_data).await?
+                }
+            };
             Ok(ResponseContent::MessageSignature { signature: sig })
         }
+        RequestContent::Call { transaction } => {
+            // reads don't prompt, switching chains would (and change the wallet behind the user's
+            // back)
+            let (chain_id, transaction) = convert_transaction(transaction)?;
+            if let Some(chain_id) = chain_id {
+                ensure_chain(&status, chain_id).await?;
+            }
+            let data = status.provider.request_call(transaction, None).await?;
+            Ok(ResponseContent::CallResult { data })
+        }
+        RequestContent::RawRequest { method, params } => {
+            let result = status.provider.request_raw(method, params).await?;
+            Ok(ResponseContent::RawResult { result })
+        }
+        RequestContent::Pong {} => {
+            Err(ProviderError::Unsupported("unexpected keepalive reply".to_owned()))
+        }
+        RequestContent::Cancel {} => {
+            Err(ProviderError::Unsupported("unexpected cancellation".to_owned()))
+        }
     }
 }
 
-fn handle_request(
-    args: hooks::use_ws::MessageCallbackArgs,
-    status: &Option<Result<ProviderStatus, ProviderError>>,
-) {
+/// The requests currently being handled by the wallet, with the websocket to reply on
+#[derive(Clone, Default)]
+struct InFlight(Rc<RefCell<HashMap<String, Arc<Mutex<WebsocketService>>>>>);
+
+impl PartialEq for InFlight {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// What the page shows about a request while the wallet handles it
+#[derive(Clone, Default, PartialEq)]
+struct Notice {
+    /// e.g. a large token approval, see `large_approval_warning`
+    warning: Option<String>,
+    /// The message whose hash is being signed, when the server sent it
+    original: Option<String>,
+}
+
+/// The notices of the requests in flight, keyed by request id so answering one doesn't clear
+/// another's (reducer actions also apply to the latest state, unlike a stale `UseStateHandle`)
+#[derive(Default, PartialEq)]
+struct Notices(Vec<(String, Notice)>);
+
+enum NoticeAction {
+    Add(String, Notice),
+    Remove(String),
+}
+
+impl Reducible for Notices {
+    type Action = NoticeAction;
+
+    fn reduce(self: Rc<Self>, action: Self::Action) -> Rc<Self> {
+        let mut notices = self.0.clone();
+        match action {
+            NoticeAction::Add(id, notice) => {
+                notices.retain(|(other, _)| *other != id);
+                notices.push((id, notice));
+            }
+            NoticeAction::Remove(id) => {
+                if !notices.iter().any(|(other, _)| *other == id) {
+                    return self
+                }
+                notices.retain(|(other, _)| *other != id);
+            }
+        }
+        Rc::new(Self(notices))
+    }
+}
+
+#[derive(PartialEq, Clone)]
+struct RequestDeps {
+    status: Option<Result<ProviderStatus, ProviderError>>,
+    config: UseStateHandle<FrontendConfig>,
+    chains: UseStateHandle<Chains>,
+    notices: UseReducerHandle<Notices>,
+    /// The signing request waiting for the user, see `FrontendConfig::review_requests`
+    review: UseStateHandle<Option<messages::Request>>,
+    /// The gas estimate of the transaction under review with its request id, see
+    /// `estimate_for_review`
+    gas_estimate: UseStateHandle<Option<(String, Result<String, String>)>>,
+    in_flight: InFlight,
+}
+
+/// Wallet calls can take arbitrarily long (e.g. passkey wallets showing their own multi-step UI),
+/// so they are never timed out here. The server waits for as long as its own timeout allows and
+/// keeps the websocket alive meanwhile, but if the connection still drops, the server resends
+/// the pending request on the new connection: it is then attached to the ongoing wallet call
+/// instead of prompting the user a second time.
+fn handle_request(args: hooks::use_ws::MessageCallbackArgs, deps: &RequestDeps) {
     let hooks::use_ws::MessageCallbackArgs { request, websocket } = args;
+    let RequestDeps { status, config, chains, notices, review, in_flight, .. } = deps.clone();
+
+    if let RequestContent::Cancel {} = request.content {
+        // the wallet can't be told, its answer is dropped (see `reply`)
+        if in_flight.0.borrow_mut().remove(&request.id).is_some() {
+            notices.dispatch(NoticeAction::Remove(request.id.clone()));
+        }
+        if matches!(&*review, Some(pending) if pending.id == request.id) {
+            review.set(None);
+        }
+        return
+    }
+
+    if let Some(current) = in_flight.0.borrow_mut().get_mut(&request.id) {
+        *current = websocket;
+        return
+    }
+    in_flight.0.borrow_mut().insert(request.id.clone(), websocket);
+
+    match &request.content {
+        RequestContent::Init { config: new_config, chains: new_chains, .. } => {
+            config.set(new_config.clone());
+            chains.set(new_chains.clone());
+        }
+        RequestContent::SignTransaction { transaction } |
+        RequestContent::SendTransaction { transaction } => {
+            let warning = large_approval_warning(transaction, config.approval_warning_threshold);
+            if warning.is_some() {
+                let notice = Notice { warning, original: None };
+                notices.dispatch(NoticeAction::Add(request.id.clone(), notice));
+            }
+        }
+        RequestContent::SignBinaryMessage { original: Some(bytes), .. } => {
+            let notice = Notice { warning: None, original: Some(bytes.to_string()) };
+            notices.dispatch(NoticeAction::Add(request.id.clone(), notice));
+        }
+        _ => {}
+    }
+
+    // without a wallet, the request is answered right away
+    let reviewed = config.review_requests.unwrap_or(true) && matches!(status, Some(Ok(_)));
+    if reviewed && describe_request(&request.content).is_some() {
+        if let RequestContent::SignTransaction { transaction } |
+        RequestContent::SendTransaction { transaction } = &request.content
+        {
+            estimate_for_review(request.id.clone(), transaction.clone(), deps);
+        }
+        review.set(Some(request));
+        return
+    }
+    forward_request(request, deps.clone());
+}
 
-    let status = status.clone();
+/// Estimates the gas of a transaction under review, skipped when it targets another chain than
+/// the wallet's as the chain is only switched once the request is approved
+fn estimate_for_review(id: String, transaction: TypedTransaction, deps: &RequestDeps) {
+    let status = match &deps.status {
+        Some(Ok(status)) => status.clone(),
+        _ => return,
+    };
+    let gas_estimate = deps.gas_estimate.clone();
+    gas_estimate.set(None);
     wasm_bindgen_futures::spawn_local(async move {
-        let res = match status {
-            Some(Ok(status)) => call_provider(status, request.content).await,
-            _ => Ok(ResponseContent::Error {
-                error: "no wallet available in your browser".to_string(),
-            }),
+        let (chain_id, transaction) = match transform_transaction(transaction) {
+            Ok(transaction) => transaction,
+            Err(e) => {
+                gas_estimate.set(Some((id, Err(e))));
+                return
+            }
         };
-        match websocket
-            .lock()
-            .expect("poisoned mutex")
-            .send(Response {
-                id: request.id,
-                content: match res {
-                    Ok(content) => content,
-                    Err(e) => ResponseContent::Error { error: format!("{}", e) },
-                },
-            })
-            .await
-        {
-            Ok(_) => (),
-            Err(e) => console_error!("error sending response: {:?}", e),
+        if chain_id.is_some() && chain_id != status.chain_id.as_deref().and_then(parse_chain_id) {
+            return
+        }
+        let estimate = match status.provider.request_estimate_gas(transaction).await {
+            Ok(gas) => Ok(gas.to_string()),
+            // e.g. `execution reverted: ERC20: transfer amount exceeds balance`
+            Err(ProviderError::RPC(e)) => Err(e.message),
+            Err(e) => Err(e.to_string()),
         };
+        gas_estimate.set(Some((id, estimate)));
     });
 }
 
+/// Passes `request` to the wallet and replies with its answer
+fn forward_request(request: messages::Request, deps: RequestDeps) {
+    wasm_bindgen_futures::spawn_local(async move {
+        let RequestDeps { status, config, chains, .. } = deps.clone();
+        let res = match status {
+            Some(Ok(status)) => {
+                call_provider(
+                    status,
+                    FrontendConfig::clone(&config),
+                    Chains::clone(&chains),
+                    request.content,
+                )
+                .await
+            }
+            // the wallet might be injected a bit after the page loads
+            None => Ok(ResponseContent::WalletLoading {}),
+            Some(Err(e)) => {
+                Ok(ResponseContent::NoWallet { error: format!("no wallet available: {}", e) })
+            }
+        };
+        reply(request.id, res, &deps).await;
+    });
+}
+
+/// Answers the request `id` on the websocket it was last received on
+async fn reply(id: String, res: Result<ResponseContent, ProviderError>, deps: &RequestDeps) {
+    deps.notices.dispatch(NoticeAction::Remove(id.clone()));
+    let websocket = match deps.in_flight.0.borrow_mut().remove(&id) {
+        Some(websocket) => websocket,
+        None => return,
+    };
+    let content = match res {
+        Ok(content) => content,
+        Err(e) => {
+            let (code, data) = match &e {
+                ProviderError::RPC(err) | ProviderError::UnknownChain(err) => {
+                    (Some(err.code.code()), err.data.clone())
+                }
+                ProviderError::ChainMismatch { expected, actual } => (
+                    Some(messages::CHAIN_MISMATCH_CODE),
+                    serde_json::to_value(messages::ChainMismatch {
+                        expected: *expected,
+                        actual: *actual,
+                    })
+                    .ok(),
+                ),
+                _ => (None, None),
+            };
+            ResponseContent::Error { error: format!("{}", e), code, data }
+        }
+    };
+    // the lock is released before awaiting, other replies might be sent meanwhile
+    let sending = websocket.lock().expect("poisoned mutex").send(Response { id, content });
+    match sending.await {
+        Ok(_) => (),
+        Err(e) => console_error!("error sending response: {:?}", e),
+    };
+}
+
 #[function_component]
 fn App() -> Html {
     let status = use_provider();
-    let callback = {
-        let status = status.clone();
-        use_callback(handle_request, status)
+    let config = use_state(FrontendConfig::default);
+    let chains = use_state(|| None);
+    let notices = use_reducer(Notices::default);
+    let review = use_state(|| None);
+    let gas_estimate = use_state(|| None);
+    let in_flight = use_state(InFlight::default);
+    let deps = RequestDeps {
+        status: status.clone(),
+        config,
+        chains: chains.clone(),
+        notices: notices.clone(),
+        review: review.clone(),
+        gas_estimate: gas_estimate.clone(),
+        in_flight: InFlight::clone(&in_flight),
+    };
+    let on_approve = {
+        let deps = deps.clone();
+        Callback::from(move |_| {
+            if let Some(request) = Option::clone(&deps.review) {
+                deps.review.set(None);
+                forward_request(request, deps.clone());
+            }
+        })
     };
+    let on_reject = {
+        let deps = deps.clone();
+        Callback::from(move |_| {
+            if let Some(request) = Option::clone(&deps.review) {
+                deps.review.set(None);
+                let deps = deps.clone();
+                wasm_bindgen_futures::spawn_local(async move {
+                    let content = ResponseContent::Error {
+                        error: "the user rejected the request".to_owned(),
+                        code: Some(USER_REJECTED_CODE),
+                        data: None,
+                    };
+                    reply(request.id, Ok(content), &deps).await;
+                });
+            }
+        })
+    };
+    let reviewing = Option::clone(&review).and_then(|request| {
+        let gas_estimate = match &*gas_estimate {
+            Some((id, estimate)) if *id == request.id => Some(estimate.clone()),
+            _ => None,
+        };
+        describe_request(&request.content).map(|(title, fields)| (title, fields, gas_estimate))
+    });
+    let callback = use_callback(handle_request, deps);
     let ws = use_ws(Some(callback));
+    let state = ConnectionState::new(&ws, &status);
+    let testnet = status.as_ref().and_then(|status| status.as_ref().ok()).and_then(|status| {
+        status.is_testnet_with(to_provider_chains(Chains::clone(&chains)).as_ref())
+    });
+
+    {
+        // the server skips `Init` when a session is resumed, so it needs to be told about any
+        // change which happened while disconnected (and any later one)
+        #[derive(PartialEq)]
+        struct SyncDeps {
+            websocket_id: Option<usize>,
+            chain_id: Option<String>,
+            accounts: Option<Vec<String>>,
+        }
+        let websocket = ws.websocket.clone();
+        let provider = status.as_ref().and_then(|status| status.as_ref().ok());
+        let deps = SyncDeps {
+            websocket_id: websocket.as_ref().map(|w| w.lock().expect("poisoned mutex").id()),
+            chain_id: provider.and_then(|p| p.chain_id.clone()),
+            accounts: provider.and_then(|p| p.accounts.clone()),
+        };
+        use_effect_with_deps(
+            move |deps| {
+                if let Some(websocket) = websocket {
+                    let content = ResponseContent::Status {
+                        chain_id: deps.chain_id.as_deref().and_then(parse_chain_id),
+                        accounts: deps.accounts.as_ref().map(|accounts| {
+                            accounts
+                                .iter()
+                                .filter_map(|a| parse_wallet_address(a).ok().map(|(a, _)| a))
+                                .collect()
+                        }),
+                    };
+                    let response = Response { id: "status".to_owned(), content };
+                    let sending = websocket.lock().expect("poisoned mutex").send(response);
+                    wasm_bindgen_futures::spawn_local(async move {
+                        if let Err(e) = sending.await {
+                            console_error!("error sending status: {:?}", e);
+                        }
+                    });
+                }
+            },
+            deps,
+        );
+    }
 
     html! {
       <>
@@ -145,8 +556,26 @@ fn App() -> Html {
           <h1 style="margin-top: 0;"><pre>{ "ethers-signers-browser" }</pre></h1>
         </header>
         <section style="max-width: 600px; margin: auto;">
-          <Label name="Server connection" value={helpers::utils::get_ws_status(ws)} />
+          if testnet == Some(true) {
+            <p style="text-align: center; font-size: x-large; color: white; background: darkorange;">
+              <strong>{ "TESTNET" }</strong>
+            </p>
+          }
+          <Label name="Status" value={state.to_string()} />
           <WalletStatus status={status} />
+          { for notices.0.iter().map(|(id, notice)| html! {
+            <div key={id.clone()}>
+              if let Some(message) = notice.warning.clone() {
+                <Warning message={message} />
+              }
+              if let Some(bytes) = notice.original.clone() {
+                <Label name="Message being hashed and signed" value={bytes} />
+              }
+            </div>
+          }) }
+          if let Some((title, fields, gas_estimate)) = reviewing {
+            <RequestReview {title} {fields} {gas_estimate} {on_approve} {on_reject} />
+          }
         </section>
       </>
     }
@@ -2,12 +2,72 @@ use ethers::core::{
     abi::Address,
     types::{
         transaction::{eip2718::TypedTransaction, eip712::TypedData},
-        H256,
+        Bytes, H256, U256,
     },
 };
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::collections::HashMap;
 
+/// The `eth_signTypedData` method used to sign typed data, different contracts expect different
+/// versions
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum TypedDataVersion {
+    /// `eth_signTypedData`, only supports flat data (the fields of `primaryType` must not be
+    /// structs) which is converted to the legacy `{ type, name, value }` format
+    V1,
+    /// `eth_signTypedData_v3`, doesn't support arrays
+    V3,
+    /// `eth_signTypedData_v4`
+    #[default]
+    V4,
+}
+
+/// The fields of a request or response hidden by `MessageTracing::Redacted`
+const REDACTED_FIELDS: &[&str] = &["message", "original", "signature", "typed_data"];
+
+/// How the websocket messages are logged, see `FrontendConfig::trace_messages`
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum MessageTracing {
+    /// Signatures and signed content are replaced with `<redacted>`
+    Redacted,
+    Full,
+}
+
+impl MessageTracing {
+    /// Renders a serialized `Request` or `Response` for logging
+    pub fn render(&self, text: &str) -> String {
+        if *self == MessageTracing::Full {
+            return text.to_owned()
+        }
+        let mut value = match serde_json::from_str::<Value>(text) {
+            Ok(value) => value,
+            Err(_) => return "<invalid message>".to_owned(),
+        };
+        if let Some(content) = value.pointer_mut("/content/message").and_then(Value::as_object_mut)
+        {
+            for (key, field) in content.iter_mut() {
+                if REDACTED_FIELDS.contains(&key.as_str()) {
+                    *field = Value::String("<redacted>".to_owned());
+                }
+            }
+        }
+        value.to_string()
+    }
+}
+
+/// Percentages added to the gas limit and gas price estimated by the wallet's node before
+/// prompting, e.g. `gas_limit: Some(20)` for +20% on congested networks.
+///
+/// Each bump only applies when the transaction leaves the value unset, explicit values are sent
+/// as is. Wallets usually show the suggested values while still letting the user edit them, and
+/// some recompute their own fees regardless, in which case the fee bump has no effect.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct EstimateBump {
+    pub gas_limit: Option<u64>,
+    pub gas_price: Option<u64>,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct NativeCurrency {
     pub name: String,
@@ -15,30 +75,133 @@ pub struct NativeCurrency {
     pub decimals: u64,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct ChainInfo {
     pub chain_name: Option<String>,
     pub rpc_urls: Option<Vec<String>>,
     pub icon_urls: Option<Vec<String>>,
     pub native_currency: Option<NativeCurrency>,
     pub block_explorer_urls: Option<Vec<String>>,
+    /// Whether the chain is a testnet (flagged as such in the browser), defaults to the built-in
+    /// classification of well-known chains
+    #[serde(default)]
+    pub testnet: Option<bool>,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+/// The id of the `<script type="application/json">` element holding the `PageConfig`
+pub const PAGE_CONFIG_ELEMENT_ID: &str = "ethers-signers-browser-config";
+
+/// The reason of the websocket close frame sent when the server stops cleanly, letting the page
+/// tell it apart from a crash
+pub const SERVER_SHUTDOWN_REASON: &str = "server shutting down";
+
+/// The reason of the websocket close frame sent to a tab connecting while another one is, which
+/// should keep retrying as the other connection might be stale
+pub const ALREADY_CONNECTED_REASON: &str = "already connected elsewhere";
+
+/// The reason of the websocket close frame sent to the connected tab when another one takes
+/// over, see `ServerOptions::replace_connected_tab`
+pub const REPLACED_REASON: &str = "another tab took over";
+
+/// The code of the `Error` response sent when the wallet isn't on the chain of a transaction,
+/// in the implementation-defined range of JSON-RPC errors, its `data` is a `ChainMismatch`
+pub const CHAIN_MISMATCH_CODE: i64 = -32099;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct ChainMismatch {
+    /// The chain of the transaction
+    pub expected: u64,
+    /// The chain the wallet is on
+    pub actual: u64,
+}
+
+/// Injected by the server into `index.html` so the frontend doesn't have to guess its URLs from
+/// `window.location`, which is wrong behind a reverse proxy
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PageConfig {
+    /// The path the server is reachable at, with a trailing slash (e.g. `/signer/`)
+    pub base_path: String,
+    /// The path of the websocket endpoint (e.g. `/signer/ws/`)
+    pub ws_path: String,
+    /// Whether to use `wss://`, defaults to whether the page itself uses `https://`
+    pub secure: Option<bool>,
+}
+
+/// Settings sent to the frontend during `Init`
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct FrontendConfig {
+    /// ERC-20 `approve` calls with an allowance at or above this amount are flagged as
+    /// suspicious, defaults to `U256::MAX / 2` (which catches "unlimited" approvals)
+    pub approval_warning_threshold: Option<U256>,
+    /// The maximum size of a websocket message in bytes, as enforced by the server
+    pub max_message_size: Option<usize>,
+    /// Reject accounts whose EIP-55 checksum is invalid instead of only logging them, defaults
+    /// to false
+    pub strict_address_checksums: Option<bool>,
+    /// How often the frontend pings the server in milliseconds, the server is considered gone
+    /// after 3 unanswered pings
+    pub keepalive_interval: Option<u64>,
+    /// Log every message in the browser console, defaults to off
+    pub trace_messages: Option<MessageTracing>,
+    /// Bumps applied to the estimated gas limit and price of transactions, defaults to none
+    pub estimate_bump: Option<EstimateBump>,
+    /// Show signing requests in the page and only forward them to the wallet once the user
+    /// approves them, defaults to true
+    pub review_requests: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Request {
     pub id: String,
     pub content: RequestContent,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 #[serde(tag = "type", content = "message")]
 pub enum RequestContent {
-    Init { chain_id: u64, chains: Option<HashMap<u64, ChainInfo>> },
+    Init {
+        chain_id: u64,
+        chains: Option<HashMap<u64, ChainInfo>>,
+        config: FrontendConfig,
+    },
     Accounts {},
-    SignBinaryMessage { address: Address, message: H256 },
-    SignTextMessage { address: Address, message: String },
-    SignTransaction { transaction: TypedTransaction },
-    SignTypedData { address: Address, typed_data: TypedData },
+    SignBinaryMessage {
+        address: Address,
+        message: H256,
+        /// The bytes which were hashed into `message`, shown to the user when available
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        original: Option<Bytes>,
+    },
+    SignTextMessage {
+        address: Address,
+        message: String,
+    },
+    SignTransaction {
+        transaction: TypedTransaction,
+    },
+    /// Signs and broadcasts the transaction, for wallets which can't only sign
+    SendTransaction {
+        transaction: TypedTransaction,
+    },
+    SignTypedData {
+        address: Address,
+        typed_data: TypedData,
+        #[serde(default)]
+        version: TypedDataVersion,
+    },
+    /// Read-only `eth_call` through the wallet's node, on the transaction's chain
+    Call {
+        transaction: TypedTransaction,
+    },
+    /// Any EIP-1193 request, passed to the wallet as is
+    RawRequest {
+        method: String,
+        params: Value,
+    },
+    /// Reply to a `ResponseContent::Ping`, handled by the websocket itself
+    Pong {},
+    /// The server stopped waiting for the request with the same `id`, no reply is expected
+    Cancel {},
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -51,8 +214,77 @@ pub struct Response {
 #[serde(tag = "type", content = "message")]
 pub enum ResponseContent {
     Init {},
-    Accounts { addresses: Vec<Address> },
-    MessageSignature { signature: String },
-    TransactionSignature { signature: String },
-    Error { error: String },
+    Accounts {
+        addresses: Vec<Address>,
+    },
+    MessageSignature {
+        signature: String,
+    },
+    TransactionSignature {
+        signature: String,
+    },
+    TransactionHash {
+        hash: String,
+    },
+    /// The hex encoded return data of a `RequestContent::Call`
+    CallResult {
+        data: String,
+    },
+    /// The result of a `RequestContent::RawRequest`
+    RawResult {
+        result: Value,
+    },
+    Error {
+        error: String,
+        /// The EIP-1193/JSON-RPC code of the wallet error, if any
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        code: Option<i64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        data: Option<serde_json::Value>,
+    },
+    /// The wallet is still being loaded by the browser, the server retries the request later
+    WalletLoading {},
+    /// There is no usable wallet in the browser, retrying won't help
+    NoWallet {
+        error: String,
+    },
+    /// The current state of the wallet, sent whenever it changes or the websocket reconnects
+    Status {
+        chain_id: Option<u64>,
+        accounts: Option<Vec<Address>>,
+    },
+    /// Keepalive sent periodically by the frontend, the server answers with a
+    /// `RequestContent::Pong`
+    Ping {},
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_redacts_traced_messages() {
+        let response = serde_json::to_string(&Response {
+            id: "abc".to_owned(),
+            content: ResponseContent::MessageSignature { signature: "0x1234".to_owned() },
+        })
+        .unwrap();
+        assert_eq!(MessageTracing::Full.render(&response), response);
+        let redacted = MessageTracing::Redacted.render(&response);
+        assert!(redacted.contains("MessageSignature"));
+        assert!(redacted.contains("<redacted>"));
+        assert!(!redacted.contains("0x1234"));
+
+        let request = serde_json::to_string(&Request {
+            id: "abc".to_owned(),
+            content: RequestContent::SignTextMessage {
+                address: Address::zero(),
+                message: "secret".to_owned(),
+            },
+        })
+        .unwrap();
+        let redacted = MessageTracing::Redacted.render(&request);
+        assert!(redacted.contains("0x0000000000000000000000000000000000000000"));
+        assert!(!redacted.contains("secret"));
+    }
 }
@@ -1,14 +1,37 @@
-use crate::console::console_error;
+use crate::console::{console_error, console_log};
 use futures_channel::mpsc::{channel, SendError, Sender};
 use futures_util::{SinkExt, StreamExt};
 use gloo_utils::errors::JsError;
 use rand::Rng;
 use reqwasm::websocket::{futures::WebSocket, Message, WebSocketError as WSError};
-use std::sync::{Arc, Mutex};
-use wasm_bindgen_futures::spawn_local;
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+};
+use wasm_bindgen_futures::{spawn_local, JsFuture};
 
 pub mod messages;
 
+/// Used until the server sends its own through `FrontendConfig::keepalive_interval`
+const DEFAULT_KEEPALIVE_INTERVAL: u64 = 10_000;
+const MISSED_KEEPALIVES: u64 = 3;
+const KEEPALIVE_ID: &str = "keepalive";
+/// Application-defined close code (4000-4999) used when the server stops answering pings
+const KEEPALIVE_CLOSE_CODE: u16 = 4000;
+
+async fn sleep(ms: u64) {
+    let promise = js_sys::Promise::new(&mut |resolve, _| {
+        if let Some(window) = web_sys::window() {
+            if let Err(e) =
+                window.set_timeout_with_callback_and_timeout_and_arguments_0(&resolve, ms as i32)
+            {
+                console_error!("error setting timeout: {:?}", e);
+            }
+        }
+    });
+    let _ = JsFuture::from(promise).await;
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum WebsocketError {
     #[error("js error: {0}")]
@@ -63,6 +86,9 @@ pub(super) struct WebsocketService {
     tx: Sender<String>,
     status: Arc<Mutex<WebsocketStatus>>,
     subscribers: Arc<Mutex<Vec<CallBack>>>,
+    max_message_size: Arc<Mutex<Option<usize>>>,
+    keepalive_interval: Arc<Mutex<u64>>,
+    trace_messages: Arc<Mutex<Option<messages::MessageTracing>>>,
 }
 
 impl WebsocketService {
@@ -75,6 +101,10 @@ impl WebsocketService {
         let (mut write, mut read) = ws.split();
 
         let (in_tx, mut in_rx) = channel::<String>(10);
+        let max_message_size = Arc::new(Mutex::new(None));
+        let keepalive_interval = Arc::new(Mutex::new(DEFAULT_KEEPALIVE_INTERVAL));
+        let trace_messages = Arc::new(Mutex::new(None::<messages::MessageTracing>));
+        let last_message = Arc::new(Mutex::new(js_sys::Date::now()));
 
         let subscribers = Arc::new(Mutex::new(Vec::<CallBack>::new()));
         let broadcast = {
@@ -109,13 +139,72 @@ impl WebsocketService {
             }
         });
 
+        // browsers don't expose ping frames, so the server is pinged at the application level to
+        // notice a dead connection without waiting for the OS to time it out
+        {
+            let mut tx = in_tx.clone();
+            let status = status.clone();
+            let set_status = set_status.clone();
+            let keepalive_interval = keepalive_interval.clone();
+            let last_message = last_message.clone();
+            spawn_local(async move {
+                loop {
+                    let interval = *keepalive_interval.lock().expect("poisoned mutex");
+                    sleep(interval).await;
+                    let current = status.lock().expect("poisoned mutex").clone();
+                    if let WebsocketStatus::Disconnected(_) | WebsocketStatus::Error(_) = current {
+                        break
+                    }
+                    let elapsed =
+                        js_sys::Date::now() - *last_message.lock().expect("poisoned mutex");
+                    if elapsed > (interval * MISSED_KEEPALIVES) as f64 {
+                        set_status(WebsocketStatus::Disconnected(CloseEvent {
+                            code: KEEPALIVE_CLOSE_CODE,
+                            reason: "the server stopped responding".to_owned(),
+                            was_clean: false,
+                        }));
+                        break
+                    }
+                    let ping = messages::Response {
+                        id: KEEPALIVE_ID.to_owned(),
+                        content: messages::ResponseContent::Ping {},
+                    };
+                    let sent = match serde_json::to_string(&ping) {
+                        Ok(ping) => tx.send(ping).await.is_ok(),
+                        Err(_) => false,
+                    };
+                    if !sent {
+                        break
+                    }
+                }
+            });
+        }
+
         {
+            let max_message_size = max_message_size.clone();
+            let trace_messages = trace_messages.clone();
             spawn_local(async move {
                 while let Some(msg) = read.next().await {
+                    *last_message.lock().expect("poisoned mutex") = js_sys::Date::now();
                     set_status(WebsocketStatus::Connected);
+                    let limit = *max_message_size.lock().expect("poisoned mutex");
                     match msg {
+                        Ok(Message::Text(data)) if limit.map_or(false, |l| data.len() > l) => {
+                            set_status(WebsocketStatus::Error(format!(
+                                "received a message too large ({} bytes, max {})",
+                                data.len(),
+                                limit.unwrap_or_default(),
+                            )));
+                        }
                         Ok(Message::Text(data)) => {
+                            if let Some(tracing) = *trace_messages.lock().expect("poisoned mutex") {
+                                console_log!("ws received: {}", tracing.render(&data));
+                            }
                             match serde_json::from_str::<messages::Request>(&data) {
+                                Ok(messages::Request {
+                                    content: messages::RequestContent::Pong {},
+                                    ..
+                                }) => {}
                                 Ok(req) => {
                                     broadcast(WebsocketEvent::Message(req));
                                 }
@@ -144,7 +233,15 @@ impl WebsocketService {
             });
         }
 
-        Ok(Self { id, tx: in_tx, status, subscribers })
+        Ok(Self {
+            id,
+            tx: in_tx,
+            status,
+            subscribers,
+            max_message_size,
+            keepalive_interval,
+            trace_messages,
+        })
     }
 
     #[allow(dead_code)] // FIXME: incorrect lint issue
@@ -152,10 +249,64 @@ impl WebsocketService {
         self.id
     }
 
+    /// Sends `msg` to the server, the returned future doesn't borrow the service so the lock on it
+    /// can be released before awaiting
     #[allow(dead_code)] // FIXME: incorrect lint issue
-    pub async fn send(&mut self, msg: messages::Response) -> Result<(), WebsocketError> {
-        self.tx.send(serde_json::to_string(&msg)?).await?;
-        Ok(())
+    pub fn send(
+        &self,
+        msg: messages::Response,
+    ) -> impl Future<Output = Result<(), WebsocketError>> {
+        let data = self.encode(&msg);
+        let mut tx = self.tx.clone();
+        async move {
+            tx.send(data?).await?;
+            Ok(())
+        }
+    }
+
+    fn encode(&self, msg: &messages::Response) -> Result<String, WebsocketError> {
+        let data = serde_json::to_string(msg)?;
+        if let Some(limit) = *self.max_message_size.lock().expect("poisoned mutex") {
+            if data.len() > limit {
+                let err = WebsocketError::Protocol(format!(
+                    "response too large ({} bytes, max {})",
+                    data.len(),
+                    limit
+                ));
+                self.set_status(WebsocketStatus::Error(err.to_string()));
+                return Err(err)
+            }
+        }
+        if let Some(tracing) = *self.trace_messages.lock().expect("poisoned mutex") {
+            console_log!("ws sent: {}", tracing.render(&data));
+        }
+        Ok(data)
+    }
+
+    /// Mirrors the server's limit, see `FrontendConfig::max_message_size`
+    #[allow(dead_code)] // FIXME: incorrect lint issue
+    pub fn set_max_message_size(&mut self, size: Option<usize>) {
+        *self.max_message_size.lock().expect("poisoned mutex") = size;
+    }
+
+    /// Uses the server's interval, see `FrontendConfig::keepalive_interval`
+    #[allow(dead_code)] // FIXME: incorrect lint issue
+    pub fn set_keepalive_interval(&mut self, interval: Option<u64>) {
+        *self.keepalive_interval.lock().expect("poisoned mutex") =
+            interval.unwrap_or(DEFAULT_KEEPALIVE_INTERVAL);
+    }
+
+    /// Mirrors the server's setting, see `FrontendConfig::trace_messages`
+    #[allow(dead_code)] // FIXME: incorrect lint issue
+    pub fn set_trace_messages(&mut self, tracing: Option<messages::MessageTracing>) {
+        *self.trace_messages.lock().expect("poisoned mutex") = tracing;
+    }
+
+    fn set_status(&self, status: WebsocketStatus) {
+        *self.status.lock().expect("poisoned mutex") = status.clone();
+        for sub in self.subscribers.lock().expect("poisoned mutex").iter() {
+            sub.emit(WebsocketEvent::Status(status.clone()));
+        }
     }
 
     #[allow(dead_code)] // FIXME: incorrect lint issue
@@ -3,25 +3,87 @@ use ethers::core::{
     abi::Address,
     types::{
         transaction::{eip2718::TypedTransaction, eip712::TypedData},
-        H256,
+        Bytes, H256,
     },
 };
-use ethers_signers_browser_frontend::ws::messages::ChainInfo;
-use log::{error, info, warn};
+use ethers_signers_browser_frontend::ws::messages::{
+    ChainInfo, FrontendConfig, TypedDataVersion, ALREADY_CONNECTED_REASON, REPLACED_REASON,
+    SERVER_SHUTDOWN_REASON,
+};
 use rand::distributions::{Alphanumeric, DistString};
-use std::{collections::HashMap, sync::mpsc};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    time::Duration,
+};
+use tokio::sync::oneshot;
+use tracing::{debug, error, info, warn};
+
+/// How long to wait before sending a request again when the browser's wallet is still loading
+const WALLET_LOADING_RETRY: Duration = Duration::from_millis(500);
+/// How long a resumed browser has to report its status before the pending requests are sent anyway
+const RESUMED_STATUS_TIMEOUT: Duration = Duration::from_secs(2);
+/// Why a transaction fails when the browser sending it is replaced by a new page
+const INTERRUPTED_TRANSACTION_ERROR: &str =
+    "the browser disconnected while sending the transaction, it might have been broadcast already";
 
 /// Comm sends this message to sessions
 #[derive(Clone, Message)]
 #[rtype(result = "()")]
 pub(super) enum WSRequest {
-    Init { id: String, chain_id: u64, chains: Option<HashMap<u64, ChainInfo>> },
-    Accounts { id: String },
-    SignBinaryMessage { id: String, address: Address, message: H256 },
-    SignTextMessage { id: String, address: Address, message: String },
-    SignTransaction { id: String, transaction: TypedTransaction },
-    SignTypedData { id: String, address: Address, typed_data: TypedData },
-    Close { reason: String },
+    Init {
+        id: String,
+        chain_id: u64,
+        chains: Option<HashMap<u64, ChainInfo>>,
+        config: FrontendConfig,
+    },
+    Accounts {
+        id: String,
+    },
+    SignBinaryMessage {
+        id: String,
+        address: Address,
+        message: H256,
+        original: Option<Bytes>,
+    },
+    SignTextMessage {
+        id: String,
+        address: Address,
+        message: String,
+    },
+    SignTransaction {
+        id: String,
+        transaction: TypedTransaction,
+    },
+    SendTransaction {
+        id: String,
+        transaction: TypedTransaction,
+    },
+    SignTypedData {
+        id: String,
+        address: Address,
+        typed_data: TypedData,
+        version: TypedDataVersion,
+    },
+    Call {
+        id: String,
+        transaction: TypedTransaction,
+    },
+    RawRequest {
+        id: String,
+        method: String,
+        params: serde_json::Value,
+    },
+    /// The server stopped waiting for the request `id`, which was already sent
+    Cancel {
+        id: String,
+    },
+    Close {
+        reason: String,
+    },
 }
 
 type WebsocketClient = Recipient<WSRequest>;
@@ -30,30 +92,226 @@ type WebsocketClient = Recipient<WSRequest>;
 #[derive(Clone, Message)]
 #[rtype(result = "()")]
 pub(super) enum WSReply {
-    Connect { client: WebsocketClient },
-    Init { id: String, client: WebsocketClient },
-    Accounts { id: String, client: WebsocketClient, accounts: Vec<Address> },
-    MessageSignature { id: String, client: WebsocketClient, signature: String },
-    TransactionSignature { id: String, client: WebsocketClient, signature: String },
-    Error { id: String, client: WebsocketClient, error: String },
-    Disconnect { client: WebsocketClient },
+    Connect {
+        client: WebsocketClient,
+        session: Option<String>,
+    },
+    Init {
+        id: String,
+        client: WebsocketClient,
+    },
+    Accounts {
+        id: String,
+        client: WebsocketClient,
+        accounts: Vec<Address>,
+    },
+    MessageSignature {
+        id: String,
+        client: WebsocketClient,
+        signature: String,
+    },
+    TransactionSignature {
+        id: String,
+        client: WebsocketClient,
+        signature: String,
+    },
+    TransactionHash {
+        id: String,
+        client: WebsocketClient,
+        hash: String,
+    },
+    CallResult {
+        id: String,
+        client: WebsocketClient,
+        data: String,
+    },
+    RawResult {
+        id: String,
+        client: WebsocketClient,
+        result: serde_json::Value,
+    },
+    Error {
+        id: String,
+        client: WebsocketClient,
+        error: String,
+        code: Option<i64>,
+        data: Option<serde_json::Value>,
+    },
+    WalletLoading {
+        id: String,
+        client: WebsocketClient,
+    },
+    NoWallet {
+        id: String,
+        client: WebsocketClient,
+        error: String,
+    },
+    Status {
+        client: WebsocketClient,
+        chain_id: Option<u64>,
+        accounts: Option<Vec<Address>>,
+    },
+    Disconnect {
+        client: WebsocketClient,
+    },
 }
 
 /// Server sends this message to comm
-#[derive(Clone, Message, Debug)]
+#[derive(Message, Debug)]
 #[rtype(result = "()")]
 pub(super) struct AsyncRequest {
     pub id: String,
     pub content: AsyncRequestContent,
+    /// Where to send the response
+    pub reply_to: oneshot::Sender<AsyncResponse>,
+    /// Whether the request comes from the JSON-RPC endpoint, whose callers aren't affected by
+    /// `AsyncCancelPending`
+    pub rpc: bool,
+}
+
+/// Server sends this message to comm to receive a `QueueState` on every change
+#[derive(Message)]
+#[rtype(result = "()")]
+pub(super) struct AsyncSubscribe {
+    pub sender: mpsc::Sender<QueueState>,
+}
+
+/// Whether the browser can receive requests
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BrowserState {
+    Disconnected,
+    /// Connected, waiting for the answer to `Init` (e.g. the user switching chain)
+    Initializing,
+    Ready,
+}
+
+/// A snapshot of the requests waiting for the browser, see `BrowserSigner::queue_updates`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QueueState {
+    pub browser: BrowserState,
+    /// The number of prompting requests, including the ones in flight
+    pub queued: usize,
+    /// The id of the prompting request currently shown by the browser (the oldest one when
+    /// `ServerOptions::max_concurrent_prompts` allows several)
+    pub in_flight: Option<String>,
+    /// The number of non-prompting requests (e.g. accounts) not answered yet
+    pub reads: usize,
 }
 
+/// Server sends this message to comm before stopping, to close the browser connection cleanly
+#[derive(Message)]
+#[rtype(result = "()")]
+pub(super) struct AsyncShutdown {}
+
+/// Server sends this message to comm when it stopped waiting for the reply to a request
+#[derive(Clone, Message, Debug)]
+#[rtype(result = "()")]
+pub(super) struct AsyncCancel {
+    pub id: String,
+    /// Whether to answer the request with `AsyncResponseContent::Cancelled`, which isn't needed
+    /// when the waiter already gave up (e.g. on timeout)
+    pub reply: bool,
+}
+
+/// Cancels the request `id` when dropped, unless disarmed once answered, so a request whose waiter
+/// gave up (e.g. timed out or dropped its future) isn't sent to the next browser to connect
+pub(super) struct CancelGuard {
+    comm: Addr<CommServer>,
+    id: Option<String>,
+}
+
+impl CancelGuard {
+    pub fn new(comm: Addr<CommServer>, id: String) -> Self {
+        CancelGuard { comm, id: Some(id) }
+    }
+
+    pub fn disarm(&mut self) {
+        self.id = None;
+    }
+}
+
+impl Drop for CancelGuard {
+    fn drop(&mut self) {
+        if let Some(id) = self.id.take() {
+            self.comm.do_send(AsyncCancel { id, reply: false });
+        }
+    }
+}
+
+/// Server sends this message to comm to cancel all its requests (not the JSON-RPC ones), see
+/// `AsyncCancel`
+#[derive(Clone, Message, Debug)]
+#[rtype(result = "()")]
+pub(super) struct AsyncCancelPending {}
+
 #[derive(Clone, Debug)]
 pub(super) enum AsyncRequestContent {
     Accounts {},
-    SignTextMessage { address: Address, message: String },
-    SignBinaryMessage { address: Address, message: H256 },
-    SignTransaction { transaction: TypedTransaction },
-    SignTypedData { address: Address, typed_data: TypedData },
+    SignTextMessage {
+        address: Address,
+        message: String,
+    },
+    SignBinaryMessage {
+        address: Address,
+        message: H256,
+        original: Option<Bytes>,
+    },
+    SignTransaction {
+        transaction: TypedTransaction,
+    },
+    SendTransaction {
+        transaction: TypedTransaction,
+    },
+    SignTypedData {
+        address: Address,
+        typed_data: TypedData,
+        version: TypedDataVersion,
+    },
+    Call {
+        transaction: TypedTransaction,
+    },
+    /// Might prompt, depending on the method
+    RawRequest {
+        method: String,
+        params: serde_json::Value,
+    },
+}
+
+impl AsyncRequestContent {
+    /// Whether the wallet shows a prompt for this request, only those are serialized: the others
+    /// don't wait for the user. `Accounts` (`eth_requestAccounts`) can still prompt when the
+    /// wallet isn't connected, it's exempt as connecting must not wait behind signatures
+    fn prompts_user(&self) -> bool {
+        !matches!(self, Self::Accounts {} | Self::Call { .. })
+    }
+}
+
+impl AsyncRequest {
+    fn to_ws_request(&self) -> WSRequest {
+        let id = self.id.clone();
+        match self.content.clone() {
+            AsyncRequestContent::Accounts {} => WSRequest::Accounts { id },
+            AsyncRequestContent::SignTextMessage { address, message } => {
+                WSRequest::SignTextMessage { id, address, message }
+            }
+            AsyncRequestContent::SignBinaryMessage { address, message, original } => {
+                WSRequest::SignBinaryMessage { id, address, message, original }
+            }
+            AsyncRequestContent::SignTransaction { transaction } => {
+                WSRequest::SignTransaction { id, transaction }
+            }
+            AsyncRequestContent::SendTransaction { transaction } => {
+                WSRequest::SendTransaction { id, transaction }
+            }
+            AsyncRequestContent::SignTypedData { address, typed_data, version } => {
+                WSRequest::SignTypedData { id, address, typed_data, version }
+            }
+            AsyncRequestContent::Call { transaction } => WSRequest::Call { id, transaction },
+            AsyncRequestContent::RawRequest { method, params } => {
+                WSRequest::RawRequest { id, method, params }
+            }
+        }
+    }
 }
 
 /// Comm sends this message to the server
@@ -68,19 +326,49 @@ pub(super) enum AsyncResponseContent {
     Accounts { accounts: Vec<Address> },
     MessageSignature { signature: String },
     TransactionSignature { signature: String },
-    Error { error: String },
+    TransactionHash { hash: String },
+    CallResult { data: String },
+    RawResult { result: serde_json::Value },
+    Error { error: String, code: Option<i64>, data: Option<serde_json::Value> },
+    NoWallet { error: String },
+    Cancelled {},
 }
 
 /// `CommServer` manages clients and forward server requests to them.
 #[derive(Debug)]
 pub(super) struct CommServer {
-    server: mpsc::Sender<AsyncResponse>,
     chain_id: u64,
     chains: Option<HashMap<u64, ChainInfo>>,
+    config: FrontendConfig,
     client: Option<WebsocketClient>,
+    /// Mirrors `client.is_some()` for the server
+    connected: Arc<AtomicBool>,
+    /// The id of the last successful init, a reconnecting browser presenting it skips init
+    session: Option<String>,
+    /// Set when a session was resumed until the browser reports its state, which might have
+    /// changed while it was disconnected, nothing is sent to it meanwhile
+    resumed: bool,
     init_status: InitStatus,
-    is_handling_request: bool,
+    /// How many of the first `pending_messages` were sent to the browser
+    handling: usize,
+    /// How many prompting requests can be sent to the browser at once
+    max_handling: usize,
+    /// Whether a new tab replaces the ready one instead of being rejected
+    replace_client: bool,
+    /// How many times a request is retried while the wallet loads before failing it
+    max_wallet_loading_retries: usize,
+    /// How many times each request was retried while the wallet loads
+    wallet_loading_retries: HashMap<String, usize>,
     pending_messages: Vec<AsyncRequest>,
+    /// Requests which don't prompt the user, sent as soon as possible instead of waiting for
+    /// `pending_messages`, see `AsyncRequestContent::prompts_user`
+    pending_reads: Vec<AsyncRequest>,
+    /// The `SendTransaction` requests the browser was handling when it went away. A resumed
+    /// session attaches them to the ongoing wallet call but a new page would broadcast them again
+    interrupted_transactions: HashSet<String>,
+    observers: Vec<mpsc::Sender<QueueState>>,
+    /// The last state sent to `observers`, to only notify them of changes
+    last_state: Option<QueueState>,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -92,21 +380,53 @@ enum InitStatus {
 
 impl CommServer {
     pub fn new(
-        server: mpsc::Sender<AsyncResponse>,
         chain_id: u64,
         chains: Option<HashMap<u64, ChainInfo>>,
+        config: FrontendConfig,
+        connected: Arc<AtomicBool>,
     ) -> CommServer {
         CommServer {
             client: None,
-            server,
+            connected,
+            session: None,
+            resumed: false,
             chain_id,
             chains,
+            config,
             init_status: InitStatus::None,
-            is_handling_request: false,
+            handling: 0,
+            max_handling: 1,
+            replace_client: false,
+            max_wallet_loading_retries: usize::MAX,
+            wallet_loading_retries: HashMap::new(),
             pending_messages: vec![],
+            pending_reads: vec![],
+            interrupted_transactions: HashSet::new(),
+            observers: vec![],
+            last_state: None,
         }
     }
 
+    /// Lets up to `max` prompting requests reach the browser at once instead of one at a time,
+    /// for wallets which queue their prompts themselves
+    pub fn with_concurrent_prompts(mut self, max: usize) -> Self {
+        self.max_handling = max.max(1);
+        self
+    }
+
+    /// Lets a new tab take over from the ready one, which is closed, instead of rejecting it
+    pub fn with_client_replacement(mut self, replace: bool) -> Self {
+        self.replace_client = replace;
+        self
+    }
+
+    /// Fails requests with `AsyncResponseContent::NoWallet` once the wallet is still loading after
+    /// `max` retries
+    pub fn with_max_wallet_loading_retries(mut self, max: usize) -> Self {
+        self.max_wallet_loading_retries = max;
+        self
+    }
+
     fn gen_id(&self) -> String {
         Alphanumeric.sample_string(&mut rand::thread_rng(), 16)
     }
@@ -121,8 +441,9 @@ impl CommServer {
         self.init_status == InitStatus::Done
     }
 
+    /// Whether requests can be sent to the browser, see `resumed`
     fn has_ready_client(&self) -> bool {
-        self.client.is_some() && self.is_client_init()
+        self.client.is_some() && self.is_client_init() && !self.resumed
     }
 
     fn kick_client(&self, client: &Recipient<WSRequest>, reason: &str) {
@@ -134,44 +455,60 @@ impl CommServer {
         if let Some(ref addr) = self.client {
             self.kick_client(addr, reason);
             self.cleanup_client();
+            self.session = None;
         }
     }
 
+    fn set_client(&mut self, client: Option<WebsocketClient>) {
+        self.connected.store(client.is_some(), Ordering::SeqCst);
+        self.client = client;
+    }
+
     fn cleanup_client(&mut self) {
-        self.client = None;
+        self.set_client(None);
         self.init_status = InitStatus::None;
-        self.is_handling_request = false;
+        let interrupted = self
+            .pending_messages
+            .iter()
+            .take(self.handling)
+            .filter(|msg| matches!(msg.content, AsyncRequestContent::SendTransaction { .. }));
+        self.interrupted_transactions.extend(interrupted.map(|msg| msg.id.clone()));
+        self.handling = 0;
     }
 }
 
 impl CommServer {
+    /// Sends the next pending messages, as long as the browser handles less than `max_handling`
     fn send_pending_message(&mut self) {
-        if self.is_handling_request || !self.has_ready_client() {
+        if !self.has_ready_client() {
             return
         }
-        if let Some(msg) = self.pending_messages.first() {
-            self.is_handling_request = true;
-            self.client.as_ref().unwrap().do_send({
-                let AsyncRequest { id, content } = msg.clone();
-                match content {
-                    AsyncRequestContent::Accounts {} => WSRequest::Accounts { id },
-                    AsyncRequestContent::SignTextMessage { address, message } => {
-                        WSRequest::SignTextMessage { id, address, message }
-                    }
-                    AsyncRequestContent::SignBinaryMessage { address, message } => {
-                        WSRequest::SignBinaryMessage { id, address, message }
-                    }
-                    AsyncRequestContent::SignTransaction { transaction } => {
-                        WSRequest::SignTransaction { id, transaction }
-                    }
-                    AsyncRequestContent::SignTypedData { address, typed_data } => {
-                        WSRequest::SignTypedData { id, address, typed_data }
-                    }
-                }
-            });
+        while self.handling < self.max_handling {
+            let msg = match self.pending_messages.get(self.handling) {
+                Some(msg) => msg,
+                None => return,
+            };
+            self.client.as_ref().unwrap().do_send(msg.to_ws_request());
+            self.handling += 1;
         }
     }
 
+    /// Sends every pending read, used when a client becomes ready
+    fn send_pending_reads(&self) {
+        if !self.has_ready_client() {
+            return
+        }
+        for msg in &self.pending_reads {
+            self.client.as_ref().unwrap().do_send(msg.to_ws_request());
+        }
+    }
+
+    /// Sends both lanes, used when a client becomes ready
+    fn send_pending(&mut self) {
+        self.send_pending_reads();
+        self.send_pending_message();
+    }
+
     fn handle_init(&mut self, id: String) {
         match self.init_status.clone() {
             InitStatus::Pending { id: original_id } => {
@@ -180,14 +517,15 @@ impl CommServer {
                     return
                 }
                 self.init_status = InitStatus::Done;
-                self.send_pending_message();
+                self.session = Some(id);
+                self.send_pending();
             }
             _ => self.kick_current_client("init already done"),
         }
     }
 
-    fn send_server_reply(&mut self, reply: AsyncResponse) {
-        match self.server.send(reply) {
+    fn send_server_reply(&mut self, msg: AsyncRequest, reply: AsyncResponse) {
+        match msg.reply_to.send(reply) {
             Ok(_) => {}
             Err(e) => {
                 error!("failed to send response to server: {:?}", e);
@@ -196,6 +534,7 @@ impl CommServer {
     }
 
     fn handle_response(&mut self, id: String, content: AsyncResponseContent) {
+        self.wallet_loading_retries.remove(&id);
         if !self.is_client_init() {
             match self.init_status.clone() {
                 InitStatus::Pending { id: original_id } => {
@@ -203,15 +542,24 @@ impl CommServer {
                         self.kick_current_client("invalid id on init");
                         return
                     }
-                    if let AsyncResponseContent::Error { .. } = content {
-                        if let Some(msg) = self.pending_messages.first() {
+                    if let AsyncResponseContent::Error { .. } |
+                    AsyncResponseContent::NoWallet { .. } = content
+                    {
+                        let lane = if self.pending_messages.is_empty() {
+                            &mut self.pending_reads
+                        } else {
+                            &mut self.pending_messages
+                        };
+                        if !lane.is_empty() {
                             // Basically we cheat a little bit to be able to send the error message
-                            // to the server despite init being sort of
-                            // implicit
-                            self.send_server_reply(AsyncResponse {
-                                id: msg.id.clone(),
-                                content: content.clone(),
-                            });
+                            // to the server despite init being sort of implicit, the request is
+                            // answered so it's gone
+                            let msg = lane.remove(0);
+                            let id = msg.id.clone();
+                            self.send_server_reply(
+                                msg,
+                                AsyncResponse { id, content: content.clone() },
+                            );
                         }
                     }
                     self.kick_current_client(format!("failed init: {:?}", content).as_str());
@@ -223,22 +571,189 @@ impl CommServer {
             return
         }
 
-        if let Some(msg) = self.pending_messages.first() {
-            if msg.id != id {
-                print!("invalid response id ({} vs {}), ignore and send the next one", msg.id, id);
-            } else {
-                self.pending_messages.remove(0);
-                self.send_server_reply(AsyncResponse { id, content });
+        if let Some(index) = self.pending_reads.iter().position(|msg| msg.id == id) {
+            let msg = self.pending_reads.remove(index);
+            self.send_server_reply(msg, AsyncResponse { id, content });
+            return
+        }
+
+        match self.pending_messages[..self.handling].iter().position(|msg| msg.id == id) {
+            Some(index) => {
+                let msg = self.pending_messages.remove(index);
+                self.handling -= 1;
+                self.send_server_reply(msg, AsyncResponse { id, content });
             }
-        } else {
-            print!("no pending message, ignore and send the next one");
+            None => warn!("invalid response id ({}), ignore it", id),
         }
 
-        self.is_handling_request = false;
         self.send_pending_message();
     }
 
+    /// Whether `id` is the init or a request the browser was given
+    fn is_awaiting_reply(&self, id: &str) -> bool {
+        matches!(&self.init_status, InitStatus::Pending { id: init_id } if init_id == id) ||
+            self.pending_reads.iter().any(|msg| msg.id == id) ||
+            self.pending_messages[..self.handling].iter().any(|msg| msg.id == id)
+    }
+
+    /// Sends the request `id` again, used when the wallet was still loading the first time
+    fn retry_request(&mut self, id: String) {
+        match self.init_status.clone() {
+            InitStatus::Pending { id: init_id } if init_id == id => {
+                if let Some(client) = self.client.clone() {
+                    self.send_init(&client, id);
+                }
+            }
+            _ => {
+                if let Some(msg) = self.pending_reads.iter().find(|msg| msg.id == id) {
+                    if let (true, Some(client)) = (self.has_ready_client(), self.client.as_ref()) {
+                        client.do_send(msg.to_ws_request());
+                    }
+                } else if let Some(msg) =
+                    self.pending_messages[..self.handling].iter().find(|msg| msg.id == id)
+                {
+                    if let (true, Some(client)) = (self.has_ready_client(), self.client.as_ref()) {
+                        client.do_send(msg.to_ws_request());
+                    }
+                }
+            }
+        }
+    }
+
+    /// Fails the transactions a previous browser was sending instead of risking a second
+    /// broadcast, see `interrupted_transactions`
+    fn fail_interrupted_transactions(&mut self) {
+        for id in std::mem::take(&mut self.interrupted_transactions) {
+            if let Some(index) = self.pending_messages.iter().position(|msg| msg.id == id) {
+                let msg = self.pending_messages.remove(index);
+                let content = AsyncResponseContent::Error {
+                    error: INTERRUPTED_TRANSACTION_ERROR.to_owned(),
+                    code: None,
+                    data: None,
+                };
+                self.send_server_reply(msg, AsyncResponse { id, content });
+            }
+        }
+    }
+
+    fn start_init(&mut self, client: &WebsocketClient) {
+        let id = self.gen_id();
+        self.init_status = InitStatus::Pending { id: id.clone() };
+        self.send_init(client, id);
+    }
+
+    /// Sends the pending requests once a resumed browser reports its state, initializing it again
+    /// first if it's on a different chain
+    fn handle_status(&mut self, chain_id: Option<u64>, accounts: Option<Vec<Address>>) {
+        debug!("browser status: chain {:?}, accounts {:?}", chain_id, accounts);
+        if !self.resumed || !self.is_client_init() {
+            return
+        }
+        self.resumed = false;
+        if chain_id.map_or(true, |chain_id| chain_id == self.chain_id) {
+            self.send_pending();
+            return
+        }
+        info!("browser switched chain while disconnected, initializing it again");
+        if let Some(client) = self.client.clone() {
+            self.session = None;
+            self.start_init(&client);
+        }
+    }
+
+    fn send_init(&self, client: &WebsocketClient, id: String) {
+        client.do_send(WSRequest::Init {
+            id,
+            chain_id: self.chain_id,
+            chains: self.chains.clone(),
+            config: self.config.clone(),
+        });
+    }
+
+    /// Drops the request `id`, moving on to the next one if it was being handled. The browser is
+    /// told about requests it already received (it might still answer them, which is then
+    /// ignored) and `reply` answers the request with `AsyncResponseContent::Cancelled`
+    fn cancel_request(&mut self, id: String, reply: bool) {
+        self.wallet_loading_retries.remove(&id);
+        let (msg, sent) = match self.pending_reads.iter().position(|msg| msg.id == id) {
+            Some(index) => (self.pending_reads.remove(index), self.has_ready_client()),
+            None => match self.pending_messages.iter().position(|msg| msg.id == id) {
+                Some(index) => {
+                    let sent = index < self.handling;
+                    (self.pending_messages.remove(index), sent)
+                }
+                None => return,
+            },
+        };
+        if let (true, Some(client)) = (sent, self.client.as_ref()) {
+            client.do_send(WSRequest::Cancel { id: id.clone() });
+        }
+        let prompts_user = msg.content.prompts_user();
+        if reply {
+            self.send_server_reply(
+                msg,
+                AsyncResponse { id, content: AsyncResponseContent::Cancelled {} },
+            );
+        }
+        if sent && prompts_user {
+            self.handling -= 1;
+            self.send_pending_message();
+        }
+    }
+
+    /// Cancels every request made by the server, from the last so the next ones aren't sent in
+    /// between. The JSON-RPC ones are left alone, their callers didn't ask for it
+    fn cancel_pending(&mut self) {
+        let ids = self
+            .pending_messages
+            .iter()
+            .chain(self.pending_reads.iter())
+            .filter(|msg| !msg.rpc)
+            .map(|msg| msg.id.clone())
+            .collect::<Vec<_>>();
+        for id in ids.into_iter().rev() {
+            self.cancel_request(id, true);
+        }
+    }
+
+    fn queue_state(&self) -> QueueState {
+        QueueState {
+            browser: match (&self.client, &self.init_status) {
+                (None, _) => BrowserState::Disconnected,
+                (Some(_), InitStatus::Done) if !self.resumed => BrowserState::Ready,
+                (Some(_), _) => BrowserState::Initializing,
+            },
+            queued: self.pending_messages.len(),
+            in_flight: self
+                .pending_messages
+                .first()
+                .filter(|_| self.handling > 0)
+                .map(|msg| msg.id.clone()),
+            reads: self.pending_reads.len(),
+        }
+    }
+
+    /// Sends the current state to the observers if it changed, forgetting the ones which are gone
+    fn notify_observers(&mut self) {
+        if self.observers.is_empty() {
+            return
+        }
+        let state = self.queue_state();
+        if self.last_state.as_ref() == Some(&state) {
+            return
+        }
+        self.observers.retain(|observer| observer.send(state.clone()).is_ok());
+        self.last_state = Some(state);
+    }
+
     fn queue_pending_message(&mut self, msg: AsyncRequest) {
+        if !msg.content.prompts_user() {
+            if let (true, Some(client)) = (self.has_ready_client(), self.client.as_ref()) {
+                client.do_send(msg.to_ws_request());
+            }
+            self.pending_reads.push(msg);
+            return
+        }
         self.pending_messages.push(msg);
         self.send_pending_message();
     }
@@ -252,18 +767,57 @@ impl Actor for CommServer {
 impl Handler<WSReply> for CommServer {
     type Result = ();
 
-    fn handle(&mut self, msg: WSReply, _: &mut Context<Self>) -> Self::Result {
+    fn handle(&mut self, msg: WSReply, ctx: &mut Context<Self>) -> Self::Result {
+        self.handle_reply(msg, ctx);
+        self.notify_observers();
+    }
+}
+
+impl CommServer {
+    fn handle_reply(&mut self, msg: WSReply, ctx: &mut Context<Self>) {
         match msg {
-            WSReply::Connect { client } => {
+            WSReply::Connect { client, session } => {
                 info!("Browser connected");
-                self.client = Some(client.clone());
-                let id = self.gen_id();
-                self.init_status = InitStatus::Pending { id: id.clone() };
-                client.do_send(WSRequest::Init {
-                    id,
-                    chain_id: self.chain_id,
-                    chains: self.chains.clone(),
-                });
+                let resuming = session.is_some() && session == self.session;
+                // only the tab which did the init knows the session, resuming replaces its
+                // previous (likely dead) connection
+                let occupied = self.client.is_some() && self.is_client_init();
+                if !resuming && occupied && !self.is_same_client(&client) {
+                    if !self.replace_client {
+                        self.kick_client(&client, ALREADY_CONNECTED_REASON);
+                        return
+                    }
+                    self.kick_current_client(REPLACED_REASON);
+                }
+                if resuming {
+                    info!("Browser session resumed");
+                    self.resumed = true;
+                    self.interrupted_transactions.clear();
+                    self.set_client(Some(client.clone()));
+                    self.init_status = InitStatus::Done;
+                    self.handling = 0;
+                    // in case the status got lost, the requests shouldn't wait forever
+                    ctx.run_later(RESUMED_STATUS_TIMEOUT, move |act, _| {
+                        if act.resumed && act.is_same_client(&client) {
+                            warn!("resumed browser didn't report its status, sending requests");
+                            act.resumed = false;
+                            act.send_pending();
+                            act.notify_observers();
+                        }
+                    });
+                    return
+                }
+                self.resumed = false;
+                self.fail_interrupted_transactions();
+                self.set_client(Some(client.clone()));
+                self.start_init(&client);
+            }
+            WSReply::Status { client, chain_id, accounts } => {
+                if !self.is_same_client(&client) {
+                    self.kick_client(&client, "invalid client");
+                    return
+                }
+                self.handle_status(chain_id, accounts);
             }
             WSReply::Disconnect { client } => {
                 info!("Browser disconnected");
@@ -300,12 +854,61 @@ impl Handler<WSReply> for CommServer {
                 }
                 self.handle_response(id, AsyncResponseContent::TransactionSignature { signature });
             }
-            WSReply::Error { id, client, error } => {
+            WSReply::TransactionHash { id, client, hash } => {
+                if !self.is_same_client(&client) {
+                    self.kick_client(&client, "invalid client");
+                    return
+                }
+                self.handle_response(id, AsyncResponseContent::TransactionHash { hash });
+            }
+            WSReply::CallResult { id, client, data } => {
+                if !self.is_same_client(&client) {
+                    self.kick_client(&client, "invalid client");
+                    return
+                }
+                self.handle_response(id, AsyncResponseContent::CallResult { data });
+            }
+            WSReply::RawResult { id, client, result } => {
+                if !self.is_same_client(&client) {
+                    self.kick_client(&client, "invalid client");
+                    return
+                }
+                self.handle_response(id, AsyncResponseContent::RawResult { result });
+            }
+            WSReply::Error { id, client, error, code, data } => {
+                if !self.is_same_client(&client) {
+                    self.kick_client(&client, "invalid client");
+                    return
+                }
+                self.handle_response(id, AsyncResponseContent::Error { error, code, data });
+            }
+            WSReply::WalletLoading { id, client } => {
                 if !self.is_same_client(&client) {
                     self.kick_client(&client, "invalid client");
                     return
                 }
-                self.handle_response(id, AsyncResponseContent::Error { error });
+                if !self.is_awaiting_reply(&id) {
+                    warn!("invalid wallet loading id ({}), ignore it", id);
+                    return
+                }
+                let retries = self.wallet_loading_retries.entry(id.clone()).or_insert(0);
+                if *retries >= self.max_wallet_loading_retries {
+                    let error = format!("the wallet was still loading after {} retries", retries);
+                    self.handle_response(id, AsyncResponseContent::NoWallet { error });
+                    return
+                }
+                *retries += 1;
+                ctx.run_later(WALLET_LOADING_RETRY, move |act, _| {
+                    act.retry_request(id);
+                    act.notify_observers();
+                });
+            }
+            WSReply::NoWallet { id, client, error } => {
+                if !self.is_same_client(&client) {
+                    self.kick_client(&client, "invalid client");
+                    return
+                }
+                self.handle_response(id, AsyncResponseContent::NoWallet { error });
             }
         }
     }
@@ -317,5 +920,457 @@ impl Handler<AsyncRequest> for CommServer {
 
     fn handle(&mut self, msg: AsyncRequest, _: &mut Context<Self>) {
         self.queue_pending_message(msg);
+        self.notify_observers();
+    }
+}
+
+impl Handler<AsyncCancel> for CommServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: AsyncCancel, _: &mut Context<Self>) {
+        self.cancel_request(msg.id, msg.reply);
+        self.notify_observers();
+    }
+}
+
+impl Handler<AsyncCancelPending> for CommServer {
+    type Result = ();
+
+    fn handle(&mut self, _: AsyncCancelPending, _: &mut Context<Self>) {
+        self.cancel_pending();
+        self.notify_observers();
+    }
+}
+
+impl Handler<AsyncShutdown> for CommServer {
+    type Result = ();
+
+    fn handle(&mut self, _: AsyncShutdown, _: &mut Context<Self>) {
+        if let Some(client) = &self.client {
+            info!("Closing the browser connection");
+            client.do_send(WSRequest::Close { reason: SERVER_SHUTDOWN_REASON.to_owned() });
+        }
+    }
+}
+
+impl Handler<AsyncSubscribe> for CommServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: AsyncSubscribe, _: &mut Context<Self>) {
+        // the current state first, then every change
+        let state = self.queue_state();
+        if msg.sender.send(state.clone()).is_ok() {
+            self.observers.push(msg.sender);
+            self.last_state = Some(state);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::core::types::TransactionRequest;
+    use std::{sync::Mutex, time::Duration};
+
+    /// Records the requests sent to it, `init:ID` for inits
+    struct FakeBrowser(Arc<Mutex<Vec<String>>>);
+
+    impl Actor for FakeBrowser {
+        type Context = Context<Self>;
+    }
+
+    impl Handler<WSRequest> for FakeBrowser {
+        type Result = ();
+
+        fn handle(&mut self, msg: WSRequest, _: &mut Context<Self>) {
+            self.0.lock().unwrap().push(match msg {
+                WSRequest::Init { id, .. } => format!("init:{}", id),
+                WSRequest::Accounts { id } => format!("accounts:{}", id),
+                WSRequest::SignTextMessage { id, .. } => format!("sign:{}", id),
+                WSRequest::SendTransaction { id, .. } => format!("send:{}", id),
+                WSRequest::Cancel { id } => format!("cancel:{}", id),
+                WSRequest::Close { .. } => "close".to_owned(),
+                _ => "request".to_owned(),
+            });
+        }
+    }
+
+    type Received = Arc<Mutex<Vec<String>>>;
+    type Reply = oneshot::Receiver<AsyncResponse>;
+
+    fn fake_browser() -> (WebsocketClient, Received) {
+        let received = Arc::new(Mutex::new(vec![]));
+        (FakeBrowser(received.clone()).start().recipient(), received)
+    }
+
+    async fn settle() {
+        actix::clock::sleep(Duration::from_millis(20)).await;
+    }
+
+    async fn response(reply: Reply) -> AsyncResponse {
+        actix::clock::timeout(Duration::from_secs(1), reply).await.unwrap().unwrap()
+    }
+
+    /// A server on chain 5 with the default options, not started yet
+    fn comm_server() -> CommServer {
+        CommServer::new(5, None, FrontendConfig::default(), Arc::new(AtomicBool::new(false)))
+    }
+
+    /// Connects a new browser and answers its init, returns its session
+    async fn connect_browser(comm: &Addr<CommServer>) -> (WebsocketClient, Received, String) {
+        let (client, received) = fake_browser();
+        comm.send(WSReply::Connect { client: client.clone(), session: None }).await.unwrap();
+        settle().await;
+        let session = received.lock().unwrap()[0].strip_prefix("init:").unwrap().to_owned();
+        comm.send(WSReply::Init { id: session.clone(), client: client.clone() }).await.unwrap();
+        (client, received, session)
+    }
+
+    /// A started server with a browser ready to handle requests
+    async fn ready_comm() -> (Addr<CommServer>, WebsocketClient, Received) {
+        let comm = comm_server().start();
+        let (client, received, _) = connect_browser(&comm).await;
+        (comm, client, received)
+    }
+
+    fn request(id: &str, content: AsyncRequestContent) -> (AsyncRequest, Reply) {
+        let (reply_to, reply) = oneshot::channel();
+        (AsyncRequest { id: id.to_owned(), content, reply_to, rpc: false }, reply)
+    }
+
+    fn sign(id: &str) -> (AsyncRequest, Reply) {
+        let content = AsyncRequestContent::SignTextMessage {
+            address: Address::zero(),
+            message: "hello".to_owned(),
+        };
+        request(id, content)
+    }
+
+    #[test]
+    fn it_resyncs_a_resumed_browser_on_another_chain() {
+        System::new().block_on(async {
+            let comm = comm_server().start();
+            let (client, _, session) = connect_browser(&comm).await;
+            comm.send(WSReply::Disconnect { client }).await.unwrap();
+
+            // same chain: the queued request waits for the status, then goes through
+            comm.send(sign("sign").0).await.unwrap();
+            let (client, received) = fake_browser();
+            let connect =
+                WSReply::Connect { client: client.clone(), session: Some(session.clone()) };
+            comm.send(connect).await.unwrap();
+            settle().await;
+            assert!(received.lock().unwrap().is_empty());
+            let status =
+                WSReply::Status { client: client.clone(), chain_id: Some(5), accounts: None };
+            comm.send(status).await.unwrap();
+            settle().await;
+            assert_eq!(*received.lock().unwrap(), ["sign:sign"]);
+            comm.send(AsyncCancel { id: "sign".to_owned(), reply: false }).await.unwrap();
+            comm.send(WSReply::Disconnect { client }).await.unwrap();
+
+            // the wallet switched chain in the meantime: init again
+            let (client, received) = fake_browser();
+            let connect = WSReply::Connect { client: client.clone(), session: Some(session) };
+            comm.send(connect).await.unwrap();
+            let status = WSReply::Status { client, chain_id: Some(1), accounts: None };
+            comm.send(status).await.unwrap();
+            settle().await;
+            let received = received.lock().unwrap();
+            assert_eq!(received.len(), 1);
+            assert!(received[0].starts_with("init:"));
+        });
+    }
+
+    #[test]
+    fn it_fails_transactions_interrupted_by_a_new_browser() {
+        System::new().block_on(async {
+            let (comm, client, received) = ready_comm().await;
+            let transaction = TransactionRequest::new().into();
+            let (send, reply) =
+                request("send", AsyncRequestContent::SendTransaction { transaction });
+            comm.send(send).await.unwrap();
+            comm.send(sign("sign").0).await.unwrap();
+            settle().await;
+            assert_eq!(received.lock().unwrap()[1..], ["send:send"]);
+            comm.send(WSReply::Disconnect { client }).await.unwrap();
+
+            // the wallet might have broadcast it already, a new page would do it again
+            let (_, received, _) = connect_browser(&comm).await;
+            settle().await;
+            assert_eq!(received.lock().unwrap()[1..], ["sign:sign"]);
+            let res = response(reply).await;
+            assert!(matches!(
+                res.content,
+                AsyncResponseContent::Error { error, code: None, .. }
+                    if error == INTERRUPTED_TRANSACTION_ERROR
+            ));
+        });
+    }
+
+    #[test]
+    fn it_gives_up_on_a_wallet_which_never_loads() {
+        System::new().block_on(async {
+            let comm = comm_server().with_max_wallet_loading_retries(2).start();
+            let (client, _, _) = connect_browser(&comm).await;
+            let loading =
+                || WSReply::WalletLoading { id: "read".to_owned(), client: client.clone() };
+            // unknown ids are ignored, they don't count for a later request
+            comm.send(loading()).await.unwrap();
+            comm.send(loading()).await.unwrap();
+            let (read, mut reply) = request("read", AsyncRequestContent::Accounts {});
+            comm.send(read).await.unwrap();
+
+            comm.send(loading()).await.unwrap();
+            comm.send(loading()).await.unwrap();
+            assert!(reply.try_recv().is_err());
+            comm.send(loading()).await.unwrap();
+            let res = response(reply).await;
+            assert!(matches!(
+                res.content,
+                AsyncResponseContent::NoWallet { error }
+                    if error == "the wallet was still loading after 2 retries"
+            ));
+        });
+    }
+
+    #[test]
+    fn it_answers_reads_while_a_signature_is_pending() {
+        System::new().block_on(async {
+            let (comm, client, received) = ready_comm().await;
+
+            let (sign1, reply1) = sign("sign1");
+            comm.send(sign1).await.unwrap();
+            comm.send(sign("sign2").0).await.unwrap();
+            let (read, read_reply) = request("read", AsyncRequestContent::Accounts {});
+            comm.send(read).await.unwrap();
+            settle().await;
+            // the second signature waits for the first one, the read doesn't
+            assert_eq!(received.lock().unwrap()[1..], ["sign:sign1", "accounts:read"]);
+
+            let accounts = vec![Address::zero()];
+            let reply =
+                WSReply::Accounts { id: "read".to_owned(), client: client.clone(), accounts };
+            comm.send(reply).await.unwrap();
+            let res = response(read_reply).await;
+            assert_eq!(res.id, "read");
+            assert!(matches!(res.content, AsyncResponseContent::Accounts { .. }));
+
+            let signature = "0x12".to_owned();
+            let reply = WSReply::MessageSignature { id: "sign1".to_owned(), client, signature };
+            comm.send(reply).await.unwrap();
+            settle().await;
+            assert_eq!(response(reply1).await.id, "sign1");
+            assert_eq!(received.lock().unwrap()[3..], ["sign:sign2"]);
+        });
+    }
+
+    #[test]
+    fn it_moves_on_from_cancelled_requests() {
+        System::new().block_on(async {
+            let comm = comm_server().start();
+            comm.send(sign("sign1").0).await.unwrap();
+            comm.send(sign("sign2").0).await.unwrap();
+            let (sign3, reply3) = sign("sign3");
+            comm.send(sign3).await.unwrap();
+            // cancelled before any browser connected: never sent
+            comm.send(AsyncCancel { id: "sign2".to_owned(), reply: false }).await.unwrap();
+
+            let (client, received, _) = connect_browser(&comm).await;
+            settle().await;
+            assert_eq!(received.lock().unwrap()[1..], ["sign:sign1"]);
+
+            // cancelled while the browser handles it: the next one is sent right away
+            comm.send(AsyncCancel { id: "sign1".to_owned(), reply: false }).await.unwrap();
+            settle().await;
+            assert_eq!(received.lock().unwrap()[1..], ["sign:sign1", "cancel:sign1", "sign:sign3"]);
+
+            let signature = "0x12".to_owned();
+            let reply = WSReply::MessageSignature { id: "sign3".to_owned(), client, signature };
+            comm.send(reply).await.unwrap();
+            assert_eq!(response(reply3).await.id, "sign3");
+        });
+    }
+
+    #[test]
+    fn it_sends_concurrent_prompts_up_to_the_limit() {
+        System::new().block_on(async {
+            let comm = comm_server().with_concurrent_prompts(2).start();
+            comm.send(sign("sign1").0).await.unwrap();
+            let (sign2, reply2) = sign("sign2");
+            comm.send(sign2).await.unwrap();
+            comm.send(sign("sign3").0).await.unwrap();
+
+            let (client, received, _) = connect_browser(&comm).await;
+            settle().await;
+            assert_eq!(received.lock().unwrap()[1..], ["sign:sign1", "sign:sign2"]);
+
+            // answered out of order: the slot is freed for the last one
+            let signature = "0x12".to_owned();
+            let reply = WSReply::MessageSignature { id: "sign2".to_owned(), client, signature };
+            comm.send(reply).await.unwrap();
+            assert_eq!(response(reply2).await.id, "sign2");
+            settle().await;
+            assert_eq!(received.lock().unwrap()[1..], ["sign:sign1", "sign:sign2", "sign:sign3"]);
+        });
+    }
+
+    #[test]
+    fn it_rejects_a_second_tab() {
+        System::new().block_on(async {
+            let (comm, client, received) = ready_comm().await;
+
+            let (other, other_received) = fake_browser();
+            comm.send(WSReply::Connect { client: other.clone(), session: None }).await.unwrap();
+            comm.send(WSReply::Disconnect { client: other }).await.unwrap();
+            settle().await;
+            assert_eq!(*other_received.lock().unwrap(), ["close"]);
+
+            // the first tab keeps working
+            let (accounts, answer) = request("accounts", AsyncRequestContent::Accounts {});
+            comm.send(accounts).await.unwrap();
+            settle().await;
+            assert_eq!(received.lock().unwrap()[1..], ["accounts:accounts"]);
+            let reply = WSReply::Accounts { id: "accounts".to_owned(), client, accounts: vec![] };
+            comm.send(reply).await.unwrap();
+            assert_eq!(response(answer).await.id, "accounts");
+        });
+    }
+
+    #[test]
+    fn it_lets_a_second_tab_take_over() {
+        System::new().block_on(async {
+            let comm = comm_server().with_client_replacement(true).start();
+            let (_, received, _) = connect_browser(&comm).await;
+
+            let (other, other_received) = fake_browser();
+            comm.send(WSReply::Connect { client: other, session: None }).await.unwrap();
+            settle().await;
+            assert_eq!(received.lock().unwrap()[1..], ["close"]);
+            let other_received = other_received.lock().unwrap();
+            assert_eq!(other_received.len(), 1);
+            assert!(other_received[0].starts_with("init:"));
+        });
+    }
+
+    #[test]
+    fn it_cancels_pending_requests() {
+        System::new().block_on(async {
+            let comm = comm_server().start();
+            let (sign1, reply1) = sign("sign1");
+            comm.send(sign1).await.unwrap();
+            let (sign2, reply2) = sign("sign2");
+            comm.send(sign2).await.unwrap();
+            let (rpc, mut rpc_reply) = request("rpc", AsyncRequestContent::Accounts {});
+            comm.send(AsyncRequest { rpc: true, ..rpc }).await.unwrap();
+            let (client, received, _) = connect_browser(&comm).await;
+            settle().await;
+
+            comm.send(AsyncCancelPending {}).await.unwrap();
+            settle().await;
+            // only the request in flight reached the browser
+            assert_eq!(
+                received.lock().unwrap()[1..],
+                ["accounts:rpc", "sign:sign1", "cancel:sign1"]
+            );
+            for mut reply in [reply1, reply2] {
+                let res = reply.try_recv().unwrap();
+                assert!(matches!(res.content, AsyncResponseContent::Cancelled {}));
+            }
+
+            // the JSON-RPC request is still waiting for its answer
+            assert!(rpc_reply.try_recv().is_err());
+            let reply = WSReply::Accounts { id: "rpc".to_owned(), client, accounts: vec![] };
+            comm.send(reply).await.unwrap();
+            let res = response(rpc_reply).await;
+            assert!(matches!(res.content, AsyncResponseContent::Accounts { .. }));
+        });
+    }
+
+    #[test]
+    fn it_cancels_requests_whose_waiter_is_gone() {
+        System::new().block_on(async {
+            let (comm, _, received) = ready_comm().await;
+            let (sign1, _reply) = sign("sign1");
+            let guard = CancelGuard::new(comm.clone(), "sign1".to_owned());
+            comm.send(sign1).await.unwrap();
+            let (sign2, _reply) = sign("sign2");
+            let mut answered = CancelGuard::new(comm.clone(), "sign2".to_owned());
+            comm.send(sign2).await.unwrap();
+
+            answered.disarm();
+            drop(answered);
+            drop(guard);
+            settle().await;
+            assert_eq!(received.lock().unwrap()[1..], ["sign:sign1", "cancel:sign1", "sign:sign2"]);
+        });
+    }
+
+    #[test]
+    fn it_closes_the_browser_connection_on_shutdown() {
+        System::new().block_on(async {
+            let comm = comm_server().start();
+            // nothing to close yet
+            comm.send(AsyncShutdown {}).await.unwrap();
+
+            let (client, received) = fake_browser();
+            comm.send(WSReply::Connect { client, session: None }).await.unwrap();
+            comm.send(AsyncShutdown {}).await.unwrap();
+            settle().await;
+            assert_eq!(received.lock().unwrap()[1..], ["close"]);
+        });
+    }
+
+    #[test]
+    fn it_notifies_queue_changes() {
+        System::new().block_on(async {
+            let comm = comm_server().start();
+            let (observer, updates) = mpsc::channel();
+            comm.send(AsyncSubscribe { sender: observer }).await.unwrap();
+            let state = |browser, queued, in_flight: Option<&str>| QueueState {
+                browser,
+                queued,
+                in_flight: in_flight.map(|id| id.to_owned()),
+                reads: 0,
+            };
+
+            comm.send(sign("sign1").0).await.unwrap();
+            let (client, _, _) = connect_browser(&comm).await;
+            let signature = "0x12".to_owned();
+            let reply = WSReply::MessageSignature { id: "sign1".to_owned(), client, signature };
+            comm.send(reply).await.unwrap();
+
+            assert_eq!(
+                updates.try_iter().collect::<Vec<_>>(),
+                [
+                    state(BrowserState::Disconnected, 0, None),
+                    state(BrowserState::Disconnected, 1, None),
+                    state(BrowserState::Initializing, 1, None),
+                    state(BrowserState::Ready, 1, Some("sign1")),
+                    state(BrowserState::Ready, 0, None),
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn it_sends_the_current_state_to_late_observers() {
+        System::new().block_on(async {
+            let comm = comm_server().start();
+            comm.send(sign("sign1").0).await.unwrap();
+
+            let (observer, updates) = mpsc::channel();
+            comm.send(AsyncSubscribe { sender: observer }).await.unwrap();
+            // nothing changes: no update
+            comm.send(AsyncCancel { id: "unknown".to_owned(), reply: false }).await.unwrap();
+
+            let queued = QueueState {
+                browser: BrowserState::Disconnected,
+                queued: 1,
+                in_flight: None,
+                reads: 0,
+            };
+            assert_eq!(updates.try_iter().collect::<Vec<_>>(), [queued]);
+        });
     }
 }
@@ -1,54 +1,192 @@
-use actix::{Actor, Addr};
+use actix::{Actor, Addr, Arbiter, ArbiterHandle};
 use actix_web::{dev::ServerHandle, rt, web, App, HttpServer};
 use ethers::core::{
     abi::Address,
     types::{
         transaction::{eip2718::TypedTransaction, eip712::TypedData},
-        H256,
+        Bytes, H256,
     },
 };
-use ethers_signers_browser_frontend::ws::messages::ChainInfo;
+use ethers_signers_browser_frontend::ws::messages::{
+    ChainInfo, FrontendConfig, MessageTracing, PageConfig, TypedDataVersion,
+};
 use rand::distributions::{Alphanumeric, DistString};
-use routes::{dist, index, ws_open};
+use routes::{
+    add_trailing_slash, check_nonce, dist, favicon, index, manifest, rpc_call, ws_open, Branding,
+    Frontend, RPCSettings, WSSettings,
+};
 use std::{
     collections::HashMap,
+    net::TcpListener,
     sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
         mpsc::{self, RecvError},
-        Mutex,
+        Arc, Mutex,
     },
     thread::{self, sleep},
     time::{Duration, Instant},
 };
+use tokio::sync::oneshot;
+use tracing::warn;
 
 mod comm;
+pub use comm::{BrowserState, QueueState};
 mod routes;
+mod rpc;
 pub mod session;
+mod tls;
+pub use tls::TlsConfig;
 
 // FIXME: tweak those values
-static TIMEOUT: Duration = Duration::MAX;
+/// How long to wait for the browser to answer a request, generous as wallets can take a while
+/// (e.g. passkey wallets with their own multi-step UI). The frontend doesn't time out at all.
+const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+/// Used for the initial accounts request unless a connect timeout is given
+static CONNECT_TIMEOUT: Duration = Duration::MAX;
+const DEFAULT_MAX_MESSAGE_SIZE: usize = 1 << 20;
+const DEFAULT_KEEPALIVE_INTERVAL: Duration = Duration::from_secs(10);
+const DEFAULT_APP_NAME: &str = "ethers-signers-browser";
+const DEFAULT_HOST: &str = "127.0.0.1";
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_CLOSE_GRACE_PERIOD: Duration = Duration::from_millis(500);
+/// Retried every 500ms, so about 10 seconds
+const DEFAULT_MAX_WALLET_LOADING_RETRIES: usize = 20;
 
 type ServerDataResult = Result<ServerData, String>;
 
+fn timeout_millis(timeout: Duration) -> u64 {
+    u64::try_from(timeout.as_millis()).unwrap_or(u64::MAX)
+}
+
+/// The event loop shared by servers created with `ServerOptions::shared_runtime`
+static SHARED_RUNTIME: Mutex<Option<ArbiterHandle>> = Mutex::new(None);
+
+fn shared_runtime() -> Result<ArbiterHandle, ServerError> {
+    let mut runtime = SHARED_RUNTIME.lock().expect("poisoned mutex");
+    if let Some(runtime) = runtime.as_ref() {
+        return Ok(runtime.clone())
+    }
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let system = rt::System::new();
+        let _ = sender.send(Arbiter::current());
+        system.run()
+    });
+    let handle = receiver
+        .recv()
+        .map_err(|_| ServerError::Init("shared runtime did not start".to_owned()))?;
+    *runtime = Some(handle.clone());
+    Ok(handle)
+}
+
 struct ServerData {
     port: u16,
     server: ServerHandle,
     comm: Addr<comm::CommServer>,
 }
 
+/// The routes serving the browser, to mount into an existing actix-web `App` instead of running a
+/// dedicated server, see `BrowserSigner::new_embedded`
+#[derive(Clone)]
+pub struct SignerRoutes {
+    comm: web::Data<Addr<comm::CommServer>>,
+    nonce: web::Data<String>,
+    ws_settings: web::Data<WSSettings>,
+    branding: web::Data<Branding>,
+    frontend: web::Data<Frontend>,
+    rpc_settings: web::Data<RPCSettings>,
+    enable_rpc: bool,
+}
+
+impl SignerRoutes {
+    fn new(
+        nonce: String,
+        comm: Addr<comm::CommServer>,
+        rpc_settings: RPCSettings,
+        opts: &mut ServerOptions,
+    ) -> Self {
+        Self {
+            comm: web::Data::new(comm),
+            nonce: web::Data::new(nonce),
+            ws_settings: web::Data::new(WSSettings {
+                max_message_size: opts.max_message_size.unwrap_or(DEFAULT_MAX_MESSAGE_SIZE),
+                trace_messages: opts.message_tracing(),
+            }),
+            branding: web::Data::new(Branding {
+                app_name: opts.app_name.take().unwrap_or(DEFAULT_APP_NAME.to_owned()),
+                favicon: opts.favicon.take(),
+            }),
+            frontend: web::Data::new(Frontend {
+                page_config: opts.page_config(),
+                custom: opts.custom_frontend.take(),
+            }),
+            rpc_settings: web::Data::new(rpc_settings),
+            enable_rpc: opts.rpc.unwrap_or(false),
+        }
+    }
+
+    /// Registers the routes, e.g. under a scope with `App::new().service(web::scope("/signer")
+    /// .configure(|cfg| routes.configure(cfg)))`, in which case `ServerOptions::public_path`
+    /// must be set to `/signer/` too
+    pub fn configure(&self, cfg: &mut web::ServiceConfig) {
+        cfg.app_data(self.comm.clone())
+            .app_data(self.nonce.clone())
+            .app_data(self.ws_settings.clone())
+            .app_data(self.branding.clone())
+            .app_data(self.frontend.clone())
+            .app_data(self.rpc_settings.clone())
+            .service(ws_open)
+            .service(index)
+            .service(check_nonce)
+            .service(favicon)
+            .service(manifest);
+        if self.enable_rpc {
+            cfg.service(rpc_call);
+        }
+        cfg.service(dist);
+    }
+}
+
 async fn create_server(
     nonce: String,
     comm: Addr<comm::CommServer>,
-    port: Option<u16>,
+    rpc_settings: RPCSettings,
+    mut opts: ServerOptions,
 ) -> Result<(actix_web::dev::Server, u16), std::io::Error> {
+    let routes = SignerRoutes::new(nonce, comm, rpc_settings, &mut opts);
+    let base_path = opts.base_path();
     let server = HttpServer::new(move || {
+        let scope = base_path.trim_end_matches('/');
+        if scope.is_empty() {
+            return App::new().configure(|cfg| routes.configure(cfg))
+        }
         App::new()
-            .app_data(web::Data::new(comm.clone()))
-            .app_data(web::Data::new(nonce.clone()))
-            .service(ws_open)
-            .service(index)
-            .service(dist)
+            .route(scope, web::get().to(add_trailing_slash))
+            .service(web::scope(scope).configure(|cfg| routes.configure(cfg)))
     })
-    .bind(("127.0.0.1", port.unwrap_or(0)))?;
+    .workers(opts.workers.unwrap_or(1));
+    let addr = (opts.host.as_deref().unwrap_or(DEFAULT_HOST), opts.port.unwrap_or(0));
+    #[cfg(not(feature = "tls"))]
+    if opts.tls.is_some() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "serving over TLS requires the `tls` feature",
+        ))
+    }
+    #[cfg(feature = "tls")]
+    if let Some(tls) = opts.tls.as_ref() {
+        let config = tls.server_config()?;
+        let server = match opts.listener {
+            Some(listener) => server.listen_rustls_0_21(listener, config)?,
+            None => server.bind_rustls_021(addr, config)?,
+        };
+        let addrs = server.addrs();
+        return Ok((server.run(), addrs[0].port()))
+    }
+    let server = match opts.listener {
+        Some(listener) => server.listen(listener)?,
+        None => server.bind(addr)?,
+    };
 
     let addrs = server.addrs();
     let server = server.run();
@@ -58,11 +196,12 @@ async fn create_server(
 async fn run_server_and_comm(
     nonce: String,
     comm: comm::CommServer,
+    rpc_settings: RPCSettings,
     sender: mpsc::Sender<ServerDataResult>,
-    port: Option<u16>,
+    opts: ServerOptions,
 ) {
     let comm = comm.start();
-    let (server, data) = match create_server(nonce, comm.clone(), port).await {
+    let (server, data) = match create_server(nonce, comm.clone(), rpc_settings, opts).await {
         Ok((server, port)) => {
             let handle = server.handle();
             (Some(server), Ok(ServerData { port, server: handle, comm }))
@@ -86,6 +225,15 @@ pub enum ServerError {
     Comm(String),
     #[error("client error: {0}")]
     Client(String),
+    #[error("{0}")]
+    NoWallet(String),
+    #[error("wallet error {code}: {message}")]
+    Wallet { message: String, code: i64, data: Option<serde_json::Value> },
+    #[error("shutdown error: {0}")]
+    Shutdown(String),
+    /// The request was cancelled with `Server::cancel_pending`
+    #[error("request cancelled")]
+    Cancelled,
 }
 
 impl From<RecvError> for ServerError {
@@ -94,71 +242,419 @@ impl From<RecvError> for ServerError {
     }
 }
 
+#[derive(Default)]
 pub struct ServerOptions {
+    /// The address to listen on, defaults to `127.0.0.1`. Anything else (e.g. `0.0.0.0` to open
+    /// the page from another device) exposes the signer to the network: anyone who can reach the
+    /// port and guess or intercept the nonce can send requests to your wallet, only use it on
+    /// networks you trust
+    pub host: Option<String>,
     pub port: Option<u16>,
+    /// The nonce protecting the page, defaults to random. Using a fixed nonce (and port) lets an
+    /// open tab reconnect seamlessly after the command is restarted, otherwise the tab will ask
+    /// the user to open the new URL
     pub nonce: Option<String>,
+    /// An already bound listener to serve from (e.g. from socket activation), `port` is ignored
+    /// when provided
+    pub listener: Option<TcpListener>,
+    /// The number of HTTP workers, defaults to 1 which is enough as the server only ever talks
+    /// to a single browser tab
+    pub workers: Option<usize>,
+    /// The maximum size of a websocket message in bytes (in both directions), defaults to 1MiB
+    pub max_message_size: Option<usize>,
+    /// How often the browser pings the server, it reconnects after 3 unanswered pings, defaults
+    /// to 10 seconds. The server drops browsers silent for 30 seconds, so keep it below that
+    pub keepalive_interval: Option<Duration>,
+    /// The name used in the web app manifest, defaults to `ethers-signers-browser`
+    pub app_name: Option<String>,
+    /// Replaces the embedded favicon, must be in the ICO format
+    pub favicon: Option<Vec<u8>>,
+    /// Run the server on a background thread shared with the other servers using this option
+    /// (e.g. one signer per chain) instead of a dedicated thread, defaults to false
+    pub shared_runtime: Option<bool>,
+    /// Expose the connected wallet as a JSON-RPC endpoint at `POST /rpc?nonce=NONCE`, defaults
+    /// to false. Supports `eth_accounts`, `eth_sign`, `personal_sign`, `eth_signTransaction` and
+    /// `eth_signTypedData_v3`/`eth_signTypedData_v4`
+    pub rpc: Option<bool>,
+    /// Replaces the embedded frontend entirely, keyed by path: `index.html` is served at `/` and
+    /// every file at `/dist/{path}`. See the README for the protocol it must implement
+    pub custom_frontend: Option<HashMap<String, Vec<u8>>>,
+    /// Log every websocket message at the `debug` level (and in the browser console), defaults to
+    /// false as messages can contain sensitive data
+    pub trace_messages: Option<bool>,
+    /// Replace signatures and signed content with `<redacted>` in traced messages, defaults to
+    /// true
+    pub redact_traced_messages: Option<bool>,
+    /// The path the browser reaches the server at, e.g. `/signer/` behind a reverse proxy
+    /// forwarding `https://example.com/signer/` to the server, defaults to `base_path`
+    pub public_path: Option<String>,
+    /// The path every route is served under, e.g. `/wallet/` behind a reverse proxy which doesn't
+    /// strip it, defaults to `/`. Ignored by `Server::new_embedded`, use a scope instead
+    pub base_path: Option<String>,
+    /// Whether the browser must use `wss://` (e.g. when a reverse proxy terminates TLS), defaults
+    /// to whether the page was loaded over `https://`
+    pub public_secure: Option<bool>,
+    /// How many signing requests the browser receives at once, defaults to 1 as most wallets
+    /// (e.g. MetaMask) don't handle concurrent prompts well. Only used when
+    /// `BrowserOptions::review_requests` is disabled, as the page reviews them one at a time.
+    /// Requests which don't prompt (e.g. accounts) are never held back
+    pub max_concurrent_prompts: Option<usize>,
+    /// Whether a tab opening the signer while another one is connected takes over (closing the
+    /// other one) instead of being rejected, defaults to false
+    pub replace_connected_tab: Option<bool>,
+    /// How many times a request is sent again while the browser's wallet is still loading (every
+    /// 500ms) before failing with `ServerError::NoWallet`, defaults to 20
+    pub max_wallet_loading_retries: Option<usize>,
+    /// How long to wait after asking the browser to close its connection before stopping, so the
+    /// page shows that the command finished instead of a connection error, defaults to 500ms
+    pub close_grace_period: Option<Duration>,
+    /// Serve the page over `https://` (and the websocket over `wss://`) with this certificate,
+    /// as some wallets behave differently on secure origins. Requires the `tls` feature, the
+    /// server fails to start without it. Ignored by `Server::new_embedded`
+    pub tls: Option<TlsConfig>,
+}
+
+fn normalize_path(path: &str) -> String {
+    let path = path.trim_matches('/');
+    if path.is_empty() {
+        "/".to_owned()
+    } else {
+        format!("/{}/", path)
+    }
+}
+
+impl ServerOptions {
+    fn message_tracing(&self) -> Option<MessageTracing> {
+        match (self.trace_messages, self.redact_traced_messages) {
+            (Some(true), Some(false)) => Some(MessageTracing::Full),
+            (Some(true), _) => Some(MessageTracing::Redacted),
+            _ => None,
+        }
+    }
+
+    fn serves_tls(&self) -> bool {
+        self.tls.is_some()
+    }
+
+    /// `base_path` with a leading and a trailing slash, `/` if unset
+    fn base_path(&self) -> String {
+        normalize_path(self.base_path.as_deref().unwrap_or("/"))
+    }
+
+    fn page_config(&self) -> PageConfig {
+        let base_path = match self.public_path.as_deref() {
+            Some(path) => normalize_path(path),
+            None => self.base_path(),
+        };
+        PageConfig { ws_path: format!("{}ws/", base_path), base_path, secure: self.public_secure }
+    }
 }
 
 pub(super) struct Server {
     port: u16,
     nonce: String,
-    server: ServerHandle,
+    base_path: String,
+    /// `None` when the routes are served by the caller, see `new_embedded`
+    server: Option<ServerHandle>,
     comm: Addr<comm::CommServer>,
-    comm_receiver: Mutex<mpsc::Receiver<comm::AsyncResponse>>,
+    browser_connected: Arc<AtomicBool>,
+    strict_verifying_contract: Arc<AtomicBool>,
+    /// In milliseconds, shared with the JSON-RPC endpoint
+    request_timeout: Arc<AtomicU64>,
+    host: Option<String>,
+    /// Whether the server uses TLS, see `ServerOptions::tls`
+    secure: bool,
+    close_grace_period: Duration,
+}
+
+/// What `Server::new` and `Server::new_embedded` have in common, before the comm server starts
+struct Setup {
+    nonce: String,
+    base_path: String,
+    browser_connected: Arc<AtomicBool>,
+    rpc_settings: RPCSettings,
+    host: Option<String>,
+    secure: bool,
+    close_grace_period: Duration,
+}
+
+impl Setup {
+    fn new(
+        chain_id: u64,
+        chains: Option<HashMap<u64, ChainInfo>>,
+        config: FrontendConfig,
+        opts: &mut ServerOptions,
+    ) -> (Self, comm::CommServer) {
+        let nonce =
+            opts.nonce.take().unwrap_or(Alphanumeric.sample_string(&mut rand::thread_rng(), 16));
+        let config = FrontendConfig {
+            max_message_size: Some(opts.max_message_size.unwrap_or(DEFAULT_MAX_MESSAGE_SIZE)),
+            trace_messages: opts.message_tracing(),
+            keepalive_interval: Some(
+                opts.keepalive_interval.unwrap_or(DEFAULT_KEEPALIVE_INTERVAL).as_millis() as u64,
+            ),
+            ..config
+        };
+        let browser_connected = Arc::new(AtomicBool::new(false));
+        let max_prompts = match (config.review_requests, opts.max_concurrent_prompts) {
+            (Some(false), Some(max)) => max,
+            (_, Some(max)) if max > 1 => {
+                warn!(
+                    "requests are reviewed one at a time in the browser, ignoring \
+                     max_concurrent_prompts"
+                );
+                1
+            }
+            _ => 1,
+        };
+        let comm = comm::CommServer::new(chain_id, chains, config, browser_connected.clone())
+            .with_concurrent_prompts(max_prompts)
+            .with_client_replacement(opts.replace_connected_tab.unwrap_or(false))
+            .with_max_wallet_loading_retries(
+                opts.max_wallet_loading_retries.unwrap_or(DEFAULT_MAX_WALLET_LOADING_RETRIES),
+            );
+        let setup = Self {
+            nonce,
+            base_path: opts.base_path(),
+            browser_connected,
+            rpc_settings: RPCSettings {
+                strict_verifying_contract: Arc::new(AtomicBool::new(false)),
+                request_timeout: Arc::new(AtomicU64::new(timeout_millis(DEFAULT_REQUEST_TIMEOUT))),
+            },
+            host: opts.host.clone(),
+            secure: opts.serves_tls(),
+            close_grace_period: opts.close_grace_period.unwrap_or(DEFAULT_CLOSE_GRACE_PERIOD),
+        };
+        (setup, comm)
+    }
+
+    fn into_server(
+        self,
+        port: u16,
+        server: Option<ServerHandle>,
+        comm: Addr<comm::CommServer>,
+    ) -> Server {
+        Server {
+            port,
+            nonce: self.nonce,
+            base_path: self.base_path,
+            server,
+            comm,
+            browser_connected: self.browser_connected,
+            strict_verifying_contract: self.rpc_settings.strict_verifying_contract,
+            request_timeout: self.rpc_settings.request_timeout,
+            host: self.host,
+            secure: self.secure,
+            close_grace_period: self.close_grace_period,
+        }
+    }
 }
 
 impl Server {
     pub async fn new(
         chain_id: u64,
         chains: Option<HashMap<u64, ChainInfo>>,
+        config: FrontendConfig,
         opts: Option<ServerOptions>,
     ) -> Result<Self, ServerError> {
         let (sender, receiver) = mpsc::channel();
-        let (comm_sender, comm_receiver) = mpsc::channel();
-
-        let opts = opts.unwrap_or(ServerOptions { port: None, nonce: None });
-        let nonce = opts.nonce.unwrap_or(Alphanumeric.sample_string(&mut rand::thread_rng(), 16));
+        let mut opts = opts.unwrap_or_default();
+        let (setup, comm) = Setup::new(chain_id, chains, config, &mut opts);
 
         {
-            let nonce = nonce.clone();
-            thread::spawn(move || {
-                let fut = run_server_and_comm(
-                    nonce,
-                    comm::CommServer::new(comm_sender, chain_id, chains),
-                    sender,
-                    opts.port,
-                );
-                rt::System::new().block_on(fut)
-            });
+            let nonce = setup.nonce.clone();
+            let settings = setup.rpc_settings.clone();
+            if opts.shared_runtime.unwrap_or(false) {
+                let started = shared_runtime()?.spawn_fn(move || {
+                    rt::spawn(run_server_and_comm(nonce, comm, settings, sender, opts));
+                });
+                if !started {
+                    return Err(ServerError::Init("shared runtime is not running".to_owned()))
+                }
+            } else {
+                // keep the caller's subscriber (e.g. a scoped one with `log_layer::ChannelLayer`)
+                let dispatch = tracing::dispatcher::get_default(|dispatch| dispatch.clone());
+                thread::spawn(move || {
+                    tracing::dispatcher::with_default(&dispatch, || {
+                        rt::System::new()
+                            .block_on(run_server_and_comm(nonce, comm, settings, sender, opts))
+                    })
+                });
+            }
         }
 
         let data = receiver.recv()?.map_err(ServerError::Init)?;
+        Ok(setup.into_server(data.port, Some(data.server), data.comm))
+    }
 
-        Ok(Self {
-            port: data.port,
-            server: data.server,
-            nonce,
-            comm: data.comm,
-            comm_receiver: Mutex::new(comm_receiver),
-        })
+    /// Only starts the comm server (on the shared runtime, see `ServerOptions::shared_runtime`),
+    /// the returned routes must be served by the caller. The listening options (`host`, `port`,
+    /// `listener` and `workers`) are ignored
+    pub fn new_embedded(
+        chain_id: u64,
+        chains: Option<HashMap<u64, ChainInfo>>,
+        config: FrontendConfig,
+        opts: Option<ServerOptions>,
+    ) -> Result<(Self, SignerRoutes), ServerError> {
+        let (sender, receiver) = mpsc::channel();
+        let mut opts = opts.unwrap_or_default();
+        let (setup, comm) = Setup::new(chain_id, chains, config, &mut opts);
+
+        let started = shared_runtime()?.spawn_fn(move || {
+            let _ = sender.send(comm.start());
+        });
+        if !started {
+            return Err(ServerError::Init("shared runtime is not running".to_owned()))
+        }
+        let comm = receiver
+            .recv()
+            .map_err(|_| ServerError::Init("comm server did not start".to_owned()))?;
+
+        let routes = SignerRoutes::new(
+            setup.nonce.clone(),
+            comm.clone(),
+            setup.rpc_settings.clone(),
+            &mut opts,
+        );
+        Ok((setup.into_server(0, None, comm), routes))
+    }
+
+    /// How long to wait for the browser to answer a signing request (including the ones made
+    /// through the JSON-RPC endpoint), defaults to 5 minutes
+    pub fn set_request_timeout(&self, timeout: Option<Duration>) {
+        let timeout = timeout_millis(timeout.unwrap_or(DEFAULT_REQUEST_TIMEOUT));
+        self.request_timeout.store(timeout, Ordering::SeqCst);
+    }
+
+    fn request_timeout(&self) -> Duration {
+        Duration::from_millis(self.request_timeout.load(Ordering::SeqCst))
+    }
+
+    /// Whether the JSON-RPC endpoint rejects typed data whose `verifyingContract` has an invalid
+    /// checksum instead of only logging it, defaults to false
+    pub fn set_strict_verifying_contract(&self, strict: bool) {
+        self.strict_verifying_contract.store(strict, Ordering::SeqCst);
+    }
+
+    /// Stops the server gracefully and waits until its port is released
+    pub async fn shutdown(&self) -> Result<(), ServerError> {
+        let (sender, receiver) = oneshot::channel();
+        self.stop(true, move |stopped| {
+            let _ = sender.send(stopped);
+        });
+        match receiver.await {
+            Ok(true) => Ok(()),
+            _ => Err(ServerError::Shutdown(format!(
+                "server still running after {:?}",
+                SHUTDOWN_TIMEOUT
+            ))),
+        }
+    }
+
+    /// Closes the browser connection then stops the server after the grace period, in the
+    /// background as the server might run on the current thread's event loop. `done` receives
+    /// whether it stopped within `SHUTDOWN_TIMEOUT`
+    fn stop(&self, graceful: bool, done: impl FnOnce(bool) + Send + 'static) {
+        self.comm.do_send(comm::AsyncShutdown {});
+        let handle = match self.server.clone() {
+            Some(handle) => handle,
+            // the caller's server keeps running
+            None => return done(true),
+        };
+        let grace_period = self.close_grace_period;
+        thread::spawn(move || {
+            sleep(grace_period);
+            let stopped = rt::System::new().block_on(async {
+                rt::time::timeout(SHUTDOWN_TIMEOUT, handle.stop(graceful)).await.is_ok()
+            });
+            done(stopped);
+        });
+    }
+
+    /// Cancels every request waiting for the browser (except the JSON-RPC ones), they fail with
+    /// `ServerError::Cancelled`
+    pub fn cancel_pending(&self) {
+        self.comm.do_send(comm::AsyncCancelPending {});
+    }
+
+    /// Receives the current `QueueState` then every change, the comm server forgets the receiver
+    /// once dropped
+    pub fn subscribe_queue(&self) -> mpsc::Receiver<QueueState> {
+        let (sender, receiver) = mpsc::channel();
+        self.comm.do_send(comm::AsyncSubscribe { sender });
+        receiver
+    }
+
+    /// Whether a browser is currently connected, kept up to date by the comm server
+    pub fn browser_connected(&self) -> Arc<AtomicBool> {
+        self.browser_connected.clone()
     }
 
+    /// The port listened on, 0 when the routes are served by the caller
     pub fn port(&self) -> u16 {
         self.port
     }
 
+    /// `https` when serving over TLS, `http` otherwise
+    pub fn scheme(&self) -> &'static str {
+        if self.secure {
+            "https"
+        } else {
+            "http"
+        }
+    }
+
+    /// The host to put in URLs, `localhost` unless a specific address is listened on
+    pub fn url_host(&self) -> String {
+        match self.host.as_deref() {
+            None | Some(DEFAULT_HOST) | Some("0.0.0.0") | Some("::") => "localhost".to_owned(),
+            Some(host) if host.contains(':') => format!("[{}]", host),
+            Some(host) => host.to_owned(),
+        }
+    }
+
+    /// The path the routes are served under, see `ServerOptions::base_path`
+    pub fn base_path(&self) -> String {
+        self.base_path.clone()
+    }
+
     pub fn nonce(&self) -> String {
         self.nonce.clone()
     }
 
-    pub async fn get_user_addresses(&self) -> Result<Vec<Address>, ServerError> {
+    /// Waits until the server accepts connections, returns false if it didn't within `timeout`
+    pub async fn wait_until_ready(&self, timeout: Duration) -> bool {
+        if self.server.is_none() {
+            // up to the caller
+            return true
+        }
+        let start = Instant::now();
+        loop {
+            let host = match self.host.as_deref() {
+                None | Some("0.0.0.0") | Some("::") => DEFAULT_HOST,
+                Some(host) => host,
+            };
+            if rt::net::TcpStream::connect((host, self.port)).await.is_ok() {
+                return true
+            }
+            if start.elapsed() >= timeout {
+                return false
+            }
+            rt::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+
+    pub async fn get_user_addresses(
+        &self,
+        timeout: Option<Duration>,
+    ) -> Result<Vec<Address>, ServerError> {
         self.wait_for_reply(
             comm::AsyncRequestContent::Accounts {},
             |res| match res {
                 comm::AsyncResponseContent::Accounts { accounts } => Some(accounts.clone()),
                 _ => None,
             },
-            TIMEOUT,
+            timeout.unwrap_or(CONNECT_TIMEOUT),
         )
         .await
     }
@@ -176,7 +672,7 @@ impl Server {
                 }
                 _ => None,
             },
-            TIMEOUT,
+            self.request_timeout(),
         )
         .await
     }
@@ -185,16 +681,17 @@ impl Server {
         &self,
         address: Address,
         message: H256,
+        original: Option<Bytes>,
     ) -> Result<String, ServerError> {
         self.wait_for_reply(
-            comm::AsyncRequestContent::SignBinaryMessage { address, message },
+            comm::AsyncRequestContent::SignBinaryMessage { address, message, original },
             |res| match res {
                 comm::AsyncResponseContent::MessageSignature { signature } => {
                     Some(signature.clone())
                 }
                 _ => None,
             },
-            TIMEOUT,
+            self.request_timeout(),
         )
         .await
     }
@@ -211,7 +708,23 @@ impl Server {
                 }
                 _ => None,
             },
-            TIMEOUT,
+            self.request_timeout(),
+        )
+        .await
+    }
+
+    /// Returns the hash of the transaction broadcast by the wallet
+    pub async fn send_transaction(
+        &self,
+        transaction: TypedTransaction,
+    ) -> Result<String, ServerError> {
+        self.wait_for_reply(
+            comm::AsyncRequestContent::SendTransaction { transaction },
+            |res| match res {
+                comm::AsyncResponseContent::TransactionHash { hash } => Some(hash.clone()),
+                _ => None,
+            },
+            self.request_timeout(),
         )
         .await
     }
@@ -220,9 +733,10 @@ impl Server {
         &self,
         address: Address,
         typed_data: TypedData,
+        version: TypedDataVersion,
     ) -> Result<String, ServerError> {
         self.wait_for_reply(
-            comm::AsyncRequestContent::SignTypedData { address, typed_data },
+            comm::AsyncRequestContent::SignTypedData { address, typed_data, version },
             |res| match res {
                 // FIXME: maybe it needs a different response type
                 comm::AsyncResponseContent::MessageSignature { signature } => {
@@ -230,7 +744,37 @@ impl Server {
                 }
                 _ => None,
             },
-            TIMEOUT,
+            self.request_timeout(),
+        )
+        .await
+    }
+
+    /// Makes a read-only `eth_call` through the wallet's node, returns the hex encoded result
+    pub async fn call(&self, transaction: TypedTransaction) -> Result<String, ServerError> {
+        self.wait_for_reply(
+            comm::AsyncRequestContent::Call { transaction },
+            |res| match res {
+                comm::AsyncResponseContent::CallResult { data } => Some(data.clone()),
+                _ => None,
+            },
+            self.request_timeout(),
+        )
+        .await
+    }
+
+    /// Sends any EIP-1193 request to the wallet, returns its result as is
+    pub async fn request_raw(
+        &self,
+        method: String,
+        params: serde_json::Value,
+    ) -> Result<serde_json::Value, ServerError> {
+        self.wait_for_reply(
+            comm::AsyncRequestContent::RawRequest { method, params },
+            |res| match res {
+                comm::AsyncResponseContent::RawResult { result } => Some(result.clone()),
+                _ => None,
+            },
+            self.request_timeout(),
         )
         .await
     }
@@ -241,40 +785,32 @@ impl Server {
         pred: fn(&comm::AsyncResponseContent) -> Option<U>,
         timeout: Duration,
     ) -> Result<U, ServerError> {
-        // TODO: should be wrapped in a mutex
+        // a channel per request, so concurrent calls don't receive each other's replies
+        let (sender, receiver) = oneshot::channel();
         let id = self.gen_id();
-        let req: comm::AsyncRequest = comm::AsyncRequest { id: id.clone(), content: req_content };
+        // otherwise it would still be sent to the next browser to connect (e.g. on timeout)
+        let mut cancel = comm::CancelGuard::new(self.comm.clone(), id.clone());
+        let req = comm::AsyncRequest { id, content: req_content, reply_to: sender, rpc: false };
         self.comm.send(req).await.map_err(|_| ServerError::Comm("internal error".to_owned()))?;
 
-        // one request at a time
-        let receiver = self.comm_receiver.lock().expect("poisoned mutex");
-
-        let start = Instant::now();
-        while start.elapsed() < timeout {
-            let res = receiver.try_recv();
-            match res {
-                Ok(res) => {
-                    if res.id == id {
-                        return match pred(&res.content) {
-                            Some(res) => Ok(res),
-                            None => match res.content {
-                                comm::AsyncResponseContent::Error { error } => {
-                                    Err(ServerError::Client(error))
-                                }
-                                _ => Err(ServerError::Comm("unexpected response".to_string())),
-                            },
-                        }
-                    }
-                    // ignore ids that don't match
+        let res = match rt::time::timeout(timeout, receiver).await {
+            Ok(Ok(res)) => res,
+            Ok(Err(_)) => return Err(ServerError::Comm("disconnected".to_string())),
+            Err(_) => return Err(ServerError::Comm("timeout".to_string())),
+        };
+        cancel.disarm();
+        match pred(&res.content) {
+            Some(res) => Ok(res),
+            None => match res.content {
+                comm::AsyncResponseContent::Error { error, code: Some(code), data } => {
+                    Err(ServerError::Wallet { message: error, code, data })
                 }
-                Err(mpsc::TryRecvError::Empty) => (),
-                Err(mpsc::TryRecvError::Disconnected) => {
-                    return Err(ServerError::Comm("disconnected".to_string()))
-                }
-            }
-            sleep(Duration::from_millis(100));
+                comm::AsyncResponseContent::Error { error, .. } => Err(ServerError::Client(error)),
+                comm::AsyncResponseContent::NoWallet { error } => Err(ServerError::NoWallet(error)),
+                comm::AsyncResponseContent::Cancelled {} => Err(ServerError::Cancelled),
+                _ => Err(ServerError::Comm(format!("unexpected response to {}", res.id))),
+            },
         }
-        Err(ServerError::Comm("timeout".to_string()))
     }
 
     fn gen_id(&self) -> String {
@@ -284,10 +820,529 @@ impl Server {
 
 impl Drop for Server {
     fn drop(&mut self) {
-        let handle = self.server.clone();
+        self.stop(false, |_| {});
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::core::types::TransactionRequest;
+    use ethers_signers_browser_frontend::ws::messages::{
+        Request, RequestContent, Response, ResponseContent,
+    };
+    use std::{io::BufReader, net::TcpStream};
+
+    #[tokio::test]
+    async fn it_serves_from_a_provided_listener() {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let server = Server::new(
+            1,
+            None,
+            FrontendConfig::default(),
+            Some(ServerOptions { listener: Some(listener), ..Default::default() }),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(server.port(), port);
+        assert!(server.wait_until_ready(Duration::from_secs(1)).await);
+    }
+
+    #[tokio::test]
+    async fn it_shares_a_runtime_between_servers() {
+        let opts = || Some(ServerOptions { shared_runtime: Some(true), ..Default::default() });
+        let first = Server::new(1, None, FrontendConfig::default(), opts()).await.unwrap();
+        let second = Server::new(10, None, FrontendConfig::default(), opts()).await.unwrap();
+
+        assert_ne!(first.port(), second.port());
+        assert!(first.wait_until_ready(Duration::from_secs(1)).await);
+        assert!(second.wait_until_ready(Duration::from_secs(1)).await);
+        drop(first);
+        assert!(second.wait_until_ready(Duration::from_secs(1)).await);
+    }
+
+    fn send(port: u16, method: &str, path: &str, body: &str) -> String {
+        use std::io::{Read, Write};
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        write!(
+            stream,
+            "{} {} HTTP/1.0\r\nContent-Length: {}\r\n\r\n{}",
+            method,
+            path,
+            body.len(),
+            body
+        )
+        .unwrap();
+        let mut res = String::new();
+        stream.read_to_string(&mut res).unwrap();
+        res
+    }
+
+    fn get(port: u16, path: &str) -> String {
+        send(port, "GET", path, "")
+    }
+
+    /// Starts a websocket handshake, only the status line is read as an accepted connection stays
+    /// open
+    fn ws_status(port: u16, path: &str) -> String {
+        use std::io::{BufRead, BufReader, Write};
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        write!(
+            stream,
+            "GET {} HTTP/1.1\r\nHost: 127.0.0.1\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+             Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n",
+            path
+        )
+        .unwrap();
+        let mut status = String::new();
+        BufReader::new(stream).read_line(&mut status).unwrap();
+        status.trim_end().to_owned()
+    }
+
+    /// Stands in for the page over a real websocket, text frames only
+    struct TestBrowser {
+        reader: BufReader<TcpStream>,
+        writer: TcpStream,
+    }
+
+    impl TestBrowser {
+        fn connect(port: u16, path: &str) -> Self {
+            use std::io::{BufRead, Write};
+            let mut writer = TcpStream::connect(("127.0.0.1", port)).unwrap();
+            writer.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            write!(
+                writer,
+                "GET {} HTTP/1.1\r\nHost: 127.0.0.1\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+                 Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n",
+                path
+            )
+            .unwrap();
+            let mut reader = BufReader::new(writer.try_clone().unwrap());
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            assert!(line.starts_with("HTTP/1.1 101"), "{}", line);
+            while line != "\r\n" {
+                line.clear();
+                reader.read_line(&mut line).unwrap();
+            }
+            Self { reader, writer }
+        }
+
+        /// The next request from the server, skipping pings
+        fn receive(&mut self) -> Request {
+            use std::io::Read;
+            loop {
+                let mut header = [0; 2];
+                self.reader.read_exact(&mut header).unwrap();
+                let len = match header[1] & 0x7f {
+                    126 => {
+                        let mut len = [0; 2];
+                        self.reader.read_exact(&mut len).unwrap();
+                        u16::from_be_bytes(len) as usize
+                    }
+                    len => len as usize,
+                };
+                let mut payload = vec![0; len];
+                self.reader.read_exact(&mut payload).unwrap();
+                if header[0] & 0x0f == 1 {
+                    return serde_json::from_slice(&payload).unwrap()
+                }
+            }
+        }
+
+        fn reply(&mut self, id: &str, content: ResponseContent) {
+            use std::io::Write;
+            let data = serde_json::to_vec(&Response { id: id.to_owned(), content }).unwrap();
+            let mut frame = vec![0x81];
+            if data.len() < 126 {
+                frame.push(0x80 | data.len() as u8);
+            } else {
+                frame.push(0x80 | 126);
+                frame.extend((data.len() as u16).to_be_bytes());
+            }
+            // clients must mask their frames, a zero key leaves the payload as is
+            frame.extend([0; 4]);
+            frame.extend(data);
+            self.writer.write_all(&frame).unwrap();
+        }
+
+        /// Answers the init, the server then sends the pending requests
+        fn init(&mut self) {
+            let req = self.receive();
+            assert!(matches!(req.content, RequestContent::Init { .. }));
+            self.reply(&req.id, ResponseContent::Init {});
+        }
+    }
+
+    /// Signs with the message itself, a request answered out of order gets the wrong one
+    fn answer_sign(browser: &mut TestBrowser, req: Request) {
+        let signature = match req.content {
+            RequestContent::SignTextMessage { message, .. } => message,
+            content => panic!("unexpected request: {:?}", content),
+        };
+        browser.reply(&req.id, ResponseContent::MessageSignature { signature });
+    }
+
+    #[tokio::test]
+    async fn it_handles_concurrent_requests() {
+        let config = FrontendConfig { review_requests: Some(false), ..Default::default() };
+        let opts = ServerOptions {
+            nonce: Some("abc".to_owned()),
+            max_concurrent_prompts: Some(2),
+            ..Default::default()
+        };
+        let server = Server::new(1, None, config, Some(opts)).await.unwrap();
+        server.set_request_timeout(Some(Duration::from_secs(5)));
+        let port = server.port();
+        let (done, answered) = mpsc::channel();
+        let browser = thread::spawn(move || {
+            let mut browser = TestBrowser::connect(port, "/ws/?nonce=abc");
+            browser.init();
+            // both are in flight, the first one is only answered once the second one returned
+            let first = browser.receive();
+            let second = browser.receive();
+            answer_sign(&mut browser, second);
+            answered.recv_timeout(Duration::from_secs(5)).unwrap();
+            answer_sign(&mut browser, first);
+        });
+
+        let sign = |message: &str| server.sign_text_message(Address::zero(), message.to_owned());
+        let (one, two) = tokio::join!(sign("one"), async {
+            let res = sign("two").await;
+            done.send(()).unwrap();
+            res
+        });
+        assert_eq!(two.unwrap(), "two");
+        assert_eq!(one.unwrap(), "one");
+        browser.join().unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_reads_while_a_signature_is_pending() {
+        let opts = ServerOptions { nonce: Some("abc".to_owned()), ..Default::default() };
+        let server = Server::new(1, None, FrontendConfig::default(), Some(opts)).await.unwrap();
+        server.set_request_timeout(Some(Duration::from_secs(5)));
+        let port = server.port();
+        let (done, answered) = mpsc::channel();
+        let browser = thread::spawn(move || {
+            let mut browser = TestBrowser::connect(port, "/ws/?nonce=abc");
+            browser.init();
+            // the signature is only given once the call returned
+            let (mut sign, mut call) = (browser.receive(), browser.receive());
+            if matches!(sign.content, RequestContent::Call { .. }) {
+                std::mem::swap(&mut sign, &mut call);
+            }
+            assert!(matches!(call.content, RequestContent::Call { .. }));
+            browser.reply(&call.id, ResponseContent::CallResult { data: "0x01".to_owned() });
+            answered.recv_timeout(Duration::from_secs(5)).unwrap();
+            answer_sign(&mut browser, sign);
+        });
+
+        let (signature, data) =
+            tokio::join!(server.sign_text_message(Address::zero(), "one".to_owned()), async {
+                let res = server.call(TransactionRequest::new().into()).await;
+                done.send(()).unwrap();
+                res
+            });
+        assert_eq!(data.unwrap(), "0x01");
+        assert_eq!(signature.unwrap(), "one");
+        browser.join().unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_serves_a_custom_frontend() {
+        let mut files = HashMap::new();
+        files.insert("index.html".to_owned(), b"<h1>custom</h1>".to_vec());
+        files.insert("app.js".to_owned(), b"connect()".to_vec());
+        let server = Server::new(
+            1,
+            None,
+            FrontendConfig::default(),
+            Some(ServerOptions {
+                nonce: Some("abc".to_owned()),
+                custom_frontend: Some(files),
+                ..Default::default()
+            }),
+        )
+        .await
+        .unwrap();
+
+        let res = get(server.port(), "/?nonce=abc");
+        assert!(res.contains(r#"<script id="ethers-signers-browser-config""#));
+        assert!(res.ends_with("<h1>custom</h1>"));
+        let res = get(server.port(), "/dist/app.js");
+        assert!(res.contains("javascript"));
+        assert!(res.ends_with("connect()"));
+        let res = get(server.port(), "/dist/index.js");
+        assert!(res.starts_with("HTTP/1.0 404"));
+    }
+
+    #[tokio::test]
+    async fn it_serves_json_rpc_when_enabled() {
+        let opts = |rpc| {
+            Some(ServerOptions {
+                nonce: Some("abc".to_owned()),
+                rpc: Some(rpc),
+                ..Default::default()
+            })
+        };
+        let body = r#"{"jsonrpc":"2.0","id":7,"method":"eth_sendTransaction","params":[]}"#;
+
+        let server = Server::new(1, None, FrontendConfig::default(), opts(true)).await.unwrap();
+        let res = send(server.port(), "POST", "/rpc?nonce=abc", body);
+        assert!(res.contains(r#""id":7"#));
+        assert!(res.contains(r#""code":-32601"#));
+        let res = send(server.port(), "POST", "/rpc?nonce=wrong", body);
+        assert!(res.starts_with("HTTP/1.0 404"));
+
+        let server = Server::new(1, None, FrontendConfig::default(), opts(false)).await.unwrap();
+        let res = send(server.port(), "POST", "/rpc?nonce=abc", body);
+        assert!(res.starts_with("HTTP/1.0 404"));
+    }
+
+    #[tokio::test]
+    async fn it_checks_the_nonce_of_websocket_connections() {
+        let opts = ServerOptions { nonce: Some("abc".to_owned()), ..Default::default() };
+        let server = Server::new(1, None, FrontendConfig::default(), Some(opts)).await.unwrap();
+
+        assert!(ws_status(server.port(), "/ws/").starts_with("HTTP/1.1 400"));
+        assert!(ws_status(server.port(), "/ws/?nonce=wrong").starts_with("HTTP/1.1 404"));
+        assert!(ws_status(server.port(), "/ws/?nonce=abc").starts_with("HTTP/1.1 101"));
+    }
+
+    #[tokio::test]
+    async fn it_times_out_unanswered_requests() {
+        let opts =
+            ServerOptions { nonce: Some("abc".to_owned()), rpc: Some(true), ..Default::default() };
+        let server = Server::new(1, None, FrontendConfig::default(), Some(opts)).await.unwrap();
+        server.set_request_timeout(Some(Duration::from_millis(200)));
+
+        let res = server.sign_text_message(Address::zero(), "hello".to_owned()).await;
+        assert!(matches!(res, Err(ServerError::Comm(e)) if e == "timeout"));
+        let body = r#"{"jsonrpc":"2.0","id":7,"method":"eth_accounts","params":[]}"#;
+        let res = send(server.port(), "POST", "/rpc?nonce=abc", body);
+        assert!(res.contains(r#""message":"timeout""#));
+    }
+
+    #[tokio::test]
+    async fn it_releases_the_port_on_shutdown() {
+        let server = Server::new(1, None, FrontendConfig::default(), None).await.unwrap();
+        assert!(server.wait_until_ready(Duration::from_secs(1)).await);
+
+        server.shutdown().await.unwrap();
+        TcpListener::bind(("127.0.0.1", server.port())).unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_mounts_into_an_existing_app() {
+        let mut files = HashMap::new();
+        files.insert("index.html".to_owned(), b"<h1>custom</h1>".to_vec());
+        let (server, routes) = Server::new_embedded(
+            1,
+            None,
+            FrontendConfig::default(),
+            Some(ServerOptions {
+                nonce: Some("abc".to_owned()),
+                public_path: Some("/signer/".to_owned()),
+                custom_frontend: Some(files),
+                ..Default::default()
+            }),
+        )
+        .unwrap();
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
         thread::spawn(move || {
-            let fut = handle.stop(false);
-            rt::System::new().block_on(fut)
+            rt::System::new().block_on(async move {
+                HttpServer::new(move || {
+                    let routes = routes.clone();
+                    App::new().service(web::scope("/signer").configure(move |cfg| {
+                        routes.configure(cfg);
+                    }))
+                })
+                .listen(listener)
+                .unwrap()
+                .run()
+                .await
+            })
         });
+
+        assert_eq!(server.port(), 0);
+        let res = get(port, "/signer/?nonce=abc");
+        assert!(res.contains("/signer/ws/"));
+        assert!(res.ends_with("<h1>custom</h1>"));
+        assert!(get(port, "/signer/nonce/?nonce=wrong").starts_with("HTTP/1.0 404"));
+        assert!(get(port, "/?nonce=abc").starts_with("HTTP/1.0 404"));
+        server.shutdown().await.unwrap();
+    }
+
+    #[test]
+    fn it_signs_from_a_handler_of_the_app() {
+        async fn sign(server: web::Data<Server>) -> String {
+            let res = server.sign_text_message(Address::zero(), "hello".to_owned()).await;
+            res.unwrap_or_else(|e| e.to_string())
+        }
+
+        let opts = ServerOptions { nonce: Some("abc".to_owned()), ..Default::default() };
+        let (server, routes) =
+            Server::new_embedded(1, None, FrontendConfig::default(), Some(opts)).unwrap();
+        server.set_request_timeout(Some(Duration::from_secs(5)));
+        let server = web::Data::new(server);
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            rt::System::new().block_on(async move {
+                HttpServer::new(move || {
+                    let routes = routes.clone();
+                    App::new()
+                        .app_data(server.clone())
+                        .route("/sign", web::get().to(sign))
+                        .configure(move |cfg| routes.configure(cfg))
+                })
+                // the handler and the browser's session share the only worker
+                .workers(1)
+                .listen(listener)
+                .unwrap()
+                .run()
+                .await
+            })
+        });
+
+        let mut browser = TestBrowser::connect(port, "/ws/?nonce=abc");
+        browser.init();
+        let signing = thread::spawn(move || get(port, "/sign"));
+        let req = browser.receive();
+        answer_sign(&mut browser, req);
+        assert!(signing.join().unwrap().ends_with("\r\n\r\nhello"));
+    }
+
+    #[test]
+    fn it_derives_the_page_config() {
+        let config = |public_path: Option<&str>| {
+            ServerOptions { public_path: public_path.map(str::to_owned), ..Default::default() }
+                .page_config()
+        };
+        assert_eq!(config(None).ws_path, "/ws/");
+        let opts = ServerOptions { base_path: Some("/wallet".to_owned()), ..Default::default() };
+        assert_eq!(opts.page_config().ws_path, "/wallet/ws/");
+        assert_eq!(config(Some("signer")).base_path, "/signer/");
+        assert_eq!(config(Some("/signer/")).ws_path, "/signer/ws/");
+    }
+
+    #[tokio::test]
+    async fn it_serves_under_a_base_path() {
+        let mut files = HashMap::new();
+        files.insert("index.html".to_owned(), br#"<script src="/dist/app.js"></script>"#.to_vec());
+        files.insert("app.js".to_owned(), b"connect()".to_vec());
+        let server = Server::new(
+            1,
+            None,
+            FrontendConfig::default(),
+            Some(ServerOptions {
+                nonce: Some("abc".to_owned()),
+                base_path: Some("wallet".to_owned()),
+                custom_frontend: Some(files),
+                ..Default::default()
+            }),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(server.base_path(), "/wallet/");
+        let res = get(server.port(), "/wallet/?nonce=abc");
+        assert!(res.contains(r#"<script src="/wallet/dist/app.js">"#));
+        assert!(res.contains("/wallet/ws/"));
+        assert!(get(server.port(), "/wallet/dist/app.js").ends_with("connect()"));
+        let res = get(server.port(), "/wallet?nonce=abc");
+        assert!(res.starts_with("HTTP/1.0 308"));
+        assert!(res.to_lowercase().contains("location: /wallet/?nonce=abc"));
+        assert!(get(server.port(), "/?nonce=abc").starts_with("HTTP/1.0 404"));
+    }
+
+    #[cfg(not(feature = "tls"))]
+    #[tokio::test]
+    async fn it_refuses_tls_without_the_feature() {
+        let tls = TlsConfig { cert_chain: vec![], private_key: vec![] };
+        let opts = ServerOptions { tls: Some(tls), ..Default::default() };
+        let res = Server::new(1, None, FrontendConfig::default(), Some(opts)).await;
+        assert!(matches!(res, Err(ServerError::Init(e)) if e.contains("`tls` feature")));
+    }
+
+    #[cfg(feature = "tls")]
+    #[tokio::test]
+    async fn it_serves_over_tls() {
+        use std::io::{Read, Write};
+        let cert = include_bytes!("testdata/localhost.crt");
+        let tls = TlsConfig {
+            cert_chain: cert.to_vec(),
+            private_key: include_bytes!("testdata/localhost.key").to_vec(),
+        };
+        let server = Server::new(
+            1,
+            None,
+            FrontendConfig::default(),
+            Some(ServerOptions {
+                nonce: Some("abc".to_owned()),
+                tls: Some(tls),
+                ..Default::default()
+            }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(server.scheme(), "https");
+
+        let mut roots = rustls::RootCertStore::empty();
+        let certs = rustls_pemfile::certs(&mut cert.as_slice()).unwrap();
+        roots.add(&rustls::Certificate(certs[0].clone())).unwrap();
+        let config = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let mut client =
+            rustls::ClientConnection::new(Arc::new(config), "localhost".try_into().unwrap())
+                .unwrap();
+        let mut stream = TcpStream::connect(("127.0.0.1", server.port())).unwrap();
+        let mut stream = rustls::Stream::new(&mut client, &mut stream);
+        stream.write_all(b"GET /nonce/?nonce=abc HTTP/1.0\r\n\r\n").unwrap();
+        let mut res = vec![];
+        // the server may close the connection without a TLS close notification
+        let _ = stream.read_to_end(&mut res);
+        assert!(String::from_utf8_lossy(&res).starts_with("HTTP/1.0 204"));
+    }
+
+    #[tokio::test]
+    async fn it_listens_on_the_configured_host() {
+        let opts = Some(ServerOptions { host: Some("0.0.0.0".to_owned()), ..Default::default() });
+        let server = Server::new(1, None, FrontendConfig::default(), opts).await.unwrap();
+
+        assert_eq!(server.url_host(), "localhost");
+        assert!(server.wait_until_ready(Duration::from_secs(1)).await);
+        assert!(get(server.port(), "/nonce/?nonce=wrong").starts_with("HTTP/1.0 404"));
+    }
+
+    #[tokio::test]
+    async fn it_serves_the_branding() {
+        let server = Server::new(
+            1,
+            None,
+            FrontendConfig::default(),
+            Some(ServerOptions {
+                app_name: Some("My Deployer".to_owned()),
+                favicon: Some(b"icon".to_vec()),
+                ..Default::default()
+            }),
+        )
+        .await
+        .unwrap();
+
+        let res = get(server.port(), "/manifest.webmanifest");
+        assert!(res.contains("application/manifest+json"));
+        assert!(!res.contains("start_url"));
+        assert!(res.contains(r#""name":"My Deployer""#));
+        let res = get(server.port(), "/favicon.ico");
+        assert!(res.contains("image/x-icon"));
+        assert!(res.ends_with("\r\n\r\nicon"));
     }
 }
@@ -1,21 +1,83 @@
-use super::{comm::CommServer, session::WSFlow};
+use super::{comm::CommServer, rpc, session::WSFlow};
 use actix::Addr;
 use actix_web::{web, Error, HttpRequest, HttpResponse, Responder};
 use actix_web_actors::ws;
+use ethers_signers_browser_frontend::ws::messages::{
+    MessageTracing, PageConfig, PAGE_CONFIG_ELEMENT_ID,
+};
 use mime_guess::from_path;
 use rust_embed::RustEmbed;
 use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
 #[derive(RustEmbed)]
 #[folder = "$OUT_DIR/frontend"]
 struct Asset;
 
-fn handle_embedded_file(path: &str) -> HttpResponse {
-    match Asset::get(path) {
-        Some(content) => HttpResponse::Ok()
-            .content_type(from_path(path).first_or_octet_stream().as_ref())
-            .body(content.data.into_owned()),
-        None => HttpResponse::NotFound().body("404 Not Found"),
+/// Adds the page config to the `<head>` of `html` (or at the start if there is none)
+fn inject_page_config(html: &[u8], config: &PageConfig) -> Vec<u8> {
+    let html = String::from_utf8_lossy(html);
+    // `</` can't appear inside a script element
+    let json = serde_json::to_string(config).unwrap_or_default().replace("</", "<\\/");
+    let script = format!(
+        r#"<script id="{}" type="application/json">{}</script>"#,
+        PAGE_CONFIG_ELEMENT_ID, json
+    );
+    let html = rebase_dist_urls(&html, &config.base_path);
+    match html.find("</head>") {
+        Some(end) => format!("{}{}{}", &html[..end], script, &html[end..]),
+        None => format!("{}{}", script, html),
+    }
+    .into_bytes()
+}
+
+/// The frontend is built with absolute `/dist/` URLs, which need the base path when it isn't `/`
+fn rebase_dist_urls(html: &str, base_path: &str) -> String {
+    if base_path == "/" {
+        return html.to_owned()
+    }
+    html.replace("\"/dist/", &format!("\"{}dist/", base_path))
+        .replace("'/dist/", &format!("'{}dist/", base_path))
+}
+
+/// The files served to the browser, either the embedded frontend or the one from
+/// `ServerOptions::custom_frontend`
+pub(super) struct Frontend {
+    pub page_config: PageConfig,
+    pub custom: Option<HashMap<String, Vec<u8>>>,
+}
+
+impl Frontend {
+    fn file(&self, path: &str) -> Option<Vec<u8>> {
+        match &self.custom {
+            Some(files) => files.get(path).cloned(),
+            None => Asset::get(path).map(|content| content.data.into_owned()),
+        }
+    }
+
+    fn serve(&self, path: &str) -> HttpResponse {
+        match self.file(path) {
+            Some(content) => HttpResponse::Ok()
+                .content_type(from_path(path).first_or_octet_stream().as_ref())
+                .body(content),
+            None => HttpResponse::NotFound().body("404 Not Found"),
+        }
+    }
+
+    fn serve_index(&self) -> HttpResponse {
+        match self.file("index.html") {
+            Some(content) => HttpResponse::Ok()
+                .content_type("text/html; charset=utf-8")
+                .body(inject_page_config(&content, &self.page_config)),
+            None => HttpResponse::NotFound().body("404 Not Found"),
+        }
     }
 }
 
@@ -28,23 +90,126 @@ pub(super) struct IndexQuery {
 pub(super) async fn index(
     info: web::Query<IndexQuery>,
     nonce: web::Data<String>,
+    frontend: web::Data<Frontend>,
+) -> impl Responder {
+    if info.nonce != **nonce {
+        return HttpResponse::NotFound().body("404 Not Found")
+    }
+    frontend.serve_index()
+}
+
+/// Lets a page check whether its nonce is still valid, e.g. after the command was restarted
+#[actix_web::get("/nonce/")]
+pub(super) async fn check_nonce(
+    info: web::Query<IndexQuery>,
+    nonce: web::Data<String>,
+) -> impl Responder {
+    if info.nonce != **nonce {
+        return HttpResponse::NotFound().body("404 Not Found")
+    }
+    HttpResponse::NoContent().finish()
+}
+
+#[derive(Clone)]
+pub(super) struct RPCSettings {
+    /// Shared with `Server`, see `Server::set_strict_verifying_contract`
+    pub strict_verifying_contract: Arc<AtomicBool>,
+    /// In milliseconds, shared with `Server`, see `Server::set_request_timeout`
+    pub request_timeout: Arc<AtomicU64>,
+}
+
+/// The JSON-RPC endpoint, only registered when `ServerOptions::rpc` is set
+#[actix_web::post("/rpc")]
+pub(super) async fn rpc_call(
+    info: web::Query<IndexQuery>,
+    nonce: web::Data<String>,
+    comm: web::Data<Addr<CommServer>>,
+    settings: web::Data<RPCSettings>,
+    body: web::Bytes,
 ) -> impl Responder {
     if info.nonce != **nonce {
         return HttpResponse::NotFound().body("404 Not Found")
     }
-    handle_embedded_file("index.html")
+    let strict = settings.strict_verifying_contract.load(Ordering::SeqCst);
+    let timeout = Duration::from_millis(settings.request_timeout.load(Ordering::SeqCst));
+    HttpResponse::Ok().json(rpc::handle(comm.get_ref(), &body, strict, timeout).await)
+}
+
+#[derive(Deserialize)]
+pub(super) struct WSQuery {
+    nonce: String,
+    session: Option<String>,
+}
+
+pub(super) struct WSSettings {
+    pub max_message_size: usize,
+    pub trace_messages: Option<MessageTracing>,
 }
 
 #[actix_web::get("/ws/")]
 pub(super) async fn ws_open(
     req: HttpRequest,
     stream: web::Payload,
+    info: web::Query<WSQuery>,
+    nonce: web::Data<String>,
     comm: web::Data<Addr<CommServer>>,
+    settings: web::Data<WSSettings>,
 ) -> Result<HttpResponse, Error> {
-    ws::start(WSFlow::new(comm.get_ref().clone()), &req, stream)
+    if info.nonce != **nonce {
+        return Ok(HttpResponse::NotFound().body("404 Not Found"))
+    }
+    ws::WsResponseBuilder::new(
+        WSFlow::new(comm.get_ref().clone(), info.into_inner().session, settings.trace_messages),
+        &req,
+        stream,
+    )
+    .frame_size(settings.max_message_size)
+    .start()
+}
+
+pub(super) struct Branding {
+    pub app_name: String,
+    pub favicon: Option<Vec<u8>>,
+}
+
+#[actix_web::get("/favicon.ico")]
+pub(super) async fn favicon(
+    branding: web::Data<Branding>,
+    frontend: web::Data<Frontend>,
+) -> impl Responder {
+    match &branding.favicon {
+        Some(favicon) => HttpResponse::Ok().content_type("image/x-icon").body(favicon.clone()),
+        None => frontend.serve("static/icon.ico"),
+    }
+}
+
+#[actix_web::get("/manifest.webmanifest")]
+pub(super) async fn manifest(branding: web::Data<Branding>) -> impl Responder {
+    let manifest = serde_json::json!({
+        "name": branding.app_name,
+        "short_name": branding.app_name,
+        // no `start_url`, it defaults to the page (with its nonce) while this file is public
+        "display": "standalone",
+        // relative to the manifest, so they work under any base path
+        "icons": [
+            { "src": "favicon.ico", "sizes": "48x48", "type": "image/x-icon" },
+            { "src": "dist/static/logo.png", "sizes": "512x512", "type": "image/png" },
+        ],
+    });
+    HttpResponse::Ok().content_type("application/manifest+json").body(manifest.to_string())
+}
+
+/// Redirects `/wallet` to `/wallet/` (keeping the nonce) when `ServerOptions::base_path` is set,
+/// as relative URLs would resolve outside of it
+pub(super) async fn add_trailing_slash(req: HttpRequest) -> HttpResponse {
+    let location = match req.query_string() {
+        "" => format!("{}/", req.path()),
+        query => format!("{}/?{}", req.path(), query),
+    };
+    HttpResponse::PermanentRedirect().insert_header(("Location", location)).finish()
 }
 
 #[actix_web::get("/dist/{_:.*}")]
-pub(super) async fn dist(path: web::Path<String>) -> impl Responder {
-    handle_embedded_file(path.as_str())
+pub(super) async fn dist(path: web::Path<String>, frontend: web::Data<Frontend>) -> impl Responder {
+    frontend.serve(path.as_str())
 }
@@ -0,0 +1,245 @@
+use super::comm::{
+    AsyncRequest, AsyncRequestContent, AsyncResponseContent, CancelGuard, CommServer,
+};
+use crate::typed_data;
+use actix::Addr;
+use actix_web::rt;
+use ethers::{
+    core::types::{transaction::eip2718::TypedTransaction, Address, Bytes, TransactionRequest},
+    utils::hash_message,
+};
+use ethers_signers_browser_frontend::ws::messages::TypedDataVersion;
+use rand::distributions::{Alphanumeric, DistString};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{json, Value};
+use std::{fmt::Display, time::Duration};
+use tokio::sync::oneshot;
+
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+/// EIP-1193's "disconnected" error
+const DISCONNECTED: i64 = 4900;
+
+#[derive(Deserialize)]
+struct RPCRequest {
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Debug, PartialEq)]
+struct RPCError {
+    code: i64,
+    message: String,
+    data: Option<Value>,
+}
+
+impl RPCError {
+    fn new(code: i64, message: impl Display) -> Self {
+        Self { code, message: message.to_string(), data: None }
+    }
+}
+
+fn param<T: DeserializeOwned>(params: &Value, index: usize) -> Result<T, RPCError> {
+    let value = params
+        .get(index)
+        .cloned()
+        .ok_or_else(|| RPCError::new(INVALID_PARAMS, format!("missing parameter {}", index)))?;
+    serde_json::from_value(value)
+        .map_err(|e| RPCError::new(INVALID_PARAMS, format!("invalid parameter {}: {}", index, e)))
+}
+
+/// Same as `BrowserSigner::sign_message`, text is signed as is while binary data is hashed first
+fn message_content(address: Address, message: Bytes) -> AsyncRequestContent {
+    match String::from_utf8(message.to_vec()) {
+        Ok(message) => AsyncRequestContent::SignTextMessage { address, message },
+        Err(_) => AsyncRequestContent::SignBinaryMessage {
+            address,
+            message: hash_message(&message),
+            original: Some(message),
+        },
+    }
+}
+
+fn parse_transaction(value: Value) -> Result<TypedTransaction, RPCError> {
+    let tx = match value.get("type") {
+        Some(_) => serde_json::from_value::<TypedTransaction>(value),
+        None => serde_json::from_value::<TransactionRequest>(value).map(Into::into),
+    };
+    tx.map_err(|e| RPCError::new(INVALID_PARAMS, format!("invalid transaction: {}", e)))
+}
+
+/// `strict` rejects typed data whose `verifyingContract` has an invalid checksum, see
+/// `Server::set_strict_verifying_contract`
+fn parse_request(
+    method: &str,
+    params: &Value,
+    strict: bool,
+) -> Result<AsyncRequestContent, RPCError> {
+    match method {
+        "eth_accounts" | "eth_requestAccounts" => Ok(AsyncRequestContent::Accounts {}),
+        "eth_sign" => Ok(message_content(param(params, 0)?, param(params, 1)?)),
+        "personal_sign" => Ok(message_content(param(params, 1)?, param(params, 0)?)),
+        "eth_signTransaction" => Ok(AsyncRequestContent::SignTransaction {
+            transaction: parse_transaction(param(params, 0)?)?,
+        }),
+        "eth_signTypedData_v3" | "eth_signTypedData_v4" => {
+            let version =
+                if method.ends_with("v3") { TypedDataVersion::V3 } else { TypedDataVersion::V4 };
+            let typed_data = typed_data::from_param(&param::<Value>(params, 1)?, strict)
+                .map_err(|e| RPCError::new(INVALID_PARAMS, format!("invalid typed data: {}", e)))?;
+            Ok(AsyncRequestContent::SignTypedData {
+                address: param(params, 0)?,
+                typed_data,
+                version,
+            })
+        }
+        _ => Err(RPCError::new(METHOD_NOT_FOUND, format!("method `{}` is not supported", method))),
+    }
+}
+
+async fn call_comm(
+    comm: &Addr<CommServer>,
+    content: AsyncRequestContent,
+    timeout: Duration,
+) -> Result<Value, RPCError> {
+    let (sender, receiver) = oneshot::channel();
+    let id = Alphanumeric.sample_string(&mut rand::thread_rng(), 16);
+    // the client might disconnect (dropping this future) before the browser answers
+    let mut cancel = CancelGuard::new(comm.clone(), id.clone());
+    comm.send(AsyncRequest { id, content, reply_to: sender, rpc: true })
+        .await
+        .map_err(|_| RPCError::new(INTERNAL_ERROR, "internal error"))?;
+
+    match rt::time::timeout(timeout, receiver).await {
+        Ok(Ok(res)) => {
+            cancel.disarm();
+            match res.content {
+                AsyncResponseContent::Accounts { accounts } => Ok(json!(accounts)),
+                AsyncResponseContent::MessageSignature { signature } |
+                AsyncResponseContent::TransactionSignature { signature } => Ok(json!(signature)),
+                AsyncResponseContent::TransactionHash { hash } => Ok(json!(hash)),
+                AsyncResponseContent::CallResult { data } => Ok(json!(data)),
+                AsyncResponseContent::RawResult { result } => Ok(result),
+                AsyncResponseContent::NoWallet { error } => Err(RPCError::new(DISCONNECTED, error)),
+                AsyncResponseContent::Cancelled {} => {
+                    Err(RPCError::new(INTERNAL_ERROR, "request cancelled"))
+                }
+                AsyncResponseContent::Error { error, code, data } => {
+                    Err(RPCError { code: code.unwrap_or(INTERNAL_ERROR), message: error, data })
+                }
+            }
+        }
+        Ok(Err(_)) => Err(RPCError::new(INTERNAL_ERROR, "disconnected")),
+        Err(_) => Err(RPCError::new(INTERNAL_ERROR, "timeout")),
+    }
+}
+
+/// Handles a single JSON-RPC request (batches are not supported) by forwarding it to the browser
+pub(super) async fn handle(
+    comm: &Addr<CommServer>,
+    body: &[u8],
+    strict: bool,
+    timeout: Duration,
+) -> Value {
+    let (id, res) = match serde_json::from_slice::<RPCRequest>(body) {
+        Ok(req) => {
+            let res = match parse_request(&req.method, &req.params, strict) {
+                Ok(content) => call_comm(comm, content, timeout).await,
+                Err(e) => Err(e),
+            };
+            (req.id, res)
+        }
+        Err(e) => (Value::Null, Err(RPCError::new(PARSE_ERROR, e))),
+    };
+    match res {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(RPCError { code, message, data }) => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": code, "message": message, "data": data },
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_maps_methods_to_requests() {
+        let address = "0xcd2a3d9f938e13cd947ec05abc7fe734df8dd826";
+        let req = parse_request("personal_sign", &json!(["0x68656c6c6f", address]), false).unwrap();
+        assert!(matches!(
+            req,
+            AsyncRequestContent::SignTextMessage { message, .. } if message == "hello"
+        ));
+        let req = parse_request("eth_sign", &json!([address, "0xff00"]), false).unwrap();
+        assert!(matches!(
+            req,
+            AsyncRequestContent::SignBinaryMessage { original: Some(original), .. }
+                if original.to_vec() == vec![0xff, 0x00]
+        ));
+        let req = parse_request(
+            "eth_signTransaction",
+            &json!([{ "type": "0x2", "from": address, "to": address, "chainId": "0x1" }]),
+            false,
+        )
+        .unwrap();
+        assert!(matches!(
+            req,
+            AsyncRequestContent::SignTransaction { transaction: TypedTransaction::Eip1559(_) }
+        ));
+        let req = parse_request("eth_signTransaction", &json!([{ "to": address }]), false).unwrap();
+        assert!(matches!(
+            req,
+            AsyncRequestContent::SignTransaction { transaction: TypedTransaction::Legacy(_) }
+        ));
+    }
+
+    #[test]
+    fn it_rejects_invalid_requests() {
+        assert_eq!(
+            parse_request("eth_sendTransaction", &json!([]), false).unwrap_err().code,
+            METHOD_NOT_FOUND
+        );
+        assert_eq!(
+            parse_request("eth_sign", &json!(["0x12"]), false).unwrap_err().code,
+            INVALID_PARAMS
+        );
+        assert_eq!(
+            parse_request("eth_signTypedData_v4", &json!(["0x12", "{}"]), false).unwrap_err().code,
+            INVALID_PARAMS
+        );
+    }
+
+    #[test]
+    fn it_checks_the_verifying_contract() {
+        let typed_data = |contract: &str| {
+            json!({
+                "types": {
+                    "EIP712Domain": [{ "name": "verifyingContract", "type": "address" }],
+                    "Mail": [{ "name": "contents", "type": "string" }],
+                },
+                "primaryType": "Mail",
+                "domain": { "verifyingContract": contract },
+                "message": { "contents": "Hello, Bob!" },
+            })
+        };
+        let address = "0xcd2a3d9f938e13cd947ec05abc7fe734df8dd826";
+        let typo = typed_data("0xCcCCccccCCCCcCCCCCCcCcCccCcCCCcCcccccccc");
+        let params = json!([address, typo.to_string()]);
+        assert!(parse_request("eth_signTypedData_v4", &params, false).is_ok());
+        let err = parse_request("eth_signTypedData_v4", &params, true).unwrap_err();
+        assert_eq!(err.code, INVALID_PARAMS);
+        assert!(err.message.starts_with("invalid typed data: verifyingContract"));
+        assert!(parse_request("eth_signTypedData_v3", &json!([address, typo]), true).is_err());
+
+        let lowercase = typed_data("0xcccccccccccccccccccccccccccccccccccccccc");
+        assert!(parse_request("eth_signTypedData_v4", &json!([address, lowercase]), true).is_ok());
+    }
+}
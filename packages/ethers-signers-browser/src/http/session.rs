@@ -3,29 +3,41 @@ use actix::{prelude::*, Actor, StreamHandler};
 use actix_web_actors::ws;
 use bytestring::ByteString;
 use ethers_signers_browser_frontend::ws::messages::{
-    Request, RequestContent, Response, ResponseContent,
+    MessageTracing, Request, RequestContent, Response, ResponseContent, SERVER_SHUTDOWN_REASON,
 };
-use log::{error, warn};
 use serde_json::Result as SerdeResult;
 use std::time::{Duration, Instant};
+use tracing::{debug, error, warn};
 
 const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
 const CLIENT_TIMEOUT: Duration = Duration::from_secs(30);
 
 pub(super) struct WSFlow {
     comm: Addr<comm::CommServer>,
+    session: Option<String>,
     last_heartbeat: Instant,
+    trace_messages: Option<MessageTracing>,
 }
 
 impl WSFlow {
-    pub fn new(comm: Addr<comm::CommServer>) -> Self {
-        Self { comm, last_heartbeat: Instant::now() }
+    pub fn new(
+        comm: Addr<comm::CommServer>,
+        session: Option<String>,
+        trace_messages: Option<MessageTracing>,
+    ) -> Self {
+        Self { comm, session, last_heartbeat: Instant::now(), trace_messages }
+    }
+
+    fn trace(&self, direction: &str, text: &str) {
+        if let Some(tracing) = self.trace_messages {
+            debug!("websocket {}: {}", direction, tracing.render(text));
+        }
     }
 
     fn forward_to_client(&self, msg: comm::WSRequest) -> Result<SerdeResult<String>, String> {
         let msg = match msg {
-            comm::WSRequest::Init { id, chain_id, chains } => {
-                Request { id, content: RequestContent::Init { chain_id, chains } }
+            comm::WSRequest::Init { id, chain_id, chains, config } => {
+                Request { id, content: RequestContent::Init { chain_id, chains, config } }
             }
             comm::WSRequest::Accounts { id } => {
                 Request { id, content: RequestContent::Accounts {} }
@@ -33,27 +45,42 @@ impl WSFlow {
             comm::WSRequest::SignTextMessage { id, address, message } => {
                 Request { id, content: RequestContent::SignTextMessage { address, message } }
             }
-            comm::WSRequest::SignBinaryMessage { id, address, message } => {
-                Request { id, content: RequestContent::SignBinaryMessage { address, message } }
-            }
+            comm::WSRequest::SignBinaryMessage { id, address, message, original } => Request {
+                id,
+                content: RequestContent::SignBinaryMessage { address, message, original },
+            },
             comm::WSRequest::SignTransaction { id, transaction } => {
                 Request { id, content: RequestContent::SignTransaction { transaction } }
             }
-            comm::WSRequest::SignTypedData { id, address, typed_data } => {
-                Request { id, content: RequestContent::SignTypedData { address, typed_data } }
+            comm::WSRequest::SendTransaction { id, transaction } => {
+                Request { id, content: RequestContent::SendTransaction { transaction } }
             }
+            comm::WSRequest::SignTypedData { id, address, typed_data, version } => Request {
+                id,
+                content: RequestContent::SignTypedData { address, typed_data, version },
+            },
+            comm::WSRequest::Call { id, transaction } => {
+                Request { id, content: RequestContent::Call { transaction } }
+            }
+            comm::WSRequest::RawRequest { id, method, params } => {
+                Request { id, content: RequestContent::RawRequest { method, params } }
+            }
+            comm::WSRequest::Cancel { id } => Request { id, content: RequestContent::Cancel {} },
             comm::WSRequest::Close { reason } => return Err(reason),
         };
         Ok(serde_json::to_string(&msg))
     }
 
     fn forward_to_server(
-        &self,
+        &mut self,
         ctx: &mut <Self as Actor>::Context,
         text: ByteString,
     ) -> SerdeResult<()> {
         let addr = ctx.address().recipient();
         let response: Response = serde_json::from_str(&text)?;
+        if !matches!(response.content, ResponseContent::Ping {}) {
+            self.trace("received", &text);
+        }
         match response.content {
             ResponseContent::Init {} => {
                 self.comm.do_send(comm::WSReply::Init { id: response.id, client: addr });
@@ -79,8 +106,51 @@ impl WSFlow {
                     signature,
                 });
             }
-            ResponseContent::Error { error } => {
-                self.comm.do_send(comm::WSReply::Error { id: response.id, client: addr, error });
+            ResponseContent::TransactionHash { hash } => {
+                self.comm.do_send(comm::WSReply::TransactionHash {
+                    id: response.id,
+                    client: addr,
+                    hash,
+                });
+            }
+            ResponseContent::CallResult { data } => {
+                self.comm.do_send(comm::WSReply::CallResult {
+                    id: response.id,
+                    client: addr,
+                    data,
+                });
+            }
+            ResponseContent::RawResult { result } => {
+                self.comm.do_send(comm::WSReply::RawResult {
+                    id: response.id,
+                    client: addr,
+                    result,
+                });
+            }
+            ResponseContent::Error { error, code, data } => {
+                self.comm.do_send(comm::WSReply::Error {
+                    id: response.id,
+                    client: addr,
+                    error,
+                    code,
+                    data,
+                });
+            }
+            ResponseContent::WalletLoading {} => {
+                self.comm.do_send(comm::WSReply::WalletLoading { id: response.id, client: addr });
+            }
+            ResponseContent::NoWallet { error } => {
+                self.comm.do_send(comm::WSReply::NoWallet { id: response.id, client: addr, error });
+            }
+            ResponseContent::Status { chain_id, accounts } => {
+                self.comm.do_send(comm::WSReply::Status { client: addr, chain_id, accounts });
+            }
+            ResponseContent::Ping {} => {
+                self.last_heartbeat = Instant::now();
+                ctx.text(serde_json::to_string(&Request {
+                    id: response.id,
+                    content: RequestContent::Pong {},
+                })?);
             }
         };
         Ok(())
@@ -119,7 +189,7 @@ impl Actor for WSFlow {
         self.heartbeat(ctx);
 
         let addr = ctx.address().recipient();
-        self.comm.do_send(comm::WSReply::Connect { client: addr });
+        self.comm.do_send(comm::WSReply::Connect { client: addr, session: self.session.take() });
     }
 
     fn stopping(&mut self, ctx: &mut Self::Context) -> Running {
@@ -137,6 +207,7 @@ impl Handler<comm::WSRequest> for WSFlow {
         match self.forward_to_client(msg) {
             Ok(text) => match text {
                 Ok(text) => {
+                    self.trace("sent", &text);
                     ctx.text(text);
                 }
                 Err(e) => {
@@ -147,6 +218,14 @@ impl Handler<comm::WSRequest> for WSFlow {
                     );
                 }
             },
+            // not an error, the page shows that the command finished
+            Err(reason) if reason == SERVER_SHUTDOWN_REASON => {
+                ctx.close(Some(ws::CloseReason {
+                    code: ws::CloseCode::Away,
+                    description: Some(reason),
+                }));
+                ctx.stop();
+            }
             Err(msg) => {
                 self.close(ctx, msg, None);
             }
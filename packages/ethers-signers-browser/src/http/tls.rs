@@ -0,0 +1,93 @@
+#[cfg(feature = "tls")]
+use rustls_pemfile::Item;
+#[cfg(feature = "tls")]
+use std::io::BufReader;
+use std::{fmt, fs, io, path::Path};
+
+/// A certificate chain and its private key in the PEM format, used to serve the page over
+/// `https://` (see the README to generate one for `localhost`)
+#[derive(Clone)]
+pub struct TlsConfig {
+    /// The server certificate followed by its intermediates, if any
+    pub cert_chain: Vec<u8>,
+    /// A PKCS#8, PKCS#1 (RSA) or SEC1 (EC) private key
+    pub private_key: Vec<u8>,
+}
+
+impl TlsConfig {
+    pub fn from_pem_files(
+        cert_chain: impl AsRef<Path>,
+        private_key: impl AsRef<Path>,
+    ) -> io::Result<Self> {
+        Ok(Self { cert_chain: fs::read(cert_chain)?, private_key: fs::read(private_key)? })
+    }
+}
+
+impl fmt::Debug for TlsConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // keeps the private key out of logs
+        f.debug_struct("TlsConfig")
+            .field("cert_chain", &self.cert_chain)
+            .field("private_key", &"<redacted>")
+            .finish()
+    }
+}
+
+#[cfg(feature = "tls")]
+impl TlsConfig {
+    pub(super) fn server_config(&self) -> io::Result<rustls::ServerConfig> {
+        let certs = rustls_pemfile::certs(&mut BufReader::new(self.cert_chain.as_slice()))?;
+        if certs.is_empty() {
+            return Err(invalid("no certificate found"))
+        }
+        let key = match private_key(&self.private_key)? {
+            Some(key) => key,
+            None => return Err(invalid("no private key found")),
+        };
+        rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(certs.into_iter().map(rustls::Certificate).collect(), key)
+            .map_err(|e| invalid(&e.to_string()))
+    }
+}
+
+/// The first private key in `pem`
+#[cfg(feature = "tls")]
+fn private_key(pem: &[u8]) -> io::Result<Option<rustls::PrivateKey>> {
+    let mut reader = BufReader::new(pem);
+    loop {
+        match rustls_pemfile::read_one(&mut reader)? {
+            Some(Item::PKCS8Key(key)) | Some(Item::RSAKey(key)) | Some(Item::ECKey(key)) => {
+                return Ok(Some(rustls::PrivateKey(key)))
+            }
+            Some(_) => {}
+            None => return Ok(None),
+        }
+    }
+}
+
+#[cfg(feature = "tls")]
+fn invalid(error: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, format!("invalid TLS config: {}", error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "tls")]
+    #[test]
+    fn it_rejects_incomplete_configs() {
+        let config = TlsConfig { cert_chain: b"garbage".to_vec(), private_key: vec![] };
+        let error = config.server_config().unwrap_err();
+        assert_eq!(error.to_string(), "invalid TLS config: no certificate found");
+    }
+
+    #[test]
+    fn it_redacts_the_private_key() {
+        let config = TlsConfig { cert_chain: vec![1, 2], private_key: b"secret".to_vec() };
+        let debug = format!("{:?}", config);
+        assert_eq!(debug, r#"TlsConfig { cert_chain: [1, 2], private_key: "<redacted>" }"#);
+    }
+}
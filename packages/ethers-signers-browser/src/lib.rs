@@ -3,20 +3,47 @@
 
 pub use ethers::signers::Signer;
 use ethers::{
-    core::types::{
-        transaction::{eip2718::TypedTransaction, eip712::Eip712},
-        Address, Signature as EthSig,
+    core::{
+        abi::{self, Token},
+        types::{
+            transaction::{eip2718::TypedTransaction, eip712::Eip712},
+            Address, Bytes, Signature as EthSig, SignatureError, TransactionRequest, TxHash, H256,
+            U256,
+        },
     },
     types::transaction::{eip2718::TypedTransactionError, eip712::TypedData},
     utils::{hash_message, hex, rlp},
 };
-pub use ethers_signers_browser_frontend::ws::messages::ChainInfo;
-use http::ServerOptions;
-use log::info;
-use std::{collections::HashMap, str::FromStr};
-use tracing::{instrument, trace};
+pub use ethers_signers_browser_frontend::ws::messages::{
+    ChainInfo, EstimateBump, TypedDataVersion,
+};
+use ethers_signers_browser_frontend::ws::messages::{
+    ChainMismatch, FrontendConfig, CHAIN_MISMATCH_CODE,
+};
+pub use http::{BrowserState, QueueState, ServerOptions, SignerRoutes, TlsConfig};
+use qrcode::render::unicode::Dense1x2;
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+    time::Duration,
+};
+use tracing::{info, instrument, trace, warn};
 
 mod http;
+#[cfg(feature = "tracing-layer")]
+pub mod log_layer;
+mod typed_data;
+
+const DEFAULT_READINESS_TIMEOUT: Duration = Duration::from_secs(2);
+const HEADLESS_CONNECT_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+const DEFAULT_MAX_BROWSER_REOPENS: usize = 2;
+/// EIP-1193's "user rejected request" error code
+const USER_REJECTED_CODE: i64 = 4001;
 
 /// An ethers Signer that uses keys held in a browser-based wallet (e.g. Metamask).
 ///
@@ -34,13 +61,23 @@ mod http;
 /// # Ok(())
 /// # }
 /// ```
+#[derive(Clone)]
 pub struct BrowserSigner {
     chain_id: u64,
-    server: http::Server,
+    /// Shared with the signers returned by `signer_for`, the server stops when the last one is
+    /// dropped
+    server: Arc<http::Server>,
     addresses: Vec<Address>,
+    /// The index in `addresses` of the account used to sign
+    address_index: usize,
     url: String,
+    gas_limit_buffers: HashMap<u64, GasLimitBuffer>,
+    strict_verifying_contract: bool,
 }
 
+/// A `BrowserSigner` bound to one of the wallet's accounts, see `BrowserSigner::signer_for`
+pub type AccountSigner = BrowserSigner;
+
 impl std::fmt::Debug for BrowserSigner {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("BrowserSigner")
@@ -64,13 +101,34 @@ pub enum BrowserSignerError {
     IO(#[from] std::io::Error),
     /// Error from the server
     #[error("server error: {0}")]
-    ServerError(#[from] http::ServerError),
+    ServerError(http::ServerError),
+    /// Error returned by the wallet, `code` follows EIP-1193 (e.g. 4001 when the user rejected
+    /// the request) or JSON-RPC (e.g. -32000 for an insufficient balance or a nonce too low)
+    #[error("wallet error {code}: {message}")]
+    RPC { message: String, code: i64, data: Option<serde_json::Value> },
+    /// The browser has no usable wallet (e.g. no extension installed), retrying won't help
+    #[error("{0}")]
+    NoWallet(String),
+    /// The request was cancelled with `cancel_pending` before the browser answered it
+    #[error("request cancelled")]
+    Cancelled,
+    /// The wallet is on another chain than the transaction's, even after being asked to switch,
+    /// nothing was signed
+    #[error("wallet is on chain {actual} instead of {expected}")]
+    ChainMismatch { expected: u64, actual: u64 },
+    /// The user declined to connect the wallet (or to switch to the requested chain) when the
+    /// signer was created
+    #[error("connection rejected by the user: {0}")]
+    ConnectionRejected(String),
     /// Couldn't find any addresses in the browser
     #[error("no addresses found in browser")]
     NoAddressFound,
+    /// The requested account isn't among the ones shared by the wallet
+    #[error("address not found in browser: {0}")]
+    AddressNotFound(String),
     /// Error while parsing the signature
     #[error("signature error: {0}")]
-    SignatureError(#[from] ethers::core::types::SignatureError),
+    SignatureError(#[from] SignatureError),
     /// Some methods are no supported
     #[error("unsupported: {0}")]
     Unsupported(String),
@@ -80,57 +138,588 @@ pub enum BrowserSignerError {
     /// Error while parsing the tx signature
     #[error("transaction signature error: {0}")]
     TransactionSignatureRLPError(#[from] TypedTransactionError),
+    /// The wallet returned something else than a transaction hash from `send_transaction`
+    #[error("invalid transaction hash: {0}")]
+    InvalidTransactionHash(String),
+    /// The message given to `sign_hex_message` isn't valid hex
+    #[error("invalid hex message: {0}")]
+    InvalidHexMessage(hex::FromHexError),
+    /// The signature given to `verify_signature` isn't valid hex
+    #[error("invalid hex signature: {0}")]
+    InvalidHexSignature(hex::FromHexError),
+    /// The wallet's node returned something else than hex from an `eth_call`
+    #[error("invalid call result: {0}")]
+    InvalidCallResult(String),
+    /// The typed data doesn't match its own type definitions
+    #[error("invalid typed data: {0}")]
+    InvalidTypedData(String),
+    /// The `Eip712` implementation of the payload failed, `part` is the step which failed
+    #[error("failed to encode `{type_name}` as EIP-712 ({part}): {error}")]
+    Eip712Encoding { type_name: &'static str, part: &'static str, error: String },
+}
+
+impl BrowserSignerError {
+    /// Whether the user declined the request in their wallet (EIP-1193 code 4001), e.g. to show
+    /// "you cancelled" instead of an error
+    pub fn is_user_rejected(&self) -> bool {
+        matches!(self, Self::RPC { code: USER_REJECTED_CODE, .. } | Self::ConnectionRejected(_))
+    }
+}
+
+impl From<http::ServerError> for BrowserSignerError {
+    fn from(err: http::ServerError) -> Self {
+        match err {
+            http::ServerError::Wallet { code: CHAIN_MISMATCH_CODE, data: Some(data), message } => {
+                match serde_json::from_value::<ChainMismatch>(data.clone()) {
+                    Ok(ChainMismatch { expected, actual }) => {
+                        Self::ChainMismatch { expected, actual }
+                    }
+                    Err(_) => Self::RPC { message, code: CHAIN_MISMATCH_CODE, data: Some(data) },
+                }
+            }
+            http::ServerError::Wallet { message, code, data } => Self::RPC { message, code, data },
+            http::ServerError::NoWallet(error) => Self::NoWallet(error),
+            http::ServerError::Cancelled => Self::Cancelled,
+            err => Self::ServerError(err),
+        }
+    }
+}
+
+/// Tells a user declining the initial connection apart from other failures
+fn connection_error(err: http::ServerError) -> BrowserSignerError {
+    match BrowserSignerError::from(err) {
+        BrowserSignerError::RPC { message, code: USER_REJECTED_CODE, .. } => {
+            BrowserSignerError::ConnectionRejected(message)
+        }
+        err => err,
+    }
+}
+
+/// Runs every step of the `Eip712` encoding of `payload` so failures name the type and the step
+fn encode_eip712<T: Eip712>(payload: &T) -> Result<[u8; 32], BrowserSignerError> {
+    let fail = |part: &'static str| {
+        move |e: T::Error| BrowserSignerError::Eip712Encoding {
+            type_name: std::any::type_name::<T>(),
+            part,
+            error: e.to_string(),
+        }
+    };
+    payload.domain().map_err(fail("domain"))?;
+    payload.domain_separator().map_err(fail("domain separator"))?;
+    T::type_hash().map_err(fail("type hash"))?;
+    payload.struct_hash().map_err(fail("struct hash"))?;
+    payload.encode_eip712().map_err(fail("digest"))
+}
+
+/// Some wallets return hex data without the `0x` prefix (or with surrounding whitespace)
+fn strip_hex_prefix(data: &str) -> &str {
+    let data = data.trim();
+    data.strip_prefix("0x").or_else(|| data.strip_prefix("0X")).unwrap_or(data)
+}
+
+/// Decodes hex data returned by the wallet for a signature or a signed transaction, so malformed
+/// output is reported as a `SignatureError` instead of failing further down (e.g. in RLP)
+fn decode_wallet_hex(data: &str) -> Result<Vec<u8>, SignatureError> {
+    let bytes = hex::decode(strip_hex_prefix(data))?;
+    if bytes.is_empty() {
+        return Err(SignatureError::InvalidLength(0))
+    }
+    Ok(bytes)
+}
+
+/// Parses a signature returned by the wallet, accepting both 0/1 and 27/28 recovery ids
+fn parse_signature(sig: &str) -> Result<EthSig, BrowserSignerError> {
+    let mut sig = EthSig::try_from(decode_wallet_hex(sig)?.as_slice())?;
+    if sig.v < 27 {
+        sig.v += 27;
+    }
+    Ok(sig)
+}
+
+/// Wallets show messages which are valid UTF-8 as text, the others are signed as a hash
+fn as_text_message(message: &[u8]) -> Option<String> {
+    String::from_utf8(message.to_vec()).ok()
+}
+
+/// Applies the signer's account and chain id (unless set) and gas limit buffer. Without `from`,
+/// wallets sign with whichever account they currently have selected, which can change under the
+/// signer's feet (e.g. after the page reconnects)
+fn prepare_transaction(
+    transaction: &TypedTransaction,
+    from: Address,
+    chain_id: u64,
+    gas_limit_buffers: &HashMap<u64, GasLimitBuffer>,
+) -> TypedTransaction {
+    let mut tx = transaction.clone();
+    if tx.from().is_none() {
+        tx.set_from(from);
+    }
+    let chain_id = tx.chain_id().unwrap_or(chain_id.into());
+    tx.set_chain_id(chain_id);
+    if let (Some(buffer), Some(gas)) =
+        (gas_limit_buffers.get(&chain_id.as_u64()), tx.gas().copied())
+    {
+        tx.set_gas(buffer.apply(gas));
+    }
+    tx
+}
+
+/// Extracts the signature from a signed transaction returned by the wallet (RLP, hex encoded)
+fn parse_signed_transaction(signed: &str) -> Result<EthSig, BrowserSignerError> {
+    let signed = decode_wallet_hex(signed)?;
+    let (_, sig) = TypedTransaction::decode_signed(&rlp::Rlp::new(signed.as_slice()))?;
+    Ok(sig)
 }
 
 fn prompt_user(url: String) -> Result<(), BrowserSignerError> {
     Ok(webbrowser::open(&url)?)
 }
 
+/// Reopens `url` every `delay` until a browser is `connected` or we are `done` waiting for one
+fn reopen_until_connected(
+    url: String,
+    delay: Duration,
+    max_reopens: usize,
+    connected: Arc<AtomicBool>,
+    done: Arc<AtomicBool>,
+) {
+    thread::spawn(move || {
+        for attempt in 1..=max_reopens {
+            thread::sleep(delay);
+            if connected.load(Ordering::SeqCst) || done.load(Ordering::SeqCst) {
+                return
+            }
+            warn!("no browser connected yet, opening {} again ({}/{})", url, attempt, max_reopens);
+            if let Err(e) = prompt_user(url.clone()) {
+                warn!("failed to open the browser: {}", e);
+            }
+        }
+    });
+}
+
+/// The steps of `BrowserSigner::new_with_progress`. `BrowserOpened` is skipped when the browser
+/// isn't opened automatically, and a quick browser can connect before it is sent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectProgress {
+    ServerStarted {
+        url: String,
+    },
+    BrowserOpened,
+    ClientConnected,
+    /// The browser switched to the right chain
+    Initialized,
+    AccountsReceived(Vec<Address>),
+}
+
+/// Turns the comm server's state changes into `ClientConnected` then `Initialized`, until the
+/// latter is sent or `progress` is dropped
+fn forward_progress(updates: mpsc::Receiver<QueueState>, progress: mpsc::Sender<ConnectProgress>) {
+    let mut connected = false;
+    for state in updates {
+        if state.browser != BrowserState::Disconnected && !connected {
+            connected = true;
+            if progress.send(ConnectProgress::ClientConnected).is_err() {
+                return
+            }
+        }
+        if state.browser == BrowserState::Ready {
+            let _ = progress.send(ConnectProgress::Initialized);
+            return
+        }
+    }
+}
+
+/// Sends `browser` whenever it changes, until `sender` or the comm server is dropped
+fn forward_browser_state(updates: mpsc::Receiver<QueueState>, sender: mpsc::Sender<BrowserState>) {
+    let mut last = None;
+    for state in updates {
+        if last == Some(state.browser) {
+            continue
+        }
+        last = Some(state.browser);
+        if sender.send(state.browser).is_err() {
+            return
+        }
+    }
+}
+
+/// A buffer added to the gas limit of a transaction before it is sent to the wallet.
+///
+/// Some chains (mostly L2s) have unreliable gas estimates, which can lead to transactions running
+/// out of gas. Recommended buffers:
+/// - Arbitrum One (42161) and Arbitrum Nova (42170): `Percent(25)`
+/// - Optimism (10) and Base (8453): `Percent(10)`
+/// - Polygon zkEVM (1101) and zkSync Era (324): `Percent(20)`
+///
+/// Note that the buffered gas limit is part of what the wallet signs, so the transaction you
+/// broadcast must use the same gas limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GasLimitBuffer {
+    /// Increase the gas limit by a percentage, e.g. `Percent(20)` for +20%
+    Percent(u64),
+    /// Increase the gas limit by a fixed amount of gas
+    Fixed(u64),
+}
+
+impl GasLimitBuffer {
+    fn apply(&self, gas: U256) -> U256 {
+        match self {
+            Self::Percent(percent) => {
+                gas.saturating_add(gas.saturating_mul((*percent).into()) / 100)
+            }
+            Self::Fixed(amount) => gas.saturating_add((*amount).into()),
+        }
+    }
+}
+
+/// See `BrowserOptions::builder` to only set some of them
+#[derive(Default)]
 pub struct BrowserOptions {
     /// A map of chain IDs to their info, which is used to prepopulate the browser if needed
     pub chains: Option<HashMap<u64, ChainInfo>>,
-    /// Whether to open the browser automatically, defaults to true
+    /// A map of chain IDs to the gas limit buffer applied to their transactions (only when a gas
+    /// limit is set), see `GasLimitBuffer`
+    pub gas_limit_buffers: Option<HashMap<u64, GasLimitBuffer>>,
+    /// Bumps applied in the browser to the gas limit and price estimated by the wallet (only when
+    /// they are not set), see `EstimateBump`
+    pub estimate_bump: Option<EstimateBump>,
+    /// ERC-20 approvals at or above this allowance are flagged with a warning in the browser,
+    /// defaults to `U256::MAX / 2` (which catches "unlimited" approvals)
+    pub approval_warning_threshold: Option<U256>,
+    /// Reject accounts returned by the wallet with an invalid EIP-55 checksum instead of only
+    /// logging them in the browser console, defaults to false
+    pub strict_address_checksums: Option<bool>,
+    /// Reject typed data whose domain `verifyingContract` has an invalid EIP-55 checksum instead
+    /// of only logging a warning, defaults to false. All lowercase or uppercase addresses
+    /// carry no checksum and are accepted. Only raw JSON (`sign_typed_data_json`,
+    /// `request_raw` and the JSON-RPC endpoint) can be checked as parsed typed data doesn't
+    /// keep the original casing
+    pub strict_verifying_contract: Option<bool>,
+    /// Show each signing request in the page and only pass it to the wallet once the user
+    /// approves it there, defaults to true. Rejecting it fails with a user rejection (see
+    /// `BrowserSignerError::is_user_rejected`)
+    pub review_requests: Option<bool>,
+    /// Whether to open the browser automatically, defaults to true unless running in CI (see
+    /// `is_ci`), set it explicitly to override the detection
     pub open_browser: Option<bool>,
+    /// How long to wait for the server to accept connections before opening the browser anyway,
+    /// defaults to 2 seconds
+    pub readiness_timeout: Option<Duration>,
+    /// If no browser connected within this delay, print the URL and open it again (in case the
+    /// first launch was blocked or missed), defaults to never
+    pub reopen_browser_after: Option<Duration>,
+    /// How many times the browser is reopened, see `reopen_browser_after`, defaults to 2
+    pub max_browser_reopens: Option<usize>,
+    /// Whether to print the URL and a QR code pointing to it to stderr, defaults to false
+    pub print_qr_code: Option<bool>,
+    /// How long to wait for a browser to connect and share its accounts, defaults to forever
+    pub connect_timeout: Option<Duration>,
+    /// How long to wait for the user to answer a signing request, defaults to 5 minutes
+    pub request_timeout: Option<Duration>,
+    /// Receives the browser's `BrowserState` then every change (connected, initialized,
+    /// disconnected) for as long as the signer lives, e.g. to show "waiting for the browser"
+    /// while the signer is created and warn when the page is closed later
+    pub browser_state: Option<mpsc::Sender<BrowserState>>,
     /// The server options, defaults to randomized
     pub server: Option<ServerOptions>,
 }
 
+impl BrowserOptions {
+    pub fn builder() -> BrowserOptionsBuilder {
+        BrowserOptionsBuilder::default()
+    }
+
+    fn frontend_config(&self) -> FrontendConfig {
+        FrontendConfig {
+            approval_warning_threshold: self.approval_warning_threshold,
+            strict_address_checksums: self.strict_address_checksums,
+            estimate_bump: self.estimate_bump,
+            review_requests: self.review_requests,
+            ..Default::default()
+        }
+    }
+
+    /// The recommended setup for remote signing (e.g. over SSH): the browser is not opened,
+    /// instead the URL and a QR code are printed to stderr, and the signer gives up if no browser
+    /// connects within 5 minutes.
+    ///
+    /// Set a fixed `ServerOptions::port` to be able to forward it, e.g. with `ssh -L
+    /// 7777:localhost:7777`, or open the URL from another device on the same network.
+    pub fn headless() -> Self {
+        Self {
+            open_browser: Some(false),
+            print_qr_code: Some(true),
+            connect_timeout: Some(HEADLESS_CONNECT_TIMEOUT),
+            ..Default::default()
+        }
+    }
+}
+
+/// Builds `BrowserOptions` one field at a time, leaving the others to their defaults
+#[derive(Default)]
+pub struct BrowserOptionsBuilder {
+    opts: BrowserOptions,
+}
+
+impl BrowserOptionsBuilder {
+    /// Adds a chain to `BrowserOptions::chains`
+    pub fn chain(mut self, chain_id: u64, info: ChainInfo) -> Self {
+        self.opts.chains.get_or_insert_with(HashMap::new).insert(chain_id, info);
+        self
+    }
+
+    pub fn open_browser(mut self, open_browser: bool) -> Self {
+        self.opts.open_browser = Some(open_browser);
+        self
+    }
+
+    /// Sets `ServerOptions::port`
+    pub fn port(mut self, port: u16) -> Self {
+        self.server().port = Some(port);
+        self
+    }
+
+    /// Sets `ServerOptions::nonce`
+    pub fn nonce(mut self, nonce: String) -> Self {
+        self.server().nonce = Some(nonce);
+        self
+    }
+
+    pub fn build(self) -> BrowserOptions {
+        self.opts
+    }
+
+    fn server(&mut self) -> &mut ServerOptions {
+        self.opts.server.get_or_insert_with(ServerOptions::default)
+    }
+}
+
+/// Environment variables set by common CI providers, `CI` being set by most of them (GitHub
+/// Actions, GitLab CI, CircleCI, Travis CI, Buildkite, ...)
+const CI_VARIABLES: [&str; 4] = ["CI", "JENKINS_URL", "TF_BUILD", "TEAMCITY_VERSION"];
+
+/// Whether we are running in CI, where opening a browser would fail (or worse, hang): any of
+/// `CI_VARIABLES` is set to something other than `false` or `0`
+fn is_ci(var: impl Fn(&str) -> Option<String>) -> bool {
+    CI_VARIABLES.iter().any(|name| {
+        var(name).map_or(false, |value| !matches!(value.trim(), "" | "0" | "false" | "FALSE"))
+    })
+}
+
+fn print_qr_code(url: &str) {
+    eprintln!("Open {} in a browser with your wallet, or scan this QR code:", url);
+    match qrcode::QrCode::new(url) {
+        Ok(code) => eprintln!(
+            "{}",
+            code.render::<Dense1x2>()
+                .dark_color(Dense1x2::Light)
+                .light_color(Dense1x2::Dark)
+                .build()
+        ),
+        Err(e) => warn!("failed to render the QR code: {}", e),
+    }
+}
+
+/// A signer waiting for its routes to be served, see `BrowserSigner::new_embedded`
+pub struct PendingSigner {
+    chain_id: u64,
+    server: http::Server,
+    opts: BrowserOptions,
+}
+
+impl PendingSigner {
+    /// Waits for a browser to connect like `BrowserSigner::new_with_options`, `url` being where
+    /// the routes are served (e.g. `http://localhost:8080/signer/`)
+    pub async fn connect(self, url: &str) -> Result<BrowserSigner, BrowserSignerError> {
+        let url = format!("{}?nonce={}", url, self.server.nonce());
+        // nobody listens, the progress is dropped right away
+        let (progress, _) = mpsc::channel();
+        BrowserSigner::connect(self.chain_id, self.server, url, self.opts, progress).await
+    }
+}
+
 impl BrowserSigner {
     /// Instantiate a new signer from a chain id.
     ///
     /// This function retrieves the public addresses from the browser. It is therefore `async`.
     #[instrument(err)]
     pub async fn new(chain_id: u64) -> Result<BrowserSigner, BrowserSignerError> {
-        Self::new_with_options(
-            chain_id,
-            BrowserOptions { chains: None, open_browser: Some(true), server: None },
-        )
-        .await
+        Self::new_with_options(chain_id, BrowserOptions::default()).await
     }
 
     pub async fn new_with_options(
         chain_id: u64,
         opts: BrowserOptions,
     ) -> Result<BrowserSigner, BrowserSignerError> {
-        let server = http::Server::new(chain_id, opts.chains, opts.server).await?;
+        // nobody listens, the progress is dropped right away
+        let (progress, _) = mpsc::channel();
+        Self::new_with_progress(chain_id, opts, progress).await
+    }
+
+    /// Same as `new_with_options`, sending each step of the connection to `progress` (e.g. to
+    /// show it in a GUI instead of a spinner)
+    pub async fn new_with_progress(
+        chain_id: u64,
+        mut opts: BrowserOptions,
+        progress: mpsc::Sender<ConnectProgress>,
+    ) -> Result<BrowserSigner, BrowserSignerError> {
+        let config = opts.frontend_config();
+        let server =
+            http::Server::new(chain_id, opts.chains.take(), config, opts.server.take()).await?;
+        let url = format!(
+            "{}://{}:{}{}?nonce={}",
+            server.scheme(),
+            server.url_host(),
+            server.port(),
+            server.base_path(),
+            server.nonce()
+        );
+        Self::connect(chain_id, server, url, opts, progress).await
+    }
+
+    /// Creates a signer served by an existing actix-web server instead of its own: mount the
+    /// returned routes into your `App` (see `SignerRoutes::configure`) then call
+    /// `PendingSigner::connect` with the URL they are served at
+    pub fn new_embedded(
+        chain_id: u64,
+        mut opts: BrowserOptions,
+    ) -> Result<(SignerRoutes, PendingSigner), BrowserSignerError> {
+        let config = opts.frontend_config();
+        let (server, routes) =
+            http::Server::new_embedded(chain_id, opts.chains.take(), config, opts.server.take())?;
+        Ok((routes, PendingSigner { chain_id, server, opts }))
+    }
+
+    async fn connect(
+        chain_id: u64,
+        server: http::Server,
+        url: String,
+        mut opts: BrowserOptions,
+        progress: mpsc::Sender<ConnectProgress>,
+    ) -> Result<BrowserSigner, BrowserSignerError> {
+        let open_browser = opts.open_browser.unwrap_or_else(|| {
+            let ci = is_ci(|name| std::env::var(name).ok());
+            if ci {
+                info!("running in CI, the browser won't be opened");
+            }
+            !ci
+        });
+        server.set_request_timeout(opts.request_timeout);
+        server.set_strict_verifying_contract(opts.strict_verifying_contract.unwrap_or(false));
 
-        let url = format!("http://localhost:{}?nonce={}", server.port(), server.nonce());
         info!("Please open your browser at {} and connect your wallet", url);
-        if opts.open_browser.unwrap_or(true) {
+        let _ = progress.send(ConnectProgress::ServerStarted { url: url.clone() });
+        {
+            let updates = server.subscribe_queue();
+            let progress = progress.clone();
+            thread::spawn(move || forward_progress(updates, progress));
+        }
+        if let Some(sender) = opts.browser_state.take() {
+            let updates = server.subscribe_queue();
+            thread::spawn(move || forward_browser_state(updates, sender));
+        }
+        if open_browser {
+            let timeout = opts.readiness_timeout.unwrap_or(DEFAULT_READINESS_TIMEOUT);
+            if !server.wait_until_ready(timeout).await {
+                warn!("server is not accepting connections after {:?}, opening anyway", timeout);
+            }
             prompt_user(url.clone())?;
+            let _ = progress.send(ConnectProgress::BrowserOpened);
+        }
+        if opts.print_qr_code.unwrap_or(false) {
+            print_qr_code(&url);
         }
 
-        let addresses = server.get_user_addresses().await?;
+        let done = Arc::new(AtomicBool::new(false));
+        if let (true, Some(delay)) = (open_browser, opts.reopen_browser_after) {
+            reopen_until_connected(
+                url.clone(),
+                delay,
+                opts.max_browser_reopens.unwrap_or(DEFAULT_MAX_BROWSER_REOPENS),
+                server.browser_connected(),
+                done.clone(),
+            );
+        }
+
+        let addresses = server.get_user_addresses(opts.connect_timeout).await;
+        done.store(true, Ordering::SeqCst);
+        let addresses = addresses.map_err(connection_error)?;
         if addresses.is_empty() {
             return Err(BrowserSignerError::NoAddressFound)
         }
+        let _ = progress.send(ConnectProgress::AccountsReceived(addresses.clone()));
 
-        Ok(Self { chain_id, server, addresses, url })
+        Ok(Self {
+            chain_id,
+            server: Arc::new(server),
+            addresses,
+            address_index: 0,
+            url,
+            gas_limit_buffers: opts.gas_limit_buffers.unwrap_or_default(),
+            strict_verifying_contract: opts.strict_verifying_contract.unwrap_or(false),
+        })
     }
 
     pub fn url(&self) -> String {
         self.url.clone()
     }
+
+    /// Returns the port the server is listening on, 0 for signers created with `new_embedded`
+    pub fn port(&self) -> u16 {
+        self.server.port()
+    }
+
+    /// Stops the server and waits until its port is released, unlike dropping the signer which
+    /// does it in the background. Signers returned by `signer_for` stop working too
+    pub async fn shutdown(self) -> Result<(), BrowserSignerError> {
+        Ok(self.server.shutdown().await?)
+    }
+
+    /// Cancels every request waiting for the browser (e.g. one the user is ignoring), they fail
+    /// with `BrowserSignerError::Cancelled`. Signers returned by `signer_for` share the same
+    /// requests, the ones made through the JSON-RPC endpoint are left alone
+    pub fn cancel_pending(&self) {
+        self.server.cancel_pending()
+    }
+
+    /// Receives the state of the requests waiting for the browser (e.g. to show progress or
+    /// notify the user when a signature is pending), first the current one then every change
+    pub fn queue_updates(&self) -> mpsc::Receiver<QueueState> {
+        self.server.subscribe_queue()
+    }
+
+    /// Returns every account shared by the wallet, `address()` being one of them (the first by
+    /// default)
+    pub fn addresses(&self) -> &[Address] {
+        &self.addresses
+    }
+
+    /// Signs with the account at `index` in `addresses()` instead
+    pub fn with_address_index(mut self, index: usize) -> Result<Self, BrowserSignerError> {
+        if index >= self.addresses.len() {
+            return Err(BrowserSignerError::AddressNotFound(format!(
+                "index {} out of range, {} accounts available",
+                index,
+                self.addresses.len()
+            )))
+        }
+        self.address_index = index;
+        Ok(self)
+    }
+
+    /// Returns a signer for `address` (which must be in `addresses()`) sharing this signer's
+    /// browser connection, e.g. to sign from several accounts without opening another tab
+    pub fn signer_for(&self, address: Address) -> Result<AccountSigner, BrowserSignerError> {
+        self.clone().select_address(address)
+    }
+
+    /// Signs with `address` instead, which must be in `addresses()`
+    pub fn select_address(self, address: Address) -> Result<Self, BrowserSignerError> {
+        match self.addresses.iter().position(|a| *a == address) {
+            Some(index) => self.with_address_index(index),
+            None => Err(BrowserSignerError::AddressNotFound(format!("{:#x}", address))),
+        }
+    }
 }
 
 pub trait TypedDataBrowserCompatible {
@@ -143,16 +732,209 @@ impl TypedDataBrowserCompatible for TypedData {
     }
 }
 
+/// A signature as the wallet returned it, along with its parsed form
+#[derive(Clone, Debug, PartialEq)]
+pub struct RawSignature {
+    /// The hex encoded signature, exactly as sent by the wallet
+    pub hex: String,
+    pub signature: EthSig,
+}
+
 impl BrowserSigner {
+    /// Signs a message and returns the signature exactly as the wallet sent it as well as parsed
+    /// (see `Signer::sign_message` for only the parsed version). Fails with a `SignatureError`
+    /// if the wallet returned something else than a 65 bytes signature, use `request_raw` for
+    /// the others (e.g. some smart-contract wallets)
+    #[instrument(err, skip(message))]
+    pub async fn sign_message_raw<S: Send + Sync + AsRef<[u8]>>(
+        &self,
+        message: S,
+    ) -> Result<RawSignature, BrowserSignerError> {
+        let message = message.as_ref();
+        let message_hash = hash_message(message);
+        trace!("{:?}", message_hash);
+        trace!("{:?}", message);
+        let sig = match as_text_message(message) {
+            Some(s) => self.server.sign_text_message(self.address(), s).await,
+            None => {
+                self.server
+                    .sign_binary_message(
+                        self.address(),
+                        message_hash,
+                        Some(message.to_vec().into()),
+                    )
+                    .await
+            }
+        }?;
+        let signature = parse_signature(&sig)?;
+        Ok(RawSignature { hex: sig, signature })
+    }
+
+    /// Signs a hex encoded message (with or without `0x`), which is decoded first so that hex
+    /// representing UTF-8 text is signed (and shown by the wallet) as text, like MetaMask does
+    pub async fn sign_hex_message(&self, message: &str) -> Result<EthSig, BrowserSignerError> {
+        let message = hex::decode(strip_hex_prefix(message))
+            .map_err(BrowserSignerError::InvalidHexMessage)?;
+        self.sign_message(message).await
+    }
+
+    /// Signs and broadcasts the transaction through the wallet instead of only signing it, for
+    /// wallets which refuse `eth_signTransaction` (e.g. MetaMask). Returns the transaction hash
+    pub async fn send_transaction(
+        &self,
+        transaction: &TypedTransaction,
+    ) -> Result<TxHash, BrowserSignerError> {
+        transaction_hash_result(
+            self.server.send_transaction(self.prepare_transaction(transaction)).await,
+        )
+    }
+
+    /// Sends any JSON-RPC method to the wallet (e.g. `eth_getTransactionReceipt`), for the ones
+    /// without a dedicated method. `params` is usually an array, `null` omits it. The request
+    /// goes to whichever chain the wallet is on and might prompt the user, depending on the method.
+    /// Unless it's read-only (e.g. `eth_call`, `eth_get*`), the page reviews it first (see
+    /// `BrowserOptions::review_requests`). Typed data (`eth_signTypedData_v3` and `_v4`) is
+    /// validated like with `sign_typed_data_json`
+    #[instrument(err, skip(params))]
+    pub async fn request_raw(
+        &self,
+        method: String,
+        params: serde_json::Value,
+    ) -> Result<serde_json::Value, BrowserSignerError> {
+        if method == "eth_signTypedData_v3" || method == "eth_signTypedData_v4" {
+            let data = params.get(1).ok_or_else(|| {
+                BrowserSignerError::InvalidTypedData("missing typed data parameter".to_owned())
+            })?;
+            typed_data::from_param(data, self.strict_verifying_contract)
+                .map_err(BrowserSignerError::InvalidTypedData)?;
+        }
+        Ok(self.server.request_raw(method, params).await?)
+    }
+
+    fn prepare_transaction(&self, transaction: &TypedTransaction) -> TypedTransaction {
+        prepare_transaction(transaction, self.address(), self.chain_id, &self.gas_limit_buffers)
+    }
+
     pub async fn sign_typed_data_raw(
         &self,
         data: &TypedData,
     ) -> Result<EthSig, BrowserSignerError> {
-        let sig = self.server.sign_typed_data(self.address(), data.clone()).await?;
-        Ok(EthSig::from_str(&sig)?)
+        self.sign_typed_data_raw_with_version(data, TypedDataVersion::V4).await
+    }
+
+    /// Same as `sign_typed_data_raw` with the typed data as raw JSON (e.g. read from a file), which
+    /// is validated against its own type definitions before being sent to the browser, see
+    /// `BrowserOptions::strict_verifying_contract` for the domain checks
+    pub async fn sign_typed_data_json(&self, json: &str) -> Result<EthSig, BrowserSignerError> {
+        let data = typed_data::parse(json, self.strict_verifying_contract)
+            .map_err(BrowserSignerError::InvalidTypedData)?;
+        self.sign_typed_data_raw(&data).await
+    }
+
+    /// Same as `sign_typed_data_raw` with a specific `eth_signTypedData` version
+    pub async fn sign_typed_data_raw_with_version(
+        &self,
+        data: &TypedData,
+        version: TypedDataVersion,
+    ) -> Result<EthSig, BrowserSignerError> {
+        typed_data::validate(data).map_err(BrowserSignerError::InvalidTypedData)?;
+        let sig = self.server.sign_typed_data(self.address(), data.clone(), version).await?;
+        parse_signature(&sig)
+    }
+
+    /// Checks that `signature` (hex encoded) was made by `address` over `message` (as signed by
+    /// `Signer::sign_message`), which can be a smart-contract wallet (e.g. a Safe): when the
+    /// signature doesn't recover to `address`, EIP-1271's `isValidSignature` is called on it
+    /// through the wallet's node. The wallet must already be on the signer's chain (the call
+    /// doesn't switch it), otherwise it fails with `BrowserSignerError::ChainMismatch`
+    pub async fn verify_signature<S: Send + Sync + AsRef<[u8]>>(
+        &self,
+        message: S,
+        signature: &str,
+        address: Address,
+    ) -> Result<bool, BrowserSignerError> {
+        let hash = hash_message(message);
+        // contract wallets' signatures aren't necessarily 65 bytes long
+        if let Ok(sig) = parse_signature(signature) {
+            if sig.recover(hash).map_or(false, |signer| signer == address) {
+                return Ok(true)
+            }
+        }
+
+        let signature = hex::decode(strip_hex_prefix(signature))
+            .map_err(BrowserSignerError::InvalidHexSignature)?;
+        let call: TypedTransaction = TransactionRequest::new()
+            .to(address)
+            .data(is_valid_signature_call(hash, signature))
+            .into();
+        eip1271_result(self.server.call(self.prepare_transaction(&call)).await)
+    }
+
+    /// Checks that `signature` (hex encoded) was made by `expected` over the EIP-712 digest of
+    /// `data`, e.g. to double check a signature before using it
+    pub fn verify_typed_data(
+        &self,
+        data: &TypedData,
+        signature: &str,
+        expected: Address,
+    ) -> Result<bool, BrowserSignerError> {
+        verify_typed_data_signature(data, signature, expected)
     }
 }
 
+/// The selector of EIP-1271's `isValidSignature(bytes32,bytes)`, which is also the value it
+/// returns for valid signatures
+const EIP1271_MAGIC_VALUE: [u8; 4] = [0x16, 0x26, 0xba, 0x7e];
+
+/// EIP-1474's code for a reverted `eth_call`
+const EXECUTION_REVERTED_CODE: i64 = 3;
+
+/// The calldata of EIP-1271's `isValidSignature(hash, signature)`
+fn is_valid_signature_call(hash: H256, signature: Vec<u8>) -> Bytes {
+    let params =
+        abi::encode(&[Token::FixedBytes(hash.as_bytes().to_vec()), Token::Bytes(signature)]);
+    [EIP1271_MAGIC_VALUE.as_slice(), &params].concat().into()
+}
+
+/// Whether the return data of `isValidSignature` is the magic value (left aligned in a word),
+/// accounts without code return nothing
+fn is_magic_value(result: &[u8]) -> bool {
+    result.len() == 32 && result[..4] == EIP1271_MAGIC_VALUE && result[4..].iter().all(|b| *b == 0)
+}
+
+/// Interprets the outcome of an `eth_sendTransaction` request
+fn transaction_hash_result(
+    result: Result<String, http::ServerError>,
+) -> Result<TxHash, BrowserSignerError> {
+    let hash = result?;
+    TxHash::from_str(&hash)
+        .map_err(|e| BrowserSignerError::InvalidTransactionHash(format!("{}: {}", hash, e)))
+}
+
+/// Interprets the outcome of an `isValidSignature` call
+fn eip1271_result(result: Result<String, http::ServerError>) -> Result<bool, BrowserSignerError> {
+    let result = match result {
+        Ok(result) => result,
+        // contracts revert for invalid signatures (or lack of support)
+        Err(http::ServerError::Wallet { code: EXECUTION_REVERTED_CODE, .. }) => return Ok(false),
+        Err(e) => return Err(e.into()),
+    };
+    let result = Bytes::from_str(&result)
+        .map_err(|e| BrowserSignerError::InvalidCallResult(format!("{}: {}", result, e)))?;
+    Ok(is_magic_value(&result))
+}
+
+fn verify_typed_data_signature(
+    data: &TypedData,
+    signature: &str,
+    expected: Address,
+) -> Result<bool, BrowserSignerError> {
+    let digest =
+        data.encode_eip712().map_err(|e| BrowserSignerError::InvalidTypedData(e.to_string()))?;
+    let signer = parse_signature(signature)?.recover(H256::from(digest))?;
+    Ok(signer == expected)
+}
+
 #[async_trait::async_trait]
 impl Signer for BrowserSigner {
     type Error = BrowserSignerError;
@@ -162,39 +944,32 @@ impl Signer for BrowserSigner {
         &self,
         message: S,
     ) -> Result<EthSig, Self::Error> {
-        let message = message.as_ref();
-        let message_hash = hash_message(message);
-        trace!("{:?}", message_hash);
-        trace!("{:?}", message);
-        let sig = match String::from_utf8(message.to_vec()) {
-            Ok(s) => self.server.sign_text_message(self.address(), s).await,
-            Err(_) => self.server.sign_binary_message(self.address(), message_hash).await,
-        }?;
-        Ok(EthSig::from_str(&sig)?)
+        Ok(self.sign_message_raw(message).await?.signature)
     }
 
     #[instrument(err)]
     async fn sign_transaction(&self, tx: &TypedTransaction) -> Result<EthSig, Self::Error> {
-        let mut tx = tx.clone();
-        tx.set_chain_id(tx.chain_id().unwrap_or(self.chain_id.into()));
-        let sig = self.server.sign_transaction(tx).await?;
-        let sig = hex::decode(sig)?;
-        let signed_rlp = rlp::Rlp::new(sig.as_slice());
-        let (_, decoded_sig) = TypedTransaction::decode_signed(&signed_rlp)?;
-        Ok(decoded_sig)
+        let signed = self.server.sign_transaction(self.prepare_transaction(tx)).await?;
+        parse_signed_transaction(&signed)
     }
 
     async fn sign_typed_data<T: Eip712 + Send + Sync>(
         &self,
-        _payload: &T,
+        payload: &T,
     ) -> Result<EthSig, Self::Error> {
-        Err(BrowserSignerError::Unsupported(
-            "sign_typed_data is not supported, use sign_typed_data_raw instead".to_owned(),
-        ))
+        // wallets need the full typed data: the domain is available but `Eip712` only exposes
+        // hashes of the types and fields, which can't be turned back into `TypedData`. A broken
+        // implementation is still worth reporting over the lack of support
+        encode_eip712(payload)?;
+        Err(BrowserSignerError::Unsupported(format!(
+            "`{}` can't be converted to the typed data wallets expect (`Eip712` only exposes \
+             hashes), use sign_typed_data_raw with its `TypedData` instead",
+            std::any::type_name::<T>()
+        )))
     }
 
     fn address(&self) -> Address {
-        self.addresses[0]
+        self.addresses[self.address_index]
     }
 
     /// Returns the signer's chain id
@@ -213,7 +988,10 @@ impl Signer for BrowserSigner {
 mod tests {
     use std::vec;
 
-    use ethers::types::{transaction::eip2930::AccessList, Eip1559TransactionRequest};
+    use ethers::types::{
+        transaction::{eip2930::AccessList, eip712::EIP712Domain},
+        Eip1559TransactionRequest, TransactionRequest,
+    };
     use ethers_signers_browser_frontend::ws::messages::NativeCurrency;
     use serial_test::serial;
 
@@ -227,7 +1005,7 @@ mod tests {
         test_signer_with_options(7777, None).await // goerli
     }
 
-    async fn test_signer_with_provided_chain() -> BrowserSigner {
+    fn provided_chains() -> HashMap<u64, ChainInfo> {
         let mut chains = HashMap::new();
         chains.insert(
             114,
@@ -245,9 +1023,14 @@ mod tests {
                 block_explorer_urls: Some(
                     vec!["https://coston2-explorer.flare.network".to_owned()],
                 ),
+                testnet: Some(true),
             },
         );
-        test_signer_with_options(114, Some(chains)).await // goerli
+        chains
+    }
+
+    async fn test_signer_with_provided_chain() -> BrowserSigner {
+        test_signer_with_options(114, Some(provided_chains())).await // coston2
     }
 
     async fn test_signer_with_options(
@@ -258,14 +1041,423 @@ mod tests {
             chain,
             BrowserOptions {
                 chains,
+                gas_limit_buffers: None,
+                estimate_bump: None,
+                approval_warning_threshold: None,
+                strict_address_checksums: None,
+                strict_verifying_contract: None,
+                review_requests: None,
                 open_browser: Some(false),
-                server: Some(ServerOptions { port: Some(7777), nonce: Some("123".to_owned()) }),
+                readiness_timeout: None,
+                reopen_browser_after: None,
+                max_browser_reopens: None,
+                print_qr_code: None,
+                connect_timeout: None,
+                request_timeout: None,
+                browser_state: None,
+                server: Some(ServerOptions {
+                    port: Some(7777),
+                    nonce: Some("123".to_owned()),
+                    ..Default::default()
+                }),
             },
         )
         .await
         .unwrap()
     }
 
+    #[test]
+    fn it_builds_browser_options() {
+        let opts = BrowserOptions::builder()
+            .chain(114, provided_chains().remove(&114).unwrap())
+            .open_browser(false)
+            .port(7777)
+            .nonce("123".to_owned())
+            .build();
+        assert_eq!(
+            opts.chains.map(|chains| chains.into_keys().collect::<Vec<_>>()),
+            Some(vec![114])
+        );
+        assert_eq!(opts.open_browser, Some(false));
+        let server = opts.server.unwrap();
+        assert_eq!(server.port, Some(7777));
+        assert_eq!(server.nonce.as_deref(), Some("123"));
+        assert_eq!(opts.connect_timeout, None);
+    }
+
+    #[test]
+    fn it_applies_gas_limit_buffers() {
+        assert_eq!(GasLimitBuffer::Percent(20).apply(U256::from(100_000)), U256::from(120_000));
+        assert_eq!(GasLimitBuffer::Fixed(5_000).apply(U256::from(100_000)), U256::from(105_000));
+        assert_eq!(GasLimitBuffer::Percent(10).apply(U256::MAX), U256::MAX);
+    }
+
+    #[test]
+    fn it_signs_transactions_from_the_selected_account() {
+        let selected = Address::from_low_u64_be(2);
+        let buffers = HashMap::from([(5, GasLimitBuffer::Fixed(1_000))]);
+        let tx: TypedTransaction = TransactionRequest::new().gas(21_000).into();
+        let prepared = prepare_transaction(&tx, selected, 5, &buffers);
+        assert_eq!(prepared.from(), Some(&selected));
+        assert_eq!(prepared.chain_id(), Some(5.into()));
+        assert_eq!(prepared.gas(), Some(&22_000.into()));
+
+        let other = Address::from_low_u64_be(3);
+        let tx: TypedTransaction = TransactionRequest::new().from(other).chain_id(1).into();
+        let prepared = prepare_transaction(&tx, selected, 5, &buffers);
+        assert_eq!(prepared.from(), Some(&other));
+        assert_eq!(prepared.chain_id(), Some(1.into()));
+    }
+
+    #[test]
+    fn it_normalizes_signature_v_values() {
+        let rs = "b91467e570a6466aa9e9876cbcd013baba02900b8979d43fe208a4a4f339f5fd\
+                  6007e74cd82e037b800186422fc2da167c747ef045e5d18a5f5d4300f8e1a029";
+        let legacy = parse_signature(&format!("0x{}1c", rs)).unwrap();
+        let compact = parse_signature(&format!("0x{}01", rs)).unwrap();
+        assert_eq!(legacy.v, 28);
+        assert_eq!(compact, legacy);
+        assert_eq!(parse_signature(&format!("0x{}00", rs)).unwrap().v, 27);
+        assert_eq!(parse_signature(&format!("0x{}1b", rs)).unwrap().v, 27);
+    }
+
+    #[test]
+    fn it_decodes_hex_messages() {
+        let decode = |message| hex::decode(strip_hex_prefix(message)).unwrap();
+        assert_eq!(as_text_message(&decode("0x68656c6c6f")), Some("hello".to_owned()));
+        assert_eq!(as_text_message(&decode("68656c6c6f")), Some("hello".to_owned()));
+        assert_eq!(as_text_message(&decode("0xff00")), None);
+    }
+
+    #[tokio::test]
+    async fn it_accepts_hex_without_prefix() {
+        let rs = "b91467e570a6466aa9e9876cbcd013baba02900b8979d43fe208a4a4f339f5fd\
+                  6007e74cd82e037b800186422fc2da167c747ef045e5d18a5f5d4300f8e1a029";
+        let prefixed = parse_signature(&format!("0x{}1c", rs)).unwrap();
+        assert_eq!(parse_signature(&format!("{}1c", rs)).unwrap(), prefixed);
+        assert_eq!(parse_signature(&format!(" 0X{}1c\n", rs)).unwrap(), prefixed);
+
+        let wallet = "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318"
+            .parse::<ethers::signers::LocalWallet>()
+            .unwrap();
+        let tx: TypedTransaction = ethers::types::TransactionRequest::new()
+            .to(Address::zero())
+            .gas(21_000)
+            .gas_price(1)
+            .nonce(0)
+            .chain_id(1)
+            .into();
+        let sig = wallet.sign_transaction(&tx).await.unwrap();
+        let signed = hex::encode(tx.rlp_signed(&sig));
+        assert_eq!(parse_signed_transaction(&signed).unwrap(), sig);
+        assert_eq!(parse_signed_transaction(&format!("0x{}", signed)).unwrap(), sig);
+        assert!(parse_signed_transaction("0x12").is_err());
+    }
+
+    #[test]
+    fn it_reports_malformed_wallet_signatures() {
+        for (sig, expected) in [("0x", 0), ("0x1234", 2)] {
+            assert!(matches!(
+                parse_signature(sig),
+                Err(BrowserSignerError::SignatureError(SignatureError::InvalidLength(len))) if len == expected
+            ));
+        }
+        assert!(matches!(
+            parse_signature("0xnope"),
+            Err(BrowserSignerError::SignatureError(SignatureError::DecodingError(_)))
+        ));
+        assert!(matches!(
+            parse_signed_transaction(" 0x "),
+            Err(BrowserSignerError::SignatureError(SignatureError::InvalidLength(0)))
+        ));
+        assert!(matches!(
+            parse_signed_transaction("0xzz"),
+            Err(BrowserSignerError::SignatureError(SignatureError::DecodingError(_)))
+        ));
+    }
+
+    #[test]
+    fn it_keeps_wallet_error_codes() {
+        let res: ethers_signers_browser_frontend::ws::messages::ResponseContent =
+            serde_json::from_value(serde_json::json!({
+                "type": "Error",
+                "message": { "error": "nonce too low", "code": -32000, "data": "0x" },
+            }))
+            .unwrap();
+        let (message, code, data) = match res {
+            ethers_signers_browser_frontend::ws::messages::ResponseContent::Error {
+                error,
+                code: Some(code),
+                data,
+            } => (error, code, data),
+            _ => panic!("unexpected response: {:?}", res),
+        };
+        let err = BrowserSignerError::from(http::ServerError::Wallet { message, code, data });
+        assert!(matches!(err, BrowserSignerError::RPC { code: -32000, .. }));
+        let err = BrowserSignerError::from(http::ServerError::Client("oops".to_owned()));
+        assert!(matches!(err, BrowserSignerError::ServerError(_)));
+        let err = BrowserSignerError::from(http::ServerError::NoWallet("missing".to_owned()));
+        assert!(matches!(err, BrowserSignerError::NoWallet(_)));
+
+        let mismatch = |data| http::ServerError::Wallet {
+            message: "wallet is on chain 5 instead of 1".to_owned(),
+            code: CHAIN_MISMATCH_CODE,
+            data: Some(data),
+        };
+        let err =
+            BrowserSignerError::from(mismatch(serde_json::json!({ "expected": 1, "actual": 5 })));
+        assert!(matches!(err, BrowserSignerError::ChainMismatch { expected: 1, actual: 5 }));
+        let err = BrowserSignerError::from(mismatch(serde_json::json!("0x")));
+        assert!(matches!(err, BrowserSignerError::RPC { code: CHAIN_MISMATCH_CODE, .. }));
+    }
+
+    #[test]
+    fn it_forwards_browser_state_changes() {
+        let state = |browser| QueueState { browser, queued: 1, in_flight: None, reads: 0 };
+        let (sender, updates) = mpsc::channel();
+        for browser in [
+            BrowserState::Disconnected,
+            BrowserState::Initializing,
+            BrowserState::Ready,
+            BrowserState::Ready,
+            BrowserState::Disconnected,
+        ] {
+            sender.send(state(browser)).unwrap();
+        }
+        drop(sender);
+        let (states, received) = mpsc::channel();
+        forward_browser_state(updates, states);
+        assert_eq!(
+            received.try_iter().collect::<Vec<_>>(),
+            [
+                BrowserState::Disconnected,
+                BrowserState::Initializing,
+                BrowserState::Ready,
+                BrowserState::Disconnected
+            ]
+        );
+    }
+
+    #[test]
+    fn it_forwards_the_connection_progress() {
+        let state = |browser| QueueState { browser, queued: 0, in_flight: None, reads: 1 };
+        let (sender, updates) = mpsc::channel();
+        for browser in [
+            BrowserState::Disconnected,
+            BrowserState::Initializing,
+            BrowserState::Initializing,
+            BrowserState::Ready,
+            BrowserState::Disconnected,
+        ] {
+            sender.send(state(browser)).unwrap();
+        }
+        let (progress, received) = mpsc::channel();
+        forward_progress(updates, progress);
+        assert_eq!(
+            received.try_iter().collect::<Vec<_>>(),
+            [ConnectProgress::ClientConnected, ConnectProgress::Initialized]
+        );
+
+        // a resumed session is ready straight away
+        let (sender, updates) = mpsc::channel();
+        sender.send(state(BrowserState::Ready)).unwrap();
+        let (progress, received) = mpsc::channel();
+        forward_progress(updates, progress);
+        assert_eq!(
+            received.try_iter().collect::<Vec<_>>(),
+            [ConnectProgress::ClientConnected, ConnectProgress::Initialized]
+        );
+    }
+
+    #[test]
+    fn it_detects_ci() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter().find(|(key, _)| *key == name).map(|(_, value)| value.to_string())
+            }
+        };
+        assert!(!is_ci(env(&[])));
+        assert!(!is_ci(env(&[("CI", "false"), ("HOME", "/root")])));
+        assert!(is_ci(env(&[("CI", "true")])));
+        assert!(is_ci(env(&[("JENKINS_URL", "http://jenkins")])));
+    }
+
+    #[test]
+    fn it_detects_user_rejections() {
+        let response = |code: i64| {
+            let res: ethers_signers_browser_frontend::ws::messages::ResponseContent =
+                serde_json::from_value(serde_json::json!({
+                    "type": "Error",
+                    "message": { "error": "User rejected the request.", "code": code },
+                }))
+                .unwrap();
+            match res {
+                ethers_signers_browser_frontend::ws::messages::ResponseContent::Error {
+                    error,
+                    code: Some(code),
+                    data,
+                } => BrowserSignerError::from(http::ServerError::Wallet {
+                    message: error,
+                    code,
+                    data,
+                }),
+                _ => panic!("unexpected response: {:?}", res),
+            }
+        };
+        assert!(response(4001).is_user_rejected());
+        assert!(!response(-32603).is_user_rejected());
+        assert!(BrowserSignerError::ConnectionRejected("no".to_owned()).is_user_rejected());
+        assert!(!BrowserSignerError::NoAddressFound.is_user_rejected());
+    }
+
+    #[test]
+    fn it_reports_rejected_connections() {
+        let wallet_error = |code| http::ServerError::Wallet {
+            message: "User rejected the request.".to_owned(),
+            code,
+            data: None,
+        };
+        assert!(matches!(
+            connection_error(wallet_error(4001)),
+            BrowserSignerError::ConnectionRejected(message) if message == "User rejected the request."
+        ));
+        assert!(matches!(
+            connection_error(wallet_error(-32002)),
+            BrowserSignerError::RPC { code: -32002, .. }
+        ));
+        assert!(matches!(
+            connection_error(http::ServerError::NoWallet("missing".to_owned())),
+            BrowserSignerError::NoWallet(_)
+        ));
+    }
+
+    struct BrokenStruct;
+
+    impl Eip712 for BrokenStruct {
+        type Error = std::io::Error;
+
+        fn domain(&self) -> Result<EIP712Domain, Self::Error> {
+            Ok(EIP712Domain { name: Some("Broken".to_owned()), ..Default::default() })
+        }
+
+        fn type_hash() -> Result<[u8; 32], Self::Error> {
+            Ok([0; 32])
+        }
+
+        fn struct_hash(&self) -> Result<[u8; 32], Self::Error> {
+            Err(std::io::Error::new(std::io::ErrorKind::Other, "field `amount` overflows"))
+        }
+    }
+
+    #[test]
+    fn it_encodes_eip1271_calls() {
+        let hash = H256::repeat_byte(0xaa);
+        let data = is_valid_signature_call(hash, vec![0xbb; 65]);
+        assert_eq!(data.len(), 4 + 32 * 3 + 96);
+        assert_eq!(data[..4], EIP1271_MAGIC_VALUE);
+        assert_eq!(data[4..36], *hash.as_bytes());
+        assert_eq!(U256::from_big_endian(&data[36..68]), U256::from(64));
+        assert_eq!(U256::from_big_endian(&data[68..100]), U256::from(65));
+        assert!(data[100..165].iter().all(|b| *b == 0xbb));
+
+        let mut result = [0u8; 32];
+        assert!(!is_magic_value(&result));
+        result[..4].copy_from_slice(&EIP1271_MAGIC_VALUE);
+        assert!(is_magic_value(&result));
+        assert!(!is_magic_value(&result[..4]));
+        assert!(!is_magic_value(&[]));
+    }
+
+    #[test]
+    fn it_interprets_eip1271_results() {
+        let magic = format!("0x{}{}", hex::encode(EIP1271_MAGIC_VALUE), "00".repeat(28));
+        assert!(eip1271_result(Ok(magic)).unwrap());
+        assert!(!eip1271_result(Ok(format!("0x{}", "00".repeat(32)))).unwrap());
+        assert!(!eip1271_result(Ok("0x".to_owned())).unwrap());
+
+        let wallet_error = |code, data| http::ServerError::Wallet {
+            message: "call failed".to_owned(),
+            code,
+            data: Some(data),
+        };
+        let reverted = wallet_error(EXECUTION_REVERTED_CODE, serde_json::json!("0x"));
+        assert!(!eip1271_result(Err(reverted)).unwrap());
+        assert!(matches!(
+            eip1271_result(Ok("0xzz".to_owned())),
+            Err(BrowserSignerError::InvalidCallResult(_))
+        ));
+        let mismatch =
+            wallet_error(CHAIN_MISMATCH_CODE, serde_json::json!({ "expected": 1, "actual": 5 }));
+        assert!(matches!(
+            eip1271_result(Err(mismatch)),
+            Err(BrowserSignerError::ChainMismatch { expected: 1, actual: 5 })
+        ));
+    }
+
+    #[test]
+    fn it_interprets_transaction_hashes() {
+        let hash = format!("0x{}", "ab".repeat(32));
+        assert_eq!(
+            transaction_hash_result(Ok(hash.clone())).unwrap(),
+            TxHash::from_str(&hash).unwrap()
+        );
+        assert!(matches!(
+            transaction_hash_result(Ok("0x12".to_owned())),
+            Err(BrowserSignerError::InvalidTransactionHash(_))
+        ));
+
+        let err = http::ServerError::Wallet {
+            message: "insufficient funds".to_owned(),
+            code: -32000,
+            data: None,
+        };
+        assert!(matches!(
+            transaction_hash_result(Err(err)),
+            Err(BrowserSignerError::RPC { code: -32000, .. })
+        ));
+        let err = http::ServerError::Client("the browser disconnected".to_owned());
+        assert!(matches!(
+            transaction_hash_result(Err(err)),
+            Err(BrowserSignerError::ServerError(http::ServerError::Client(_)))
+        ));
+    }
+
+    #[tokio::test]
+    async fn it_verifies_typed_data_signatures() {
+        let wallet = "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318"
+            .parse::<ethers::signers::LocalWallet>()
+            .unwrap();
+        let data: TypedData = serde_json::from_value(serde_json::json!({
+            "types": {
+                "EIP712Domain": [{ "name": "name", "type": "string" }],
+                "Mail": [{ "name": "contents", "type": "string" }],
+            },
+            "primaryType": "Mail",
+            "domain": { "name": "Test" },
+            "message": { "contents": "hello" },
+        }))
+        .unwrap();
+        let signature = wallet.sign_typed_data(&data).await.unwrap().to_string();
+
+        assert!(verify_typed_data_signature(&data, &signature, wallet.address()).unwrap());
+        assert!(!verify_typed_data_signature(&data, &signature, Address::zero()).unwrap());
+        let mut other = data.clone();
+        other.message.insert("contents".to_owned(), "bye".into());
+        assert!(!verify_typed_data_signature(&other, &signature, wallet.address()).unwrap());
+        assert!(verify_typed_data_signature(&data, "0x1234", wallet.address()).is_err());
+    }
+
+    #[test]
+    fn it_reports_eip712_encoding_errors() {
+        let err = encode_eip712(&BrokenStruct).unwrap_err();
+        assert!(matches!(
+            err,
+            BrowserSignerError::Eip712Encoding { type_name, part: "struct hash", .. }
+                if type_name.ends_with("BrokenStruct")
+        ));
+        assert!(err.to_string().contains("field `amount` overflows"));
+    }
+
     #[tokio::test]
     #[serial]
     #[cfg_attr(not(feature = "browser"), ignore)]
@@ -347,4 +1539,30 @@ mod tests {
         let sig = signer.sign_message(&message).await.unwrap();
         sig.verify(message, signer.address()).expect("valid sig");
     }
+
+    #[tokio::test]
+    #[serial]
+    #[cfg_attr(not(feature = "browser"), ignore)]
+    async fn it_switches_to_a_provided_chain_for_a_transaction() {
+        let signer = test_signer_with_options(5, Some(provided_chains())).await; // goerli
+
+        println!("address: {:#x}", signer.address());
+
+        let transaction = TypedTransaction::Eip1559(Eip1559TransactionRequest {
+            from: Some(signer.address()),
+            to: Some(ethers::types::NameOrAddress::Address(signer.address())),
+            nonce: None,
+            gas: None,
+            max_priority_fee_per_gas: None,
+            max_fee_per_gas: None,
+            value: None,
+            data: None,
+            chain_id: Some(114.into()),
+            access_list: AccessList(vec![]),
+        });
+
+        // the wallet should be switched to coston2 without asking for its details
+        let sig = signer.sign_transaction(&transaction).await.unwrap();
+        println!("sig: {:?}", sig);
+    }
 }
@@ -0,0 +1,77 @@
+//! A `tracing` layer forwarding the events of this crate to a channel, e.g. to show live logs in
+//! an application embedding `BrowserSigner` without installing a global subscriber.
+//!
+//! ```ignore
+//! use ethers_signers_browser::{log_layer::ChannelLayer, BrowserSigner};
+//! use tracing_subscriber::prelude::*;
+//!
+//! let (layer, logs) = ChannelLayer::new();
+//! let _guard = tracing_subscriber::registry().with(layer).set_default();
+//! // the server threads started from here inherit the subscriber
+//! let signer = BrowserSigner::new(1).await?;
+//! for log in logs.try_iter() {
+//!     println!("[{}] {}", log.level, log.message);
+//! }
+//! ```
+
+use std::{
+    fmt::{self, Write},
+    sync::{mpsc, Mutex},
+};
+use tracing::{
+    field::{Field, Visit},
+    Event, Level, Subscriber,
+};
+use tracing_subscriber::{layer::Context, Layer};
+
+/// An event recorded by `ChannelLayer`
+#[derive(Debug, Clone)]
+pub struct LogEvent {
+    pub level: Level,
+    /// The module which emitted the event, e.g. `ethers_signers_browser::http::comm`
+    pub target: String,
+    /// The message followed by the other fields of the event (as `name=value`)
+    pub message: String,
+}
+
+/// Forwards the events emitted by this crate to a channel, other crates are ignored
+pub struct ChannelLayer {
+    sender: Mutex<mpsc::Sender<LogEvent>>,
+}
+
+impl ChannelLayer {
+    /// Creates the layer and the receiving end of its channel
+    pub fn new() -> (Self, mpsc::Receiver<LogEvent>) {
+        let (sender, receiver) = mpsc::channel();
+        (Self { sender: Mutex::new(sender) }, receiver)
+    }
+}
+
+struct MessageVisitor(String);
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.0, "{:?}", value);
+        } else {
+            let _ = write!(self.0, " {}={:?}", field.name(), value);
+        }
+    }
+}
+
+impl<S: Subscriber> Layer<S> for ChannelLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        if !metadata.target().starts_with(env!("CARGO_CRATE_NAME")) {
+            return
+        }
+        let mut visitor = MessageVisitor(String::new());
+        event.record(&mut visitor);
+        // the receiver being gone only means nobody is listening anymore
+        let _ = self.sender.lock().expect("poisoned mutex").send(LogEvent {
+            level: *metadata.level(),
+            target: metadata.target().to_owned(),
+            message: visitor.0,
+        });
+    }
+}
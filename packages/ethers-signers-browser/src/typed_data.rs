@@ -0,0 +1,287 @@
+use ethers::{
+    core::types::{
+        transaction::eip712::{TypedData, Types},
+        Address, U256,
+    },
+    utils::{hex, to_checksum},
+};
+use serde_json::Value;
+use std::str::FromStr;
+use tracing::warn;
+
+/// Checks that every type referenced from `primaryType` exists and that the message conforms to
+/// it, so malformed data fails with a descriptive error instead of an opaque wallet one
+pub(crate) fn validate(data: &TypedData) -> Result<(), String> {
+    if !data.types.contains_key(&data.primary_type) {
+        return Err(format!("primary type `{}` is not defined", data.primary_type))
+    }
+    let message = Value::Object(data.message.clone().into_iter().collect());
+    validate_value(&data.types, &data.primary_type, &message, &data.primary_type)
+}
+
+/// Parses raw EIP-712 JSON (as given to `eth_signTypedData_v4`) and validates it, a
+/// `verifyingContract` with an invalid checksum is rejected if `strict` and logged otherwise
+pub(crate) fn parse(json: &str, strict: bool) -> Result<TypedData, String> {
+    let value =
+        serde_json::from_str::<Value>(json).map_err(|e| format!("malformed JSON: {}", e))?;
+    from_value(value, strict)
+}
+
+/// Same as `parse` with the JSON already deserialized
+pub(crate) fn from_value(value: Value, strict: bool) -> Result<TypedData, String> {
+    let data = serde_json::from_value::<TypedData>(value.clone())
+        .map_err(|e| format!("malformed JSON: {}", e))?;
+    if let Err(e) = check_verifying_contract(&value) {
+        if strict {
+            return Err(e)
+        }
+        warn!("{}", e);
+    }
+    validate(&data)?;
+    Ok(data)
+}
+
+/// Same as `parse` with a JSON-RPC parameter, wallets expect a JSON string but an object is
+/// accepted too
+pub(crate) fn from_param(param: &Value, strict: bool) -> Result<TypedData, String> {
+    match param {
+        Value::String(json) => parse(json, strict),
+        value => from_value(value.clone(), strict),
+    }
+}
+
+/// Catches typos in the contract the signature is bound to (e.g. a permit), which would otherwise
+/// produce a valid signature for the wrong contract. All lowercase or uppercase addresses carry no
+/// checksum so only mixed case ones are checked
+fn check_verifying_contract(data: &Value) -> Result<(), String> {
+    let contract = match data.pointer("/domain/verifyingContract").and_then(Value::as_str) {
+        Some(contract) => contract,
+        None => return Ok(()),
+    };
+    let address = Address::from_str(contract)
+        .map_err(|e| format!("invalid verifyingContract `{}`: {}", contract, e))?;
+    let digits = contract.strip_prefix("0x").unwrap_or(contract);
+    let mixed_case = digits.chars().any(|c| c.is_ascii_lowercase()) &&
+        digits.chars().any(|c| c.is_ascii_uppercase());
+    let checksummed = to_checksum(&address, None);
+    if mixed_case && digits != &checksummed[2..] {
+        return Err(format!(
+            "verifyingContract `{}` doesn't match its checksum `{}`",
+            contract, checksummed
+        ))
+    }
+    Ok(())
+}
+
+fn validate_value(types: &Types, typ: &str, value: &Value, path: &str) -> Result<(), String> {
+    if let Some(inner) = typ.strip_suffix(']') {
+        let (item_type, len) = inner
+            .rsplit_once('[')
+            .ok_or_else(|| format!("invalid array type `{}` at `{}`", typ, path))?;
+        let items = value
+            .as_array()
+            .ok_or_else(|| format!("expected an array for `{}` at `{}`", typ, path))?;
+        if !len.is_empty() {
+            let len = len
+                .parse::<usize>()
+                .map_err(|_| format!("invalid array length in `{}` at `{}`", typ, path))?;
+            if items.len() != len {
+                return Err(format!(
+                    "expected {} items for `{}` at `{}`, got {}",
+                    len,
+                    typ,
+                    path,
+                    items.len()
+                ))
+            }
+        }
+        for (i, item) in items.iter().enumerate() {
+            validate_value(types, item_type, item, &format!("{}[{}]", path, i))?;
+        }
+        return Ok(())
+    }
+
+    if let Some(fields) = types.get(typ) {
+        let object = value
+            .as_object()
+            .ok_or_else(|| format!("expected an object of type `{}` at `{}`", typ, path))?;
+        for field in fields {
+            let path = format!("{}.{}", path, field.name);
+            let value = object
+                .get(&field.name)
+                .ok_or_else(|| format!("missing field `{}` of type `{}`", path, field.r#type))?;
+            validate_value(types, &field.r#type, value, &path)?;
+        }
+        return Ok(())
+    }
+
+    let valid = match typ {
+        "address" => value.as_str().map_or(false, |v| Address::from_str(v).is_ok()),
+        "bool" => value.is_boolean(),
+        "string" => value.is_string(),
+        "bytes" => value.as_str().map_or(false, |v| hex::decode(v).is_ok()),
+        _ => {
+            if let Some(size) = typ.strip_prefix("bytes") {
+                let size = size.parse::<usize>().map_err(|_| unknown_type(typ, path))?;
+                if size == 0 || size > 32 {
+                    return Err(unknown_type(typ, path))
+                }
+                value.as_str().map_or(false, |v| hex::decode(v).map_or(false, |v| v.len() == size))
+            } else if let Some(size) = typ.strip_prefix("uint").or_else(|| typ.strip_prefix("int"))
+            {
+                if !size.is_empty() && size.parse::<usize>().map_or(true, |s| s == 0 || s > 256) {
+                    return Err(unknown_type(typ, path))
+                }
+                is_integer(value, typ.starts_with("int"))
+            } else {
+                return Err(unknown_type(typ, path))
+            }
+        }
+    };
+    if !valid {
+        return Err(format!("invalid value for `{}` at `{}`: {}", typ, path, value))
+    }
+    Ok(())
+}
+
+fn unknown_type(typ: &str, path: &str) -> String {
+    format!("type `{}` referenced at `{}` is not defined", typ, path)
+}
+
+fn is_integer(value: &Value, signed: bool) -> bool {
+    match value {
+        Value::Number(n) => n.is_u64() || (signed && n.is_i64()),
+        Value::String(s) => {
+            let s = if signed { s.strip_prefix('-').unwrap_or(s) } else { s };
+            match s.strip_prefix("0x") {
+                Some(hex) => U256::from_str_radix(hex, 16).is_ok(),
+                None => U256::from_dec_str(s).is_ok(),
+            }
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn typed_data(message: Value) -> TypedData {
+        serde_json::from_value(serde_json::json!({
+            "types": {
+                "EIP712Domain": [{ "name": "name", "type": "string" }],
+                "Person": [
+                    { "name": "name", "type": "string" },
+                    { "name": "wallet", "type": "address" },
+                ],
+                "Mail": [
+                    { "name": "from", "type": "Person" },
+                    { "name": "to", "type": "Person[]" },
+                    { "name": "contents", "type": "string" },
+                    { "name": "attachment", "type": "Attachment" },
+                ],
+            },
+            "primaryType": "Mail",
+            "domain": { "name": "Ether Mail" },
+            "message": message,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn it_reports_missing_types() {
+        let data = typed_data(serde_json::json!({
+            "from": { "name": "Cow", "wallet": "0xCD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826" },
+            "to": [],
+            "contents": "Hello, Bob!",
+            "attachment": {},
+        }));
+        assert_eq!(
+            validate(&data).unwrap_err(),
+            "type `Attachment` referenced at `Mail.attachment` is not defined"
+        );
+    }
+
+    #[test]
+    fn it_reports_type_mismatches() {
+        let mut data = typed_data(serde_json::json!({
+            "from": { "name": "Cow", "wallet": "0xCD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826" },
+            "to": [{ "name": "Bob", "wallet": 42 }],
+            "contents": "Hello, Bob!",
+            "attachment": "none",
+        }));
+        data.types.get_mut("Mail").unwrap()[3].r#type = "string".to_owned();
+        assert_eq!(
+            validate(&data).unwrap_err(),
+            "invalid value for `address` at `Mail.to[0].wallet`: 42"
+        );
+    }
+
+    #[test]
+    fn it_accepts_valid_data() {
+        let mut data = typed_data(serde_json::json!({
+            "from": { "name": "Cow", "wallet": "0xCD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826" },
+            "to": [{ "name": "Bob", "wallet": "0xbBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB" }],
+            "contents": "Hello, Bob!",
+            "attachment": "none",
+        }));
+        data.types.get_mut("Mail").unwrap()[3].r#type = "string".to_owned();
+        assert_eq!(validate(&data), Ok(()));
+    }
+
+    #[test]
+    fn it_parses_raw_json() {
+        let json = r#"{
+            "types": {
+                "EIP712Domain": [{ "name": "name", "type": "string" }],
+                "Mail": [{ "name": "contents", "type": "string" }]
+            },
+            "primaryType": "Mail",
+            "domain": { "name": "Ether Mail" },
+            "message": { "contents": "Hello, Bob!" }
+        }"#;
+        assert_eq!(parse(json, true).unwrap().primary_type, "Mail");
+
+        assert!(parse("{ \"types\": ", false).unwrap_err().starts_with("malformed JSON: "));
+        assert!(parse(r#"{ "primaryType": "Mail" }"#, false)
+            .unwrap_err()
+            .starts_with("malformed JSON: "));
+        assert_eq!(
+            parse(&json.replace(r#""contents": "Hello, Bob!""#, r#""contents": 42"#), false)
+                .unwrap_err(),
+            "invalid value for `string` at `Mail.contents`: 42"
+        );
+    }
+
+    #[test]
+    fn it_enforces_the_verifying_contract_checksum() {
+        let json = |contract: &str| {
+            serde_json::json!({
+                "types": {
+                    "EIP712Domain": [{ "name": "verifyingContract", "type": "address" }],
+                    "Mail": [{ "name": "contents", "type": "string" }],
+                },
+                "primaryType": "Mail",
+                "domain": { "verifyingContract": contract },
+                "message": { "contents": "Hello, Bob!" },
+            })
+            .to_string()
+        };
+        let valid = "0xCcCCccccCCCCcCCCCCCcCcCccCcCCCcCcccccccC";
+        assert!(parse(&json(valid), true).is_ok());
+
+        let typo = "0xCcCCccccCCCCcCCCCCCcCcCccCcCCCcCcccccccc";
+        assert_eq!(
+            parse(&json(typo), true).unwrap_err(),
+            format!("verifyingContract `{}` doesn't match its checksum `{}`", typo, valid)
+        );
+        assert!(parse(&json(typo), false).is_ok());
+        assert!(parse(&json(&valid.to_lowercase()), true).is_ok());
+        assert!(parse(&json(&valid.to_uppercase().replace("0X", "0x")), true).is_ok());
+
+        let value = serde_json::from_str::<Value>(&json(typo)).unwrap();
+        assert!(from_param(&value, true).is_err());
+        assert!(from_param(&Value::String(json(typo)), true).is_err());
+        assert!(from_param(&Value::String(json(valid)), true).is_ok());
+    }
+}